@@ -0,0 +1,265 @@
+use crate::options::AnchorStyle;
+use crate::transform::new_node;
+use comrak::Arena;
+use comrak::nodes::{AstNode, NodeValue};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// An anchor of [`AnchorStyle::Html`], or its opening tag when parsed,
+/// which gives the closing tag a node of its own.
+static HTML_ANCHOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^<a id="([A-Za-z0-9_-]+)">(</a>)?$"#).unwrap());
+
+/// An anchor of [`AnchorStyle::Attribute`] at the end of a paragraph.
+static ATTRIBUTE_ANCHOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s*\{#([A-Za-z0-9_-]+)\}$").unwrap());
+
+/// A number standing on its own in the text of a link, such as the `3` of
+/// `step 3`.
+static NUMBER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b\d+\b").unwrap());
+
+/// The IDs of item anchors that changed, old to new, so that links to them
+/// can follow.
+pub(crate) type MovedAnchors = HashMap<String, String>;
+
+/// Returns the ID of the anchor [`TransformOptions::item_anchors`] gives the
+/// item numbered `number` in the region `name`.
+///
+/// [`TransformOptions::item_anchors`]: crate::TransformOptions::item_anchors
+fn anchor_id(name: &str, number: usize) -> String {
+    format!("{}-step-{}", name, number)
+}
+
+/// Marks each item of `list`, numbered from `start`, with an anchor named
+/// after the region `name`, replacing the anchors an earlier run gave the
+/// items and recording those that changed in `moved`.
+pub(crate) fn add_anchors<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    list: &'a AstNode<'a>,
+    start: usize,
+    name: &str,
+    style: AnchorStyle,
+    moved: &mut MovedAnchors,
+) {
+    for (index, item) in list.children().enumerate() {
+        let Some(paragraph) = first_paragraph(item) else {
+            continue;
+        };
+        let id = anchor_id(name, start + index);
+        if let Some(old) = remove_anchor(paragraph, name)
+            && old != id
+        {
+            moved.insert(old, id.clone());
+        }
+        match style {
+            AnchorStyle::Html => paragraph.prepend(new_node(
+                arena,
+                NodeValue::HtmlInline(format!("<a id=\"{}\"></a>", id)),
+            )),
+            AnchorStyle::Attribute => {
+                paragraph.append(new_node(arena, NodeValue::Text(format!(" {{#{}}}", id))))
+            }
+        }
+    }
+}
+
+/// Rewrites the anchors of the items of `list`, now numbered from `start`,
+/// to match their numbers, keeping their style and recording those that
+/// changed in `moved`.
+pub(crate) fn renumber_anchors<'a>(
+    list: &'a AstNode<'a>,
+    start: usize,
+    name: &str,
+    moved: &mut MovedAnchors,
+) {
+    for (index, item) in list.children().enumerate() {
+        let Some(paragraph) = first_paragraph(item) else {
+            continue;
+        };
+        let Some((old, node)) = find_anchor(paragraph, name) else {
+            continue;
+        };
+        let id = anchor_id(name, start + index);
+        if old == id {
+            continue;
+        }
+        match &mut node.data.borrow_mut().value {
+            NodeValue::HtmlInline(html) => *html = html.replace(&old, &id),
+            NodeValue::Text(text) => {
+                *text = text.replace(&format!("{{#{}}}", old), &format!("{{#{}}}", id))
+            }
+            _ => {}
+        }
+        moved.insert(old, id);
+    }
+}
+
+/// Points links to moved anchors, such as `[step 3](#setup-step-3)`, at
+/// their new IDs, updating the step number in their text to match.
+pub(crate) fn sync_step_links<'a>(root: &'a AstNode<'a>, moved: &MovedAnchors) {
+    sync_links(root, moved, false);
+}
+
+/// Like [`sync_step_links`], for links to anchors that moved in other
+/// documents, such as `[step 3](part-1.md#setup-step-3)`.
+pub(crate) fn sync_links_between_files<'a>(root: &'a AstNode<'a>, moved: &MovedAnchors) {
+    sync_links(root, moved, true);
+}
+
+fn sync_links<'a>(root: &'a AstNode<'a>, moved: &MovedAnchors, other_files: bool) {
+    if moved.is_empty() {
+        return;
+    }
+    for node in root.descendants() {
+        let (old, new) = {
+            let mut data = node.data.borrow_mut();
+            let NodeValue::Link(link) = &mut data.value else {
+                continue;
+            };
+            let Some((path, fragment)) = link.url.split_once('#') else {
+                continue;
+            };
+            if path.is_empty() == other_files {
+                continue;
+            }
+            let Some((old, new)) = moved.get_key_value(fragment) else {
+                continue;
+            };
+            link.url = format!("{}#{}", path, new);
+            (old.clone(), new.clone())
+        };
+        let (Some(old_number), Some(new_number)) = (step_number(&old), step_number(&new)) else {
+            continue;
+        };
+        for text_node in node.descendants() {
+            if let NodeValue::Text(text) = &mut text_node.data.borrow_mut().value
+                && let Some(found) = NUMBER
+                    .find_iter(text)
+                    .find(|found| found.as_str() == old_number)
+            {
+                text.replace_range(found.range(), new_number);
+                break;
+            }
+        }
+    }
+}
+
+fn step_number(id: &str) -> Option<&str> {
+    id.rsplit_once("-step-").map(|(_, number)| number)
+}
+
+fn first_paragraph<'a>(item: &'a AstNode<'a>) -> Option<&'a AstNode<'a>> {
+    item.first_child()
+        .filter(|child| matches!(child.data.borrow().value, NodeValue::Paragraph))
+}
+
+/// Finds the anchor of a step of the region `name` in `paragraph`, returning
+/// its ID and the node holding it.
+fn find_anchor<'a>(paragraph: &'a AstNode<'a>, name: &str) -> Option<(String, &'a AstNode<'a>)> {
+    let is_step = |id: &str| {
+        id.strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix("-step-"))
+            .is_some_and(|number| number.parse::<usize>().is_ok())
+    };
+    if let Some(first) = paragraph.first_child()
+        && let NodeValue::HtmlInline(html) = &first.data.borrow().value
+        && let Some(captures) = HTML_ANCHOR.captures(html)
+        && is_step(&captures[1])
+    {
+        return Some((captures[1].to_string(), first));
+    }
+    if let Some(last) = paragraph.last_child()
+        && let NodeValue::Text(text) = &last.data.borrow().value
+        && let Some(captures) = ATTRIBUTE_ANCHOR.captures(text)
+        && is_step(&captures[1])
+    {
+        return Some((captures[1].to_string(), last));
+    }
+    None
+}
+
+/// Removes the anchor of a step of the region `name` from `paragraph`,
+/// returning its ID.
+fn remove_anchor<'a>(paragraph: &'a AstNode<'a>, name: &str) -> Option<String> {
+    let (id, node) = find_anchor(paragraph, name)?;
+    let mut data = node.data.borrow_mut();
+    match &mut data.value {
+        NodeValue::HtmlInline(html) => {
+            let closed = html.ends_with("</a>");
+            drop(data);
+            if !closed
+                && let Some(next) = node.next_sibling()
+                && matches!(&next.data.borrow().value, NodeValue::HtmlInline(html) if html == "</a>")
+            {
+                next.detach();
+            }
+            node.detach();
+            Some(id)
+        }
+        NodeValue::Text(text) => {
+            let start = ATTRIBUTE_ANCHOR
+                .find(text)
+                .map_or(text.len(), |found| found.start());
+            text.truncate(start);
+            Some(id)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::TransformOptions;
+    use crate::renumber::{RenumberScope, renumber};
+    use crate::transform::transform_with_options;
+
+    #[test]
+    fn test_renumber_moves_anchors_and_links() {
+        let input = r#"See [step 2](#setup-step-2) and [the last step](#setup-step-3).
+
+<!-- ol name=setup -->
+1. <a id="setup-step-1"></a>Install
+2. <a id="setup-new-step"></a>Back up
+2. <a id="setup-step-2"></a>Configure
+3. Run {#setup-step-3}
+<!-- /ol -->
+"#;
+        let expected = r#"See [step 3](#setup-step-3) and [the last step](#setup-step-4).
+
+<!-- ol name=setup -->
+1. <a id="setup-step-1"></a>Install
+2. <a id="setup-new-step"></a>Back up
+3. <a id="setup-step-3"></a>Configure
+4. Run {#setup-step-4}
+<!-- /ol -->
+"#;
+        assert_eq!(renumber(input, RenumberScope::Regions), expected);
+    }
+
+    #[test]
+    fn test_transform_replaces_anchors_and_follows_links() {
+        let input = r#"<!-- ol name=setup start=2 -->
+- <a id="setup-step-1"></a>Install
+- Configure
+<!-- /ol -->
+
+Back to [step 1](#setup-step-1).
+"#;
+        let options = TransformOptions {
+            item_anchors: Some(AnchorStyle::Attribute),
+            ..TransformOptions::default()
+        };
+        assert_eq!(
+            transform_with_options(input, &options),
+            r#"<!-- ol name=setup start=2 -->
+2. Install {#setup-step-2}
+3. Configure {#setup-step-3}
+<!-- /ol -->
+
+Back to [step 2](#setup-step-2).
+"#
+        );
+    }
+}
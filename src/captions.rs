@@ -0,0 +1,249 @@
+use crate::report::TransformReport;
+use crate::transform::new_node;
+use comrak::Arena;
+use comrak::nodes::{AstNode, NodeValue};
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// The start of a caption, numbered or not, e.g. `Figure:` or `Table 2:`.
+static CAPTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(Figure|Table)(?: \d+)?:").unwrap());
+
+/// The label of a caption, or the annotation of a reference to it.
+static LABEL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^<!--\s*((?:fig|table):[A-Za-z0-9_-]+)\s*-->$").unwrap());
+
+/// A reference to a caption, such as `(fig:pipeline)`.
+static REFERENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\(((?:fig|table):[A-Za-z0-9_-]+)\)").unwrap());
+
+/// A reference resolved by an earlier run, at the end of the text before its
+/// annotation.
+static RESOLVED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\((?:Figure|Table) \d+\)$").unwrap());
+
+/// Numbers the paragraphs starting with `Figure:` or `Table:` in order, each
+/// kind counting on its own, e.g. `Figure 3: The pipeline`, and replaces
+/// `(fig:label)` and `(table:label)` references with the caption they point
+/// at, e.g. `(Figure 3)`. A caption is labelled by a comment at its end, such
+/// as `<!-- fig:pipeline -->`.
+///
+/// With `annotate`, each resolved reference keeps its label in a comment,
+/// `(Figure 3)<!-- fig:pipeline -->`, and is updated by later runs when the
+/// captions are renumbered.
+pub(crate) fn number_captions<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    annotate: bool,
+    report: &mut TransformReport,
+) {
+    let mut counters: HashMap<String, usize> = HashMap::new();
+    let mut labels = HashMap::new();
+    for paragraph in root.descendants() {
+        if !matches!(paragraph.data.borrow().value, NodeValue::Paragraph) {
+            continue;
+        }
+        let Some(first) = paragraph.first_child() else {
+            continue;
+        };
+        let caption = {
+            let mut data = first.data.borrow_mut();
+            let NodeValue::Text(text) = &mut data.value else {
+                continue;
+            };
+            let Some(kind) = CAPTION
+                .captures(text)
+                .map(|captures| captures[1].to_string())
+            else {
+                continue;
+            };
+            let number = counters.entry(kind.clone()).or_default();
+            *number += 1;
+            let caption = format!("{} {}", kind, number);
+            *text = CAPTION.replace(text, format!("{}:", caption)).into_owned();
+            caption
+        };
+        let Some(label) = paragraph
+            .last_child()
+            .filter(|last| !is_annotation(last))
+            .and_then(|last| label(last))
+        else {
+            continue;
+        };
+        if labels.insert(label.clone(), caption).is_some() {
+            let position = paragraph.data.borrow().sourcepos.start;
+            report.warn(
+                "duplicate-caption-label",
+                position.line,
+                position.column,
+                format!("duplicate caption label `{}`", label),
+            );
+        }
+    }
+
+    let nodes: Vec<_> = root.descendants().collect();
+    for node in nodes {
+        if is_annotation(node) {
+            update_annotated(node, &labels);
+        } else {
+            resolve_references(arena, node, &labels, annotate, report);
+        }
+    }
+}
+
+fn label<'a>(node: &'a AstNode<'a>) -> Option<String> {
+    match &node.data.borrow().value {
+        NodeValue::HtmlInline(html) => LABEL.captures(html).map(|captures| captures[1].to_string()),
+        _ => None,
+    }
+}
+
+/// Returns whether `node` is the annotation of a reference resolved by an
+/// earlier run, rather than the label of a caption.
+fn is_annotation<'a>(node: &'a AstNode<'a>) -> bool {
+    label(node).is_some()
+        && node.previous_sibling().is_some_and(|previous| {
+            matches!(&previous.data.borrow().value, NodeValue::Text(text) if RESOLVED.is_match(text))
+        })
+}
+
+/// Updates the number of the reference annotated by `annotation`.
+fn update_annotated<'a>(annotation: &'a AstNode<'a>, labels: &HashMap<String, String>) {
+    let (Some(label), Some(previous)) = (label(annotation), annotation.previous_sibling()) else {
+        return;
+    };
+    let Some(caption) = labels.get(&label) else {
+        return;
+    };
+    if let NodeValue::Text(text) = &mut previous.data.borrow_mut().value {
+        *text = RESOLVED
+            .replace(text, format!("({})", caption))
+            .into_owned();
+    }
+}
+
+/// Replaces the references in a `Text` node, splitting it to annotate them
+/// when `annotate` is set. Unknown labels are reported and left as they are.
+fn resolve_references<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    node: &'a AstNode<'a>,
+    labels: &HashMap<String, String>,
+    annotate: bool,
+    report: &mut TransformReport,
+) {
+    let (text, position) = {
+        let data = node.data.borrow();
+        match &data.value {
+            NodeValue::Text(text) if REFERENCE.is_match(text) => {
+                (text.clone(), data.sourcepos.start)
+            }
+            _ => return,
+        }
+    };
+    for captures in REFERENCE.captures_iter(&text) {
+        if !labels.contains_key(&captures[1]) {
+            report.warn(
+                "unknown-caption-label",
+                position.line,
+                position.column + captures.get(0).unwrap().start(),
+                format!("no caption is labelled `{}`", &captures[1]),
+            );
+        }
+    }
+    let caption = |captures: &Captures| match labels.get(&captures[1]) {
+        Some(caption) => format!("({})", caption),
+        None => captures[0].to_string(),
+    };
+
+    if !annotate {
+        node.data.borrow_mut().value =
+            NodeValue::Text(REFERENCE.replace_all(&text, caption).into_owned());
+        return;
+    }
+    let mut anchor = node;
+    let mut last = 0;
+    let mut pieces = Vec::new();
+    for captures in REFERENCE.captures_iter(&text) {
+        if !labels.contains_key(&captures[1]) {
+            continue;
+        }
+        let whole = captures.get(0).unwrap();
+        pieces.push((
+            format!("{}{}", &text[last..whole.start()], caption(&captures)),
+            format!("<!-- {} -->", &captures[1]),
+        ));
+        last = whole.end();
+    }
+    if pieces.is_empty() {
+        return;
+    }
+    for (index, (resolved, comment)) in pieces.into_iter().enumerate() {
+        let text_node = if index == 0 {
+            node.data.borrow_mut().value = NodeValue::Text(resolved);
+            node
+        } else {
+            let text_node = new_node(arena, NodeValue::Text(resolved));
+            anchor.insert_after(text_node);
+            text_node
+        };
+        let comment_node = new_node(arena, NodeValue::HtmlInline(comment));
+        text_node.insert_after(comment_node);
+        anchor = comment_node;
+    }
+    if last < text.len() {
+        anchor.insert_after(new_node(arena, NodeValue::Text(text[last..].to_string())));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::TransformOptions;
+    use crate::transform::transform_with_report;
+
+    fn numbered(input: &str, annotate: bool) -> (String, Vec<String>) {
+        let options = TransformOptions {
+            number_captions: true,
+            annotate,
+            ..TransformOptions::default()
+        };
+        let (output, report) = transform_with_report(input, &options);
+        let messages = report
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        (output, messages)
+    }
+
+    #[test]
+    fn test_number_captions_and_resolve_references() {
+        let input = "As (fig:flow) shows, see (table:sizes) and (fig:nothing).\n\n![flow](flow.png)\n\nFigure: The flow <!-- fig:flow -->\n\nTable 7: Sizes <!-- table:sizes -->\n\nFigure 1: Another\n";
+        let (output, messages) = numbered(input, false);
+        assert_eq!(
+            output,
+            "As (Figure 1) shows, see (Table 1) and (fig:nothing).\n\n![flow](flow.png)\n\nFigure 1: The flow <!-- fig:flow -->\n\nTable 1: Sizes <!-- table:sizes -->\n\nFigure 2: Another\n"
+        );
+        assert_eq!(
+            messages,
+            vec!["1:44: warning: no caption is labelled `fig:nothing`"]
+        );
+    }
+
+    #[test]
+    fn test_annotated_references_follow_renumbered_captions() {
+        let input = "See (fig:b).\n\nFigure: A <!-- fig:a -->\n\nFigure: B <!-- fig:b -->\n";
+        let (output, _) = numbered(input, true);
+        assert_eq!(
+            output,
+            "See (Figure 2)<!-- fig:b -->.\n\nFigure 1: A <!-- fig:a -->\n\nFigure 2: B <!-- fig:b -->\n"
+        );
+
+        let moved = output.replace("Figure 1: A <!-- fig:a -->\n\n", "");
+        let (output, _) = numbered(&moved, true);
+        assert_eq!(
+            output,
+            "See (Figure 1)<!-- fig:b -->.\n\nFigure 1: B <!-- fig:b -->\n"
+        );
+    }
+}
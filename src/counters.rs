@@ -0,0 +1,268 @@
+use crate::marker::{COUNTER_NAME, Marker, MarkerSyntax, marker_text, parse_marker};
+use crate::reference::ANNOTATED_NUMBER;
+use crate::report::TransformReport;
+use crate::transform::new_node;
+use comrak::Arena;
+use comrak::nodes::{AstNode, NodeValue};
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// A counter expression, such as `(counter:example)` or
+/// `(counter:example+1)`.
+static EXPRESSION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"\((counter:({})([+-]\d+)?)\)", COUNTER_NAME)).unwrap());
+
+/// The comment [`TransformOptions::annotate`] puts after a resolved counter
+/// expression, capturing the expression.
+///
+/// [`TransformOptions::annotate`]: crate::TransformOptions::annotate
+pub(crate) static ANNOTATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"^<!-- (counter:({})([+-]\d+)?) -->$",
+        COUNTER_NAME
+    ))
+    .unwrap()
+});
+
+/// Keeps the named counters declared by `<!-- counter name -->` markers and
+/// incremented by `<!-- counter name ++ -->`, going through the document in
+/// order, and replaces each `(counter:name)` or `(counter:name±N)`
+/// expression with the counter's value at that point, plus `N`.
+///
+/// With `annotate`, each resolved expression keeps a comment holding it, e.g.
+/// `(2)<!-- counter:example -->`, and is updated by later runs when markers
+/// are added or moved. Expressions naming a counter not declared before them
+/// are reported and left as they are.
+pub(crate) fn number_counters<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    syntaxes: &[MarkerSyntax],
+    annotate: bool,
+    report: &mut TransformReport,
+) {
+    let mut counters: HashMap<String, i64> = HashMap::new();
+    let nodes: Vec<_> = root.descendants().collect();
+    for node in nodes {
+        if let Some((Marker::Counter { name, increment }, _)) =
+            marker_text(node).and_then(|text| parse_marker(&text, syntaxes))
+        {
+            if !increment {
+                counters.insert(name, 0);
+                continue;
+            }
+            if !counters.contains_key(&name) {
+                let position = node.data.borrow().sourcepos.start;
+                report.warn(
+                    "unknown-counter",
+                    position.line,
+                    position.column,
+                    format!(
+                        "counter `{}` is not declared before this; declare it with `<!-- counter {} -->`",
+                        name, name
+                    ),
+                );
+            }
+            let value = counters.entry(name).or_default();
+            *value = value.saturating_add(1);
+        } else if is_annotation(node) {
+            update_annotated(node, &counters);
+        } else {
+            resolve_expressions(arena, node, &counters, annotate, report);
+        }
+    }
+}
+
+/// Returns the value of the counter expression captured by `captures`, or
+/// `None` if its counter isn't declared or the value is too large.
+fn value(captures: &Captures, counters: &HashMap<String, i64>) -> Option<i64> {
+    let value = *counters.get(&captures[2])?;
+    match captures.get(3) {
+        Some(offset) => value.checked_add(offset.as_str().parse().ok()?),
+        None => Some(value),
+    }
+}
+
+/// Returns whether `node` is the annotation of a counter expression resolved
+/// by an earlier run.
+fn is_annotation<'a>(node: &'a AstNode<'a>) -> bool {
+    matches!(&node.data.borrow().value, NodeValue::HtmlInline(html) if ANNOTATION.is_match(html))
+        && node.previous_sibling().is_some_and(|previous| {
+            matches!(&previous.data.borrow().value, NodeValue::Text(text) if ANNOTATED_NUMBER.is_match(text))
+        })
+}
+
+/// Updates the number of the expression annotated by `annotation`.
+fn update_annotated<'a>(annotation: &'a AstNode<'a>, counters: &HashMap<String, i64>) {
+    let number = match &annotation.data.borrow().value {
+        NodeValue::HtmlInline(html) => ANNOTATION
+            .captures(html)
+            .and_then(|captures| value(&captures, counters)),
+        _ => None,
+    };
+    let (Some(number), Some(previous)) = (number, annotation.previous_sibling()) else {
+        return;
+    };
+    if let NodeValue::Text(text) = &mut previous.data.borrow_mut().value {
+        *text = ANNOTATED_NUMBER
+            .replace(text, format!("({})", number))
+            .into_owned();
+    }
+}
+
+/// Replaces the counter expressions in a `Text` node, splitting it to
+/// annotate them when `annotate` is set.
+fn resolve_expressions<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    node: &'a AstNode<'a>,
+    counters: &HashMap<String, i64>,
+    annotate: bool,
+    report: &mut TransformReport,
+) {
+    let (text, position) = {
+        let data = node.data.borrow();
+        match &data.value {
+            NodeValue::Text(text) if EXPRESSION.is_match(text) => {
+                (text.clone(), data.sourcepos.start)
+            }
+            _ => return,
+        }
+    };
+    for captures in EXPRESSION.captures_iter(&text) {
+        if !counters.contains_key(&captures[2]) {
+            report.warn(
+                "unknown-counter",
+                position.line,
+                position.column + captures.get(0).unwrap().start(),
+                format!(
+                    "no counter named `{}` is declared before this",
+                    &captures[2]
+                ),
+            );
+        }
+    }
+    let resolve = |captures: &Captures| match value(captures, counters) {
+        Some(number) => format!("({})", number),
+        None => captures[0].to_string(),
+    };
+
+    if !annotate {
+        node.data.borrow_mut().value =
+            NodeValue::Text(EXPRESSION.replace_all(&text, resolve).into_owned());
+        return;
+    }
+    let mut anchor = node;
+    let mut last = 0;
+    let mut pieces = Vec::new();
+    for captures in EXPRESSION.captures_iter(&text) {
+        if value(&captures, counters).is_none() {
+            continue;
+        }
+        let whole = captures.get(0).unwrap();
+        pieces.push((
+            format!("{}{}", &text[last..whole.start()], resolve(&captures)),
+            format!("<!-- {} -->", &captures[1]),
+        ));
+        last = whole.end();
+    }
+    if pieces.is_empty() {
+        return;
+    }
+    for (index, (resolved, comment)) in pieces.into_iter().enumerate() {
+        let text_node = if index == 0 {
+            node.data.borrow_mut().value = NodeValue::Text(resolved);
+            node
+        } else {
+            let text_node = new_node(arena, NodeValue::Text(resolved));
+            anchor.insert_after(text_node);
+            text_node
+        };
+        let comment_node = new_node(arena, NodeValue::HtmlInline(comment));
+        text_node.insert_after(comment_node);
+        anchor = comment_node;
+    }
+    if last < text.len() {
+        anchor.insert_after(new_node(arena, NodeValue::Text(text[last..].to_string())));
+    }
+}
+
+/// Turns every number annotated as a counter expression under `root`, such
+/// as `(2)<!-- counter:example -->`, back into its expression.
+pub(crate) fn restore_counters<'a>(root: &'a AstNode<'a>) {
+    let annotations: Vec<_> = root
+        .descendants()
+        .filter(|node| is_annotation(node))
+        .collect();
+    for annotation in annotations {
+        let expression = match &annotation.data.borrow().value {
+            NodeValue::HtmlInline(html) => ANNOTATION.captures(html).unwrap()[1].to_string(),
+            _ => continue,
+        };
+        if let Some(previous) = annotation.previous_sibling()
+            && let NodeValue::Text(text) = &mut previous.data.borrow_mut().value
+        {
+            *text = ANNOTATED_NUMBER
+                .replace(text, format!("({})", expression))
+                .into_owned();
+        }
+        annotation.detach();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::TransformOptions;
+    use crate::restore::restore;
+    use crate::transform::transform_with_report;
+
+    fn counted(input: &str, annotate: bool) -> (String, Vec<String>) {
+        let options = TransformOptions {
+            annotate,
+            ..TransformOptions::default()
+        };
+        let (output, report) = transform_with_report(input, &options);
+        let messages = report
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        (output, messages)
+    }
+
+    #[test]
+    fn test_named_counters() {
+        let input = "<!-- counter example -->\n\n<!-- counter example ++ -->\n\nExample (counter:example): a list.\n\n<!-- counter example ++ -->\n\nExample (counter:example), after (counter:example-1) and before (counter:example+1).\n\nSee (counter:exercise).\n\n<!-- counter exercise ++ -->\n";
+        let (output, messages) = counted(input, false);
+        assert_eq!(
+            output,
+            "<!-- counter example -->\n\n<!-- counter example ++ -->\n\nExample (1): a list.\n\n<!-- counter example ++ -->\n\nExample (2), after (1) and before (3).\n\nSee (counter:exercise).\n\n<!-- counter exercise ++ -->\n"
+        );
+        assert_eq!(
+            messages,
+            vec![
+                "11:5: warning: no counter named `exercise` is declared before this",
+                "13:1: warning: counter `exercise` is not declared before this; declare it with `<!-- counter exercise -->`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotated_counters_are_updated() {
+        let input = "<!-- counter ex -->\n\n<!-- counter ex ++ -->\n\nExercise (counter:ex)\n";
+        let (output, _) = counted(input, true);
+        assert_eq!(
+            output,
+            "<!-- counter ex -->\n\n<!-- counter ex ++ -->\n\nExercise (1)<!-- counter:ex -->\n"
+        );
+        let inserted = output.replace("\n\nExercise", "\n\n<!-- counter ex ++ -->\n\nExercise");
+        let (output, _) = counted(&inserted, true);
+        assert_eq!(
+            output,
+            "<!-- counter ex -->\n\n<!-- counter ex ++ -->\n\n<!-- counter ex ++ -->\n\nExercise (2)<!-- counter:ex -->\n"
+        );
+        assert_eq!(
+            restore(&output),
+            inserted.replace("(1)<!-- counter:ex -->", "(counter:ex)")
+        );
+    }
+}
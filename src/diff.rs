@@ -0,0 +1,279 @@
+/// The most edits looked for between two texts before giving up on matching
+/// the lines between their common start and end, which keeps the memory
+/// used by the search bounded.
+const MAX_EDITS: usize = 2000;
+
+/// Returns the indices of the lines `old` and `new` have in common, in
+/// order, as pairs of an index into `old` and one into `new`. The pairs are
+/// those left untouched by a shortest edit script turning `old` into `new`.
+///
+/// Texts needing more than [`MAX_EDITS`] edits between their common start
+/// and end are treated as having nothing in common there.
+pub(crate) fn common_lines(old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+
+    let mut common: Vec<_> = (0..prefix).map(|index| (index, index)).collect();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    if let Some(middle) = shortest_edit(old_middle, new_middle) {
+        common.extend(
+            middle
+                .into_iter()
+                .map(|(old, new)| (prefix + old, prefix + new)),
+        );
+    }
+    common
+        .extend((0..suffix).map(|index| (old.len() - suffix + index, new.len() - suffix + index)));
+    common
+}
+
+/// Lines of unchanged text shown around each change in a unified diff.
+const CONTEXT: usize = 3;
+
+/// A step of an edit script: a line kept (` `), deleted (`-`) or inserted
+/// (`+`), with how many lines of each text come before it.
+#[derive(Clone, Copy)]
+struct Edit {
+    kind: char,
+    old: usize,
+    new: usize,
+}
+
+/// Writes the changes turning `old` into `new` as a unified diff of the file
+/// at `path`, as `git diff` would, so that `git apply` or `patch -p1` can
+/// apply it. Returns an empty string if the texts are the same.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::unified_diff;
+///
+/// let patch = unified_diff("steps.md", "<!-- ol -->\n- A\n<!-- /ol -->\n", "<!-- ol -->\n1. A\n<!-- /ol -->\n");
+/// assert_eq!(
+///     patch,
+///     "diff --git a/steps.md b/steps.md\n--- a/steps.md\n+++ b/steps.md\n@@ -1,3 +1,3 @@\n <!-- ol -->\n-- A\n+1. A\n <!-- /ol -->\n"
+/// );
+/// ```
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+
+    let mut edits = Vec::new();
+    let (mut old_index, mut new_index) = (0, 0);
+    let mut common = common_lines(&old, &new);
+    common.push((old.len(), new.len()));
+    for (old_kept, new_kept) in common {
+        for old in old_index..old_kept {
+            edits.push(Edit {
+                kind: '-',
+                old,
+                new: new_index,
+            });
+        }
+        for new in new_index..new_kept {
+            edits.push(Edit {
+                kind: '+',
+                old: old_kept,
+                new,
+            });
+        }
+        if old_kept < old.len() {
+            edits.push(Edit {
+                kind: ' ',
+                old: old_kept,
+                new: new_kept,
+            });
+        }
+        (old_index, new_index) = (old_kept + 1, new_kept + 1);
+    }
+
+    let path = path.replace('\\', "/");
+    let mut patch = format!(
+        "diff --git a/{path} b/{path}\n--- a/{path}\n+++ b/{path}\n",
+        path = path
+    );
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| edit.kind != ' ')
+        .map(|(index, _)| index)
+        .collect();
+    let mut first = 0;
+    while first < changes.len() {
+        // Changes close enough to share their context go in one hunk.
+        let mut last = first;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * CONTEXT + 1 {
+            last += 1;
+        }
+        let start = changes[first].saturating_sub(CONTEXT);
+        let end = (changes[last] + CONTEXT + 1).min(edits.len());
+        patch.push_str(&hunk(&edits[start..end], &old, &new));
+        first = last + 1;
+    }
+    patch
+}
+
+/// Writes one hunk of a unified diff: its header and its lines.
+fn hunk(edits: &[Edit], old: &[&str], new: &[&str]) -> String {
+    let old_count = edits.iter().filter(|edit| edit.kind != '+').count();
+    let new_count = edits.iter().filter(|edit| edit.kind != '-').count();
+    // An empty range is written as starting at the line before it.
+    let range = |start: usize, count: usize| match count {
+        0 => format!("{},0", start),
+        count => format!("{},{}", start + 1, count),
+    };
+
+    let mut hunk = format!(
+        "@@ -{} +{} @@\n",
+        range(edits[0].old, old_count),
+        range(edits[0].new, new_count)
+    );
+    for edit in edits {
+        let line = match edit.kind {
+            '+' => new[edit.new],
+            _ => old[edit.old],
+        };
+        hunk.push(edit.kind);
+        hunk.push_str(line);
+        if !line.ends_with('\n') {
+            hunk.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+    hunk
+}
+
+/// Finds the lines left untouched by a shortest edit script with Myers'
+/// algorithm, or `None` if it takes more than [`MAX_EDITS`] edits.
+fn shortest_edit(old: &[&str], new: &[&str]) -> Option<Vec<(usize, usize)>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    // `v[offset + k]` is the furthest `x` reached on diagonal `k = x - y`.
+    let offset = max + 1;
+    let mut v = vec![0; 2 * offset as usize + 1];
+    // The part of `v` each round of edits started from, for walking back.
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    let mut found = None;
+    for d in 0..=max.min(MAX_EDITS as isize) {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let down =
+                k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]);
+            let mut x = if down {
+                v[(offset + k + 1) as usize]
+            } else {
+                v[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+            if x >= n && y >= m {
+                found = Some(d);
+                break;
+            }
+        }
+        if found.is_some() {
+            break;
+        }
+    }
+    let edits = found?;
+
+    let mut common = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..=edits).rev() {
+        let previous = &trace[d as usize];
+        let at = |k: isize| previous[(k + d) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            common.push((x as usize, y as usize));
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        common.push((x as usize, y as usize));
+    }
+    common.reverse();
+    Some(common)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_lines() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["a", "x", "c", "d", "y", "e"];
+        assert_eq!(
+            common_lines(&old, &new),
+            vec![(0, 0), (2, 2), (3, 3), (4, 5)]
+        );
+        assert_eq!(common_lines(&old, &[]), vec![]);
+        assert_eq!(common_lines(&[], &new), vec![]);
+        assert_eq!(common_lines(&["- a", "- b"], &["1. a", "2. b"]), vec![]);
+        assert_eq!(
+            common_lines(&["x", "a", "b"], &["a", "b", "x"]),
+            vec![(1, 0), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl";
+        let new = "A\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\n";
+        assert_eq!(
+            unified_diff("docs/a.md", old, new),
+            r"diff --git a/docs/a.md b/docs/a.md
+--- a/docs/a.md
++++ b/docs/a.md
+@@ -1,4 +1,4 @@
+-a
++A
+ b
+ c
+ d
+@@ -9,4 +9,5 @@
+ i
+ j
+ k
+-l
+\ No newline at end of file
++l
++m
+"
+        );
+        assert_eq!(
+            unified_diff("a.md", "", "x\n"),
+            "diff --git a/a.md b/a.md\n--- a/a.md\n+++ b/a.md\n@@ -0,0 +1,1 @@\n+x\n"
+        );
+        assert_eq!(unified_diff("a.md", "same\n", "same\n"), "");
+    }
+}
@@ -0,0 +1,254 @@
+use crate::options::ParseOptions;
+use crate::transform::{self, parse_options};
+use comrak::Arena;
+use comrak::nodes::{AstNode, ListDelimType, ListType, NodeValue};
+
+/// Renders `markdown`, typically the output of a transform, as HTML,
+/// recognizing the same extensions it was parsed with. Raw HTML in the
+/// document, such as item anchors and marker comments, is passed through, so
+/// only render documents you trust.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{ParseOptions, render_html, transform};
+///
+/// let markdown = transform("<!-- ol -->\n- Install\n- Run\n<!-- /ol -->");
+/// let html = render_html(&markdown, &ParseOptions::default());
+/// assert_eq!(
+///     html,
+///     "<!-- ol -->\n<ol>\n<li>Install</li>\n<li>Run</li>\n</ol>\n<!-- /ol -->\n"
+/// );
+/// ```
+pub fn render_html(markdown: &str, parse: &ParseOptions) -> String {
+    let mut options = parse_options(parse);
+    options.render.unsafe_ = true;
+    comrak::markdown_to_html(markdown, &options)
+}
+
+/// Renders `markdown`, typically the output of a transform, as plain text
+/// for places that don't render Markdown, such as chat messages and tickets.
+/// List numbers and bullets are kept, with nested items indented under
+/// their parent's text, while emphasis, code spans, headings and raw HTML
+/// lose their syntax. Links are followed by their URL in parentheses.
+/// Documents nested more deeply than [`ParseOptions::max_nesting`] are
+/// returned as they are.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{ParseOptions, render_text, transform};
+///
+/// let markdown = transform("## Setup\n\n<!-- ol -->\n- Install **the** `tool`\n- Run it, after (cur-1)\n<!-- /ol -->");
+/// assert_eq!(
+///     render_text(&markdown, &ParseOptions::default()),
+///     "Setup\n\n1. Install the tool\n2. Run it, after (1)\n"
+/// );
+/// ```
+pub fn render_text(markdown: &str, parse: &ParseOptions) -> String {
+    let arena = Arena::new();
+    let Ok(root) = transform::parse(&arena, markdown, parse) else {
+        return markdown.to_string();
+    };
+    let lines = blocks(root, false);
+    if lines.is_empty() {
+        return String::new();
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Renders the blocks among `node`'s children, separated by blank lines
+/// unless `tight`.
+fn blocks<'a>(node: &'a AstNode<'a>, tight: bool) -> Vec<String> {
+    let mut lines = Vec::new();
+    for child in node.children() {
+        let block = block(child);
+        if block.is_empty() {
+            continue;
+        }
+        if !tight && !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.extend(block);
+    }
+    lines
+}
+
+fn block<'a>(node: &'a AstNode<'a>) -> Vec<String> {
+    let value = node.data.borrow().value.clone();
+    match value {
+        NodeValue::Paragraph | NodeValue::Heading(_) => {
+            inline(node).lines().map(str::to_string).collect()
+        }
+        NodeValue::CodeBlock(code) => code.literal.lines().map(str::to_string).collect(),
+        NodeValue::HtmlBlock(_) | NodeValue::FrontMatter(_) => Vec::new(),
+        NodeValue::ThematicBreak => vec!["----------".to_string()],
+        NodeValue::List(list) => {
+            let mut lines = Vec::new();
+            for (index, item) in node.children().enumerate() {
+                let mut marker = match list.list_type {
+                    ListType::Bullet => "-".to_string(),
+                    ListType::Ordered => format!(
+                        "{}{}",
+                        list.start + index,
+                        match list.delimiter {
+                            ListDelimType::Period => '.',
+                            ListDelimType::Paren => ')',
+                        }
+                    ),
+                };
+                if let NodeValue::TaskItem(checked) = item.data.borrow().value {
+                    marker.push_str(if checked.is_some() { " [x]" } else { " [ ]" });
+                }
+                if !list.tight && !lines.is_empty() {
+                    lines.push(String::new());
+                }
+                lines.extend(hang(&marker, blocks(item, list.tight)));
+            }
+            lines
+        }
+        NodeValue::FootnoteDefinition(definition) => {
+            hang(&format!("[{}]", definition.name), blocks(node, false))
+        }
+        NodeValue::Table(_) => node
+            .children()
+            .map(|row| {
+                row.children()
+                    .map(|cell| inline(cell).replace('\n', " "))
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            })
+            .collect(),
+        NodeValue::BlockQuote | NodeValue::MultilineBlockQuote(_) | NodeValue::Alert(_) => {
+            blocks(node, false)
+                .into_iter()
+                .map(|line| indent(4, line))
+                .collect()
+        }
+        _ => blocks(node, false),
+    }
+}
+
+/// Puts `marker` before the first of `lines`, indenting the others to line
+/// up with the text after it.
+fn hang(marker: &str, lines: Vec<String>) -> Vec<String> {
+    let width = marker.chars().count() + 1;
+    if lines.is_empty() {
+        return vec![marker.to_string()];
+    }
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(index, line)| match index {
+            0 => format!("{} {}", marker, line),
+            _ => indent(width, line),
+        })
+        .collect()
+}
+
+fn indent(width: usize, line: String) -> String {
+    if line.is_empty() {
+        return line;
+    }
+    " ".repeat(width) + &line
+}
+
+/// Returns the text of the inlines under `node`, with line breaks as
+/// newlines.
+fn inline<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(literal) => text.push_str(literal),
+            NodeValue::Code(code) => text.push_str(&code.literal),
+            NodeValue::Math(math) => text.push_str(&math.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => text.push('\n'),
+            NodeValue::HtmlInline(_) => {}
+            NodeValue::FootnoteReference(reference) => {
+                text.push_str(&format!("[{}]", reference.name))
+            }
+            NodeValue::Link(link) => {
+                let label = inline(child);
+                if link.url.is_empty()
+                    || link.url == label
+                    || link.url == format!("mailto:{}", label)
+                {
+                    text.push_str(&label);
+                } else {
+                    text.push_str(&format!("{} ({})", label, link.url));
+                }
+            }
+            _ => text.push_str(&inline(child)),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_html_keeps_numbers_and_anchors() {
+        let markdown =
+            "<!-- ol start=3 -->\n3. <a id=\"setup-step-3\"></a>Install\n4. Run\n<!-- /ol -->\n";
+        let html = render_html(markdown, &ParseOptions::default());
+        assert!(html.contains("<ol start=\"3\">\n<li><a id=\"setup-step-3\"></a>Install</li>"));
+    }
+
+    #[test]
+    fn test_render_text() {
+        let markdown = r#"---
+title: Setup
+---
+
+# Setup
+
+Read the [guide](https://example.com/guide) or <https://example.com>.
+
+<!-- ol -->
+1. Install
+   - On Linux, run:
+
+     ```
+     make install
+     ```
+   - Elsewhere, download it
+2. [x] Check *twice*
+<!-- /ol -->
+
+> **Note:** it takes a while.
+"#;
+        let expected = r#"Setup
+
+Read the guide (https://example.com/guide) or https://example.com.
+
+1. Install
+   - On Linux, run:
+
+     make install
+
+   - Elsewhere, download it
+2. [x] Check twice
+
+    Note: it takes a while.
+"#;
+        let parse = ParseOptions {
+            front_matter_delimiter: Some("---".to_string()),
+            task_lists: true,
+            ..ParseOptions::default()
+        };
+        assert_eq!(render_text(markdown, &parse), expected);
+    }
+
+    #[test]
+    fn test_render_html_uses_parse_extensions() {
+        let markdown = "| a | b |\n|---|---|\n| 1 | 2 |\n";
+        assert!(render_html(markdown, &ParseOptions::default()).contains("<table>"));
+        let parse = ParseOptions {
+            tables: false,
+            ..ParseOptions::default()
+        };
+        assert!(!render_html(markdown, &parse).contains("<table>"));
+    }
+}
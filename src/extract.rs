@@ -0,0 +1,506 @@
+use crate::marker::RegionKind;
+use crate::options::TransformOptions;
+use crate::printer::MarkdownPrinter;
+use crate::reference::{ANNOTATION, ItemNumbers, REFERENCE, Reference};
+use crate::report::TransformReport;
+use crate::transform::{converts_whole_file, regions, transform_document, transform_with_options};
+use comrak::Arena;
+use comrak::nodes::{AstNode, ListType, NodeValue};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use yaml_rust::{Yaml, YamlEmitter, YamlLoader};
+
+/// The ID given to a region without a name.
+static GENERATED_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^line-\d+$").unwrap());
+
+/// The lists of a region, as returned by [`extract`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtractedRegion {
+    /// The region's `name`, or an ID generated from the line of its opening
+    /// marker; `None` for the lists of a file converted as a whole.
+    pub id: Option<String>,
+    /// Whether the region is an `ol` or a `ul` region.
+    pub kind: RegionKind,
+    /// The line of the region's opening marker, or of its first list.
+    /// Ignored by [`generate`].
+    #[serde(default)]
+    pub line: usize,
+    /// The items of the region's lists, with nested items right after the
+    /// item containing them.
+    pub items: Vec<ExtractedItem>,
+}
+
+/// A list item, as returned by [`extract`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtractedItem {
+    /// The item's number, or `None` in a bullet list.
+    pub number: Option<usize>,
+    /// The item's content as Markdown, without its nested lists, with
+    /// references resolved.
+    pub text: String,
+    /// How many lists the item is nested in, 0 for the region's own lists.
+    #[serde(default)]
+    pub depth: usize,
+    /// The references in the item's text, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<ExtractedReference>,
+}
+
+/// A reference in the text of an item. `(item:N)` and `(item:last)`
+/// references are given by the offset of the item they resolve to, as if
+/// they were written as `(cur±N)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtractedReference {
+    /// The `N` of `(cur±N)`.
+    pub offset: i64,
+    /// The number the reference resolves to, or `None` if it is not resolved,
+    /// as in a `ul` region.
+    pub number: Option<i64>,
+}
+
+/// A data format [`extract`] output can be written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DataFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+
+/// Transforms `input` and returns the items of the lists in each of its
+/// regions, in document order, for tools that work with procedures as data.
+///
+/// References are found from `(cur±N)` expressions, and, in lists converted
+/// earlier, from the comments [`TransformOptions::annotate`] leaves after
+/// them.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{TransformOptions, extract};
+///
+/// let input = "<!-- ol -->\n- Back up\n- Restore (cur-1)\n<!-- /ol -->";
+/// let regions = extract(input, &TransformOptions::default());
+/// assert_eq!(regions[0].items[1].text, "Restore (1)");
+/// assert_eq!(regions[0].items[1].references[0].number, Some(1));
+/// ```
+pub fn extract(input: &str, options: &TransformOptions) -> Vec<ExtractedRegion> {
+    let options = TransformOptions {
+        annotate: true,
+        ..options.clone()
+    };
+    let arena = Arena::new();
+    let transformed = transform_document(&arena, input, &options);
+    let root = transformed.root;
+    let convert_all = converts_whole_file(root);
+
+    let mut extracted = Vec::new();
+    for parent in root.descendants() {
+        for region in regions(
+            parent,
+            &options.marker_syntaxes,
+            convert_all,
+            &mut TransformReport::default(),
+        ) {
+            let Some(line) = region
+                .position
+                .or_else(|| Some(region.lists.first()?.data.borrow().sourcepos.start))
+                .map(|position| position.line)
+            else {
+                continue;
+            };
+            let mut items = Vec::new();
+            for list in &region.lists {
+                extract_items(&transformed.source, list, 0, None, &mut items);
+            }
+            extracted.push(ExtractedRegion {
+                id: region.id,
+                kind: region.kind,
+                line,
+                items,
+            });
+        }
+    }
+    extracted.sort_by_key(|region| region.line);
+    extracted
+}
+
+/// Appends the items of `list` and of the lists nested in them to `items`.
+///
+/// References in nested lists are resolved against the item of the region's
+/// list containing them, numbered as `parent` says.
+fn extract_items<'a>(
+    source: &str,
+    list: &'a AstNode<'a>,
+    depth: usize,
+    parent: Option<ItemNumbers>,
+    items: &mut Vec<ExtractedItem>,
+) {
+    let NodeValue::List(list_data) = list.data.borrow().value else {
+        return;
+    };
+    let len = list.children().count();
+    for (index, item) in list.children().enumerate() {
+        let number = (list_data.list_type == ListType::Ordered).then_some(list_data.start + index);
+        let numbers = number.map(|_| ItemNumbers::new(list_data.start as i32, index, len));
+        let mut blocks = Vec::new();
+        let mut references = Vec::new();
+        let mut nested = Vec::new();
+        for child in item.children() {
+            if matches!(child.data.borrow().value, NodeValue::List(_)) {
+                nested.push(child);
+                continue;
+            }
+            references.extend(find_references(child, parent.or(numbers)));
+            let mut printer = MarkdownPrinter::with_source(source);
+            printer.render_node(child);
+            blocks.push(printer.finish().trim_end().to_string());
+        }
+        items.push(ExtractedItem {
+            number,
+            text: blocks.join("\n\n"),
+            depth,
+            references,
+        });
+        for list in nested {
+            extract_items(source, list, depth + 1, parent.or(numbers), items);
+        }
+    }
+}
+
+/// Returns the references in `block`, removing the annotations left after
+/// resolved ones. `numbers` are those of the item the references are
+/// resolved against, if it is numbered.
+fn find_references<'a>(
+    block: &'a AstNode<'a>,
+    numbers: Option<ItemNumbers>,
+) -> Vec<ExtractedReference> {
+    let mut references = Vec::new();
+    let nodes: Vec<_> = block.descendants().collect();
+    for node in nodes {
+        let reference = match &node.data.borrow().value {
+            NodeValue::HtmlInline(html) => ANNOTATION
+                .captures(html)
+                .and_then(|captures| Reference::parse(&captures[1])),
+            NodeValue::Text(text) => {
+                references.extend(REFERENCE.captures_iter(text).filter_map(|captures| {
+                    match Reference::parse(&captures[1])? {
+                        Reference::Relative(offset) => Some(ExtractedReference {
+                            offset: offset.into(),
+                            number: None,
+                        }),
+                        // Absolute references can only be told apart from
+                        // the item they're in once the list is numbered.
+                        Reference::Item(_) | Reference::Last => None,
+                    }
+                }));
+                None
+            }
+            _ => None,
+        };
+        if let Some(reference) = reference {
+            node.detach();
+            let number = numbers.and_then(|numbers| reference.resolve(numbers));
+            let offset = match (reference, numbers, number) {
+                (Reference::Relative(offset), _, _) => offset.into(),
+                (_, Some(numbers), Some(number)) => i64::from(number) - i64::from(numbers.current),
+                _ => continue,
+            };
+            references.push(ExtractedReference {
+                offset,
+                number: number.map(i64::from),
+            });
+        }
+    }
+    references
+}
+
+/// Writes the output of [`extract`] as JSON or YAML.
+pub fn regions_to_string(regions: &[ExtractedRegion], format: DataFormat) -> String {
+    let value = serde_json::to_value(regions).expect("extracted regions serialize");
+    match format {
+        DataFormat::Json => {
+            serde_json::to_string_pretty(&value).expect("extracted regions serialize") + "\n"
+        }
+        DataFormat::Yaml => {
+            let mut output = String::new();
+            YamlEmitter::new(&mut output)
+                .dump(&to_yaml(value))
+                .expect("writing to a string cannot fail");
+            output + "\n"
+        }
+    }
+}
+
+/// Reads regions written by [`regions_to_string`], or by hand in the same
+/// shape, from JSON or YAML.
+pub fn regions_from_str(text: &str, format: DataFormat) -> Result<Vec<ExtractedRegion>, String> {
+    let value = parse_data(text, format)?.unwrap_or(serde_json::Value::Array(Vec::new()));
+    serde_json::from_value(value).map_err(|error| error.to_string())
+}
+
+/// Parses JSON or YAML into a JSON value, or `None` for a YAML file without
+/// any document.
+pub(crate) fn parse_data(
+    text: &str,
+    format: DataFormat,
+) -> Result<Option<serde_json::Value>, String> {
+    match format {
+        DataFormat::Json => serde_json::from_str(text)
+            .map(Some)
+            .map_err(|error| error.to_string()),
+        DataFormat::Yaml => {
+            let documents = YamlLoader::load_from_str(text).map_err(|error| error.to_string())?;
+            documents.into_iter().next().map(from_yaml).transpose()
+        }
+    }
+}
+
+/// Writes `regions` as Markdown: each becomes a region with its items, whose
+/// references are turned back into `(cur±N)` expressions, and is then
+/// transformed with `options` to number it. The output is always annotated,
+/// so that [`extract`] finds the references in it again.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{DataFormat, TransformOptions, generate, regions_from_str};
+///
+/// let data = r#"[{"id": "setup", "kind": "ol", "items": [
+///     {"number": 1, "text": "Back up"},
+///     {"number": 2, "text": "Restore (1)", "references": [{"offset": -1, "number": 1}]}
+/// ]}]"#;
+/// let regions = regions_from_str(data, DataFormat::Json).unwrap();
+/// assert_eq!(
+///     generate(&regions, &TransformOptions::default()),
+///     "<!-- ol name=setup -->\n1. Back up\n2. Restore (1)<!-- cur-1 -->\n<!-- /ol -->\n"
+/// );
+/// ```
+pub fn generate(regions: &[ExtractedRegion], options: &TransformOptions) -> String {
+    let mut markdown = Vec::new();
+    for region in regions {
+        let keyword = region.kind.keyword();
+        let mut lines = vec![match &region.id {
+            Some(id) if !GENERATED_ID.is_match(id) => format!("<!-- {} name={} -->", keyword, id),
+            _ => format!("<!-- {} -->", keyword),
+        }];
+        for item in &region.items {
+            let indent = "  ".repeat(item.depth);
+            let marker = match item.number {
+                Some(number) if item.depth > 0 => format!("{}. ", number),
+                _ => "- ".to_string(),
+            };
+            let text = unresolve_references(&item.text, &item.references);
+            let continuation = " ".repeat(indent.len() + marker.len());
+            for (index, line) in text.lines().enumerate() {
+                lines.push(match index {
+                    0 => format!("{}{}{}", indent, marker, line),
+                    _ if line.is_empty() => String::new(),
+                    _ => format!("{}{}", continuation, line),
+                });
+            }
+        }
+        lines.push(format!("<!-- /{} -->", keyword));
+        markdown.push(lines.join("\n"));
+    }
+    let options = TransformOptions {
+        annotate: true,
+        strip_markers: false,
+        ..options.clone()
+    };
+    transform_with_options(&markdown.join("\n\n"), &options)
+}
+
+/// Replaces each resolved reference in `text`, in order, with the `(cur±N)`
+/// expression it was resolved from.
+fn unresolve_references(text: &str, references: &[ExtractedReference]) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    for reference in references {
+        let Some(number) = reference.number else {
+            continue;
+        };
+        let resolved = format!("({})", number);
+        if let Some(index) = rest.find(&resolved) {
+            result.push_str(&rest[..index]);
+            result.push_str(&format!("(cur{:+})", reference.offset));
+            rest = &rest[index + resolved.len()..];
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+fn from_yaml(yaml: Yaml) -> Result<serde_json::Value, String> {
+    Ok(match yaml {
+        Yaml::Null => serde_json::Value::Null,
+        Yaml::Boolean(value) => serde_json::Value::Bool(value),
+        Yaml::Integer(value) => value.into(),
+        Yaml::Real(value) => value
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or(serde_json::Value::Null, serde_json::Value::Number),
+        Yaml::String(value) => serde_json::Value::String(value),
+        Yaml::Array(values) => serde_json::Value::Array(
+            values
+                .into_iter()
+                .map(from_yaml)
+                .collect::<Result<_, _>>()?,
+        ),
+        Yaml::Hash(entries) => serde_json::Value::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        Yaml::String(key) => key,
+                        Yaml::Integer(key) => key.to_string(),
+                        other => return Err(format!("unsupported YAML key: {:?}", other)),
+                    };
+                    Ok((key, from_yaml(value)?))
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        other => return Err(format!("unsupported YAML value: {:?}", other)),
+    })
+}
+
+fn to_yaml(value: serde_json::Value) -> Yaml {
+    match value {
+        serde_json::Value::Null => Yaml::Null,
+        serde_json::Value::Bool(value) => Yaml::Boolean(value),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(number) => Yaml::Integer(number),
+            None => Yaml::Real(number.to_string()),
+        },
+        serde_json::Value::String(value) => Yaml::String(value),
+        serde_json::Value::Array(values) => Yaml::Array(values.into_iter().map(to_yaml).collect()),
+        serde_json::Value::Object(entries) => Yaml::Hash(
+            entries
+                .into_iter()
+                .map(|(key, value)| (Yaml::String(key), to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_regions() {
+        let input = "Intro\n\n<!-- ol name=setup -->\n- Back up the **database**\n- Restore (cur-1)\n  - [ ] Check (cur+1)\n\n  More text\n<!-- /ol -->\n\n<!-- ul -->\n- See (cur-1)\n<!-- /ul -->\n";
+        let regions = extract(input, &TransformOptions::default());
+        assert_eq!(
+            regions,
+            vec![
+                ExtractedRegion {
+                    id: Some("setup".to_string()),
+                    kind: RegionKind::Ol,
+                    line: 3,
+                    items: vec![
+                        ExtractedItem {
+                            number: Some(1),
+                            text: "Back up the **database**".to_string(),
+                            depth: 0,
+                            references: Vec::new(),
+                        },
+                        ExtractedItem {
+                            number: Some(2),
+                            text: "Restore (1)\n\nMore text".to_string(),
+                            depth: 0,
+                            references: vec![ExtractedReference {
+                                offset: -1,
+                                number: Some(1),
+                            }],
+                        },
+                        ExtractedItem {
+                            number: None,
+                            text: "Check (3)".to_string(),
+                            depth: 1,
+                            references: vec![ExtractedReference {
+                                offset: 1,
+                                number: Some(3),
+                            }],
+                        },
+                    ],
+                },
+                ExtractedRegion {
+                    id: Some("line-11".to_string()),
+                    kind: RegionKind::Ul,
+                    line: 11,
+                    items: vec![ExtractedItem {
+                        number: None,
+                        text: "See (cur-1)".to_string(),
+                        depth: 0,
+                        references: vec![ExtractedReference {
+                            offset: -1,
+                            number: None,
+                        }],
+                    }],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_regions_to_string() {
+        let regions = extract(
+            "<!-- ol -->\n- One\n- Two (cur-1)\n<!-- /ol -->",
+            &TransformOptions::default(),
+        );
+        assert_eq!(
+            regions_to_string(&regions, DataFormat::Yaml),
+            r#"---
+- id: line-1
+  kind: ol
+  line: 1
+  items:
+    - number: 1
+      text: One
+      depth: 0
+    - number: 2
+      text: Two (1)
+      depth: 0
+      references:
+        - offset: -1
+          number: 1
+"#
+        );
+        assert!(regions_to_string(&regions, DataFormat::Json).starts_with(
+            "[\n  {\n    \"id\": \"line-1\",\n    \"kind\": \"ol\",\n    \"line\": 1,"
+        ));
+    }
+
+    #[test]
+    fn test_generate_round_trip() {
+        let input = "<!-- ol name=setup -->\n1. Back up the **database**\n\n2. Restore (1)<!-- cur-1 -->\n\n   More text\n\n   - Check (2)<!-- cur+0 -->\n<!-- /ol -->\n\n<!-- ul -->\n- See (cur-1)\n<!-- /ul -->\n";
+        let options = TransformOptions {
+            annotate: true,
+            ..TransformOptions::default()
+        };
+        let regions = extract(input, &options);
+        for format in [DataFormat::Json, DataFormat::Yaml] {
+            let data = regions_to_string(&regions, format);
+            let parsed = regions_from_str(&data, format).unwrap();
+            assert_eq!(parsed, regions);
+            assert_eq!(generate(&parsed, &options), input);
+        }
+    }
+
+    #[test]
+    fn test_extract_generated_regions() {
+        let input = "<!-- ol name=setup -->\n- Back up\n- Restore (cur-1)\n  - Check (cur+1)\n<!-- /ol -->\n\n<!-- ul -->\n- See (cur-1)\n<!-- /ul -->\n";
+        let options = TransformOptions::default();
+        let regions = extract(input, &options);
+        let generated = generate(&regions, &options);
+        assert_eq!(extract(&generated, &options), regions);
+    }
+
+    #[test]
+    fn test_regions_from_invalid_data() {
+        assert!(regions_from_str("[{\"kind\": \"ol\"}]", DataFormat::Json).is_err());
+        assert!(regions_from_str("- kind: [", DataFormat::Yaml).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use comrak::nodes::{AstNode, NodeValue};
+use std::collections::HashMap;
+
+/// Moves footnote definitions back to where they are in the source.
+///
+/// comrak moves every referenced footnote definition to the end of the
+/// document, in order of first reference; the printer would otherwise write
+/// them there.
+pub(crate) fn restore_positions<'a>(root: &'a AstNode<'a>) {
+    let definitions: Vec<_> = root
+        .children()
+        .filter(|child| is_definition(child))
+        .collect();
+    for definition in definitions {
+        definition.detach();
+        let line = definition.data.borrow().sourcepos.start.line;
+        let next = root
+            .children()
+            .find(|child| !is_definition(child) && child.data.borrow().sourcepos.start.line > line);
+        match next {
+            Some(next) => next.insert_before(definition),
+            None => root.append(definition),
+        }
+    }
+}
+
+/// Renumbers footnotes with numeric labels, such as `[^3]`, to count up from
+/// 1 in order of their first reference, and sorts each run of adjacent
+/// footnote definitions to match. Footnotes with other labels, such as
+/// `[^note]`, keep their labels and come after the numbered ones.
+pub(crate) fn renumber<'a>(root: &'a AstNode<'a>) {
+    let mut numbers: HashMap<String, String> = HashMap::new();
+    for node in root.descendants() {
+        if let NodeValue::FootnoteReference(reference) = &mut node.data.borrow_mut().value
+            && is_numeric(&reference.name)
+        {
+            let next = (numbers.len() + 1).to_string();
+            let number = numbers.entry(reference.name.clone()).or_insert(next);
+            reference.name = number.clone();
+        }
+    }
+
+    let mut runs: Vec<Vec<&'a AstNode<'a>>> = Vec::new();
+    let mut previous_was_definition = false;
+    for child in root.children() {
+        if let NodeValue::FootnoteDefinition(definition) = &mut child.data.borrow_mut().value {
+            if let Some(number) = numbers.get(&definition.name) {
+                definition.name = number.clone();
+            }
+            if !previous_was_definition {
+                runs.push(Vec::new());
+            }
+            runs.last_mut().unwrap().push(child);
+            previous_was_definition = true;
+        } else {
+            previous_was_definition = false;
+        }
+    }
+
+    for run in runs {
+        let mut sorted = run.clone();
+        sorted.sort_by_key(|definition| match &definition.data.borrow().value {
+            NodeValue::FootnoteDefinition(definition) if is_numeric(&definition.name) => {
+                definition.name.parse::<usize>().unwrap_or(usize::MAX)
+            }
+            _ => usize::MAX,
+        });
+        let mut previous = run[0].previous_sibling();
+        for definition in &run {
+            definition.detach();
+        }
+        for definition in sorted {
+            match previous {
+                Some(previous) => previous.insert_after(definition),
+                None => root.prepend(definition),
+            }
+            previous = Some(definition);
+        }
+    }
+}
+
+fn is_definition<'a>(node: &'a AstNode<'a>) -> bool {
+    matches!(node.data.borrow().value, NodeValue::FootnoteDefinition(_))
+}
+
+fn is_numeric(label: &str) -> bool {
+    !label.is_empty() && label.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::{ParseOptions, TransformOptions};
+    use crate::transform::transform_with_options;
+
+    fn with_footnotes(input: &str, renumber_footnotes: bool) -> String {
+        let options = TransformOptions {
+            parse: ParseOptions {
+                footnotes: true,
+                ..ParseOptions::default()
+            },
+            renumber_footnotes,
+            ..TransformOptions::default()
+        };
+        transform_with_options(input, &options)
+    }
+
+    #[test]
+    fn test_footnotes_stay_in_place() {
+        let input = "A[^2] and B[^1].\n\n[^1]: One.\n\nMiddle.\n\n[^2]: Two.\n\n[^3]: Never referenced.\n\nEnd.\n";
+        assert_eq!(with_footnotes(input, false), input);
+    }
+
+    #[test]
+    fn test_renumber_footnotes() {
+        let input = "A[^3], B[^note] and C[^1], again[^3].\n\n[^1]: One.\n\n[^note]: Named.\n\n[^3]: Three.\n";
+        let expected = "A[^1], B[^note] and C[^2], again[^1].\n\n[^1]: Three.\n\n[^2]: One.\n\n[^note]: Named.\n";
+        assert_eq!(with_footnotes(input, true), expected);
+        assert_eq!(with_footnotes(expected, true), expected);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::marker::{Marker, MarkerSyntax, marker_text, parse_marker};
+use crate::options::HeadingNumbers;
+use crate::transform::new_node;
+use comrak::Arena;
+use comrak::nodes::{AstNode, NodeValue};
+use regex::Regex;
+
+/// Numbers the headings among the children of `root` that are inside a
+/// `<!-- toc-numbers -->` scope, or all of them with
+/// [`HeadingNumbers::all`], e.g. `## 2.1 Installation`. With
+/// [`HeadingNumbers::strip`] the numbers are removed instead.
+///
+/// A number already at the start of a heading, in the same form, is replaced,
+/// so headings can be renumbered after sections are added or moved.
+pub(crate) fn number_headings<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    options: &HeadingNumbers,
+    syntaxes: &[MarkerSyntax],
+) {
+    let min_level = *options.levels.start();
+    let max_level = *options.levels.end();
+    if min_level > max_level {
+        return;
+    }
+
+    let existing = Regex::new(&format!(
+        r"^\d+(?:{0}\d+)*{0}?(?:\s+|$)",
+        regex::escape(&options.separator)
+    ))
+    .unwrap();
+    let mut counters = vec![0usize; usize::from(max_level - min_level) + 1];
+    let mut in_scope = options.all;
+
+    for child in root.children() {
+        match marker_text(child).and_then(|text| parse_marker(&text, syntaxes)) {
+            Some((Marker::TocNumbers, _)) => in_scope = true,
+            Some((Marker::TocNumbersEnd, _)) => in_scope = options.all,
+            _ => {}
+        }
+
+        let level = match &child.data.borrow().value {
+            NodeValue::Heading(heading) => heading.level,
+            _ => continue,
+        };
+        if !in_scope || level > max_level {
+            continue;
+        }
+        if level < min_level {
+            counters.fill(0);
+            continue;
+        }
+
+        let depth = usize::from(level - min_level);
+        counters[depth] += 1;
+        counters[depth + 1..].fill(0);
+        let number = counters[..=depth]
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(&options.separator);
+
+        let text_node = match child.first_child() {
+            Some(node) if matches!(node.data.borrow().value, NodeValue::Text(_)) => node,
+            _ if options.strip => continue,
+            _ => {
+                let node = new_node(arena, NodeValue::Text(String::new()));
+                child.prepend(node);
+                node
+            }
+        };
+        if let NodeValue::Text(text) = &mut text_node.data.borrow_mut().value {
+            let rest = match existing.find(text) {
+                Some(found) => text[found.end()..].to_string(),
+                None => text.clone(),
+            };
+            *text = if options.strip {
+                rest
+            } else if rest.is_empty() && text_node.next_sibling().is_none() {
+                number
+            } else {
+                format!("{} {}", number, rest)
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::{HeadingNumbers, TransformOptions};
+    use crate::transform::{transform, transform_with_options};
+
+    fn numbered(input: &str, heading_numbers: HeadingNumbers) -> String {
+        let options = TransformOptions {
+            heading_numbers,
+            ..TransformOptions::default()
+        };
+        transform_with_options(input, &options)
+    }
+
+    #[test]
+    fn test_number_headings_in_scope() {
+        let input = "# Guide\n\n## Intro\n\n<!-- toc-numbers -->\n\n## Install\n\n### Linux\n\n### macOS\n\n## Use *it*\n\n<!-- /toc-numbers -->\n\n## Appendix\n";
+        let expected = "# Guide\n\n## Intro\n\n<!-- toc-numbers -->\n\n## 1 Install\n\n### 1.1 Linux\n\n### 1.2 macOS\n\n## 2 Use *it*\n\n<!-- /toc-numbers -->\n\n## Appendix\n";
+        assert_eq!(transform(input), expected);
+        assert_eq!(transform(expected), expected);
+    }
+
+    #[test]
+    fn test_number_all_headings_with_options() {
+        let input = "# Title\n\n## 3 Old number\n\n#### Skipped a level\n\n## Next\n";
+        let options = HeadingNumbers {
+            all: true,
+            levels: 2..=3,
+            separator: "-".to_string(),
+            ..HeadingNumbers::default()
+        };
+        assert_eq!(
+            numbered(input, options),
+            "# Title\n\n## 1 Old number\n\n#### Skipped a level\n\n## 2 Next\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_heading_numbers() {
+        let input = "## 1 Install\n\n### 1.1 Linux\n\n## 2 Use\n";
+        let options = HeadingNumbers {
+            all: true,
+            strip: true,
+            ..HeadingNumbers::default()
+        };
+        assert_eq!(
+            numbered(input, options),
+            "## Install\n\n### Linux\n\n## Use\n"
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use crate::lint::{LintConfig, lint};
+use crate::options::{RenderOptions, TransformOptions};
+use crate::report::Diagnostic;
+use crate::transform::transform_with_report;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The largest request body accepted, so that a client can't make the server
+/// allocate without bound.
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+/// The largest request line and headers accepted, together.
+const MAX_HEAD: usize = 64 * 1024;
+
+/// How long a connection may take to send its request or read the response
+/// before it is dropped.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The most connections handled at once, each on a thread of its own.
+const MAX_CONNECTIONS: usize = 64;
+
+/// Serves a small JSON API over HTTP on `listener`, handling each connection
+/// on its own thread:
+///
+/// - `POST /transform` takes `{"markdown": "...", "options": {...}}` and
+///   returns `{"output": "...", "diagnostics": [...]}`.
+/// - `POST /analyze` takes the same and returns the transform's and the
+///   lint rules' `diagnostics` and the document's `regions`, without the
+///   output.
+///
+/// Documents are transformed with `options`, overridden by the request's
+/// `options`, which may set `annotate`, `join_lists`, `tasks`,
+/// `pad_numbers`, `number_captions` and `continue_from`. Includes are never
+/// expanded, so requests can't read files on the server.
+///
+/// Connections that stay idle for 30 seconds are dropped. At most 64
+/// connections are handled at once; further ones wait to be accepted until
+/// one of them ends. Failing to accept a connection is logged to stderr and
+/// doesn't stop the server.
+pub fn serve_http(listener: TcpListener, options: &TransformOptions) -> io::Result<()> {
+    let options = Arc::new(TransformOptions {
+        include_dir: None,
+        ..options.clone()
+    });
+    let slots = Arc::new(Slots::default());
+    loop {
+        let slot = slots.take();
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(error) => {
+                eprintln!("failed to accept a connection: {}", error);
+                continue;
+            }
+        };
+        let options = Arc::clone(&options);
+        // A client that goes away mid-request shouldn't stop the server.
+        thread::spawn(move || {
+            let _slot = slot;
+            handle_connection(stream, &options)
+        });
+    }
+}
+
+/// Counts the connections being handled, up to [`MAX_CONNECTIONS`].
+#[derive(Default)]
+struct Slots {
+    active: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Slots {
+    /// Waits until fewer than [`MAX_CONNECTIONS`] connections are handled,
+    /// returning a slot for one more that is given back when dropped.
+    fn take(self: &Arc<Self>) -> Slot {
+        let mut active = self.active.lock().unwrap();
+        while *active >= MAX_CONNECTIONS {
+            active = self.freed.wait(active).unwrap();
+        }
+        *active += 1;
+        Slot(Arc::clone(self))
+    }
+}
+
+struct Slot(Arc<Slots>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.active.lock().unwrap() -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+fn handle_connection(stream: TcpStream, options: &TransformOptions) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (status, body) = match read_request(&mut reader)? {
+        Ok((method, path, body)) => respond(&method, &path, &body, options),
+        Err(message) => (400, json!({ "error": message })),
+    };
+    write_response(stream, status, &body)
+}
+
+/// Reads a request, returning its method, path and body, or a message saying
+/// why it is malformed.
+fn read_request(reader: &mut impl BufRead) -> io::Result<Result<(String, String, String), String>> {
+    let mut head = reader.take(MAX_HEAD as u64);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Ok(Err("malformed request line".to_string()));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if head.read_line(&mut header)? == 0 {
+            if head.limit() == 0 {
+                return Ok(Err(format!("headers larger than {} bytes", MAX_HEAD)));
+            }
+            break;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            match value.trim().parse() {
+                Ok(value) => length = value,
+                Err(_) => return Ok(Err("invalid Content-Length".to_string())),
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Ok(Err(format!("body larger than {} bytes", MAX_BODY)));
+    }
+
+    let mut body = vec![0; length];
+    head.into_inner().read_exact(&mut body)?;
+    Ok(String::from_utf8(body)
+        .map(|body| (method, path, body))
+        .map_err(|_| "body is not UTF-8".to_string()))
+}
+
+fn write_response(mut stream: TcpStream, status: u16, body: &Value) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        _ => "Method Not Allowed",
+    };
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// The body of a request.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    markdown: String,
+    #[serde(default)]
+    options: RequestOptions,
+}
+
+/// The options a request may override.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RequestOptions {
+    annotate: Option<bool>,
+    join_lists: Option<bool>,
+    tasks: Option<bool>,
+    pad_numbers: Option<bool>,
+    number_captions: Option<bool>,
+    continue_from: Option<usize>,
+}
+
+impl RequestOptions {
+    fn apply(self, options: &TransformOptions) -> TransformOptions {
+        TransformOptions {
+            annotate: self.annotate.unwrap_or(options.annotate),
+            join_lists: self.join_lists.unwrap_or(options.join_lists),
+            tasks: self.tasks.unwrap_or(options.tasks),
+            number_captions: self.number_captions.unwrap_or(options.number_captions),
+            continue_from: self.continue_from.or(options.continue_from),
+            render: RenderOptions {
+                pad_numbers: self.pad_numbers.unwrap_or(options.render.pad_numbers),
+                ..options.render.clone()
+            },
+            ..options.clone()
+        }
+    }
+}
+
+/// Returns the status and body of the response to a request.
+fn respond(method: &str, path: &str, body: &str, options: &TransformOptions) -> (u16, Value) {
+    if !matches!(path, "/transform" | "/analyze") {
+        return (
+            404,
+            json!({ "error": format!("no such endpoint `{}`", path) }),
+        );
+    }
+    if method != "POST" {
+        return (
+            405,
+            json!({ "error": format!("`{}` only accepts POST", path) }),
+        );
+    }
+    let request: Request = match serde_json::from_str(body) {
+        Ok(request) => request,
+        Err(error) => return (400, json!({ "error": error.to_string() })),
+    };
+    let options = request.options.apply(options);
+
+    let (output, report) = transform_with_report(&request.markdown, &options);
+    if path == "/transform" {
+        return (
+            200,
+            json!({
+                "output": output,
+                "diagnostics": report.diagnostics.iter().map(diagnostic_json).collect::<Vec<_>>(),
+            }),
+        );
+    }
+    let diagnostics = lint(&request.markdown, &options, &LintConfig::default());
+    let regions: Vec<_> = report
+        .regions
+        .iter()
+        .map(|region| {
+            json!({
+                "id": region.id,
+                "line": region.line,
+                "column": region.column,
+                "lists": region.lists,
+                "items": region.items,
+            })
+        })
+        .collect();
+    (
+        200,
+        json!({
+            "diagnostics": diagnostics.iter().map(diagnostic_json).collect::<Vec<_>>(),
+            "regions": regions,
+        }),
+    )
+}
+
+fn diagnostic_json(diagnostic: &Diagnostic) -> Value {
+    json!({
+        "severity": diagnostic.severity.to_string(),
+        "line": diagnostic.line,
+        "column": diagnostic.column,
+        "message": diagnostic.message,
+        "region": diagnostic.region,
+        "rule": diagnostic.rule,
+        "code": diagnostic.code,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_respond() {
+        let options = TransformOptions::default();
+        let body = r#"{"markdown": "<!-- ol -->\n- A\n- B (cur-1)\n<!-- /ol -->\n", "options": {"annotate": true}}"#;
+        let (status, response) = respond("POST", "/transform", body, &options);
+        assert_eq!(status, 200);
+        assert_eq!(
+            response["output"],
+            "<!-- ol -->\n1. A\n2. B (1)<!-- cur-1 -->\n<!-- /ol -->\n"
+        );
+
+        let body = r#"{"markdown": "<!-- ol strat=2 -->\n- A\n<!-- /ol -->\n"}"#;
+        let (status, response) = respond("POST", "/analyze", body, &options);
+        assert_eq!(status, 200);
+        assert_eq!(response["regions"][0]["items"], 1);
+        assert_eq!(response["diagnostics"][0]["line"], 1);
+
+        assert_eq!(respond("GET", "/transform", "", &options).0, 405);
+        assert_eq!(respond("POST", "/", "", &options).0, 404);
+        assert_eq!(respond("POST", "/transform", "{}", &options).0, 400);
+        let unknown = r#"{"markdown": "", "options": {"include_dir": "/"}}"#;
+        assert_eq!(respond("POST", "/transform", unknown, &options).0, 400);
+    }
+
+    #[test]
+    fn test_read_request_limits_headers() {
+        let padding = "X-Padding: ".to_string() + &"a".repeat(MAX_HEAD);
+        let request = format!("POST /transform HTTP/1.1\r\n{}\r\n\r\n", padding);
+        let result = read_request(&mut request.as_bytes()).unwrap();
+        assert_eq!(
+            result,
+            Err(format!("headers larger than {} bytes", MAX_HEAD))
+        );
+    }
+
+    /// Sends `body` to `/transform` at `address`, returning the response.
+    fn post(address: SocketAddr, body: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "POST /transform HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_serve_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve_http(listener, &TransformOptions::default()));
+
+        // A client that never sends anything doesn't hold up the others.
+        let _idle = TcpStream::connect(address).unwrap();
+        let response = post(address, r#"{"markdown": "<!-- ol -->\n- A\n<!-- /ol -->"}"#);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["output"], "<!-- ol -->\n1. A\n<!-- /ol -->\n");
+    }
+
+    #[test]
+    fn test_serve_http_limits_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve_http(listener, &TransformOptions::default()));
+
+        let idle: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| TcpStream::connect(address).unwrap())
+            .collect();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        thread::spawn(move || sender.send(post(address, r#"{"markdown": "- A"}"#)));
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+
+        // Connections that end make room for the waiting one.
+        drop(idle);
+        let response = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::marker::{Marker, MarkerSyntax, RegionKind, marker_syntax, parse_marker};
+use crate::options::TransformOptions;
+use crate::report::TransformReport;
+use crate::transform::{converts_whole_file, nesting_message, parse, regions};
+use comrak::Arena;
+use std::fs;
+use std::ops::RangeInclusive;
+
+/// A document with the regions its `<!-- ol include=... -->` markers name
+/// spliced in.
+pub(crate) struct Expanded {
+    pub(crate) text: String,
+    /// The 1-based lines of the spliced-in content, each range followed by
+    /// the `<!-- /include -->` marker ending it.
+    pub(crate) included: Vec<RangeInclusive<usize>>,
+    /// The line of the input each line of `text` comes from, spliced-in
+    /// lines coming from their `include` marker.
+    pub(crate) origins: Vec<usize>,
+}
+
+impl Expanded {
+    /// Returns the line of the input line `line` of the text comes from.
+    pub(crate) fn origin(&self, line: usize) -> usize {
+        self.origins
+            .get(line.wrapping_sub(1))
+            .copied()
+            .unwrap_or(line)
+    }
+}
+
+/// Splices the lists of the region each `<!-- ol include=path#name -->`
+/// marker names after the marker, followed by a `<!-- /include -->` marker.
+/// Content spliced in by an earlier run, up to that marker, is replaced, so
+/// that changes to the included file show up.
+///
+/// Only markers at the start of a line are expanded, and the included
+/// region's own includes are not. Markers outside any `ol` region are left
+/// unexpanded, as the lists they would splice in would not be numbered.
+pub(crate) fn expand_includes(
+    input: &str,
+    options: &TransformOptions,
+    report: &mut TransformReport,
+) -> Expanded {
+    let syntaxes = &options.marker_syntaxes;
+    let marker = |line: &str| {
+        if line.starts_with(char::is_whitespace) {
+            return None;
+        }
+        parse_marker(line.trim_end(), syntaxes)
+    };
+
+    let lines: Vec<&str> = input.split_inclusive('\n').collect();
+    let mut text = String::with_capacity(input.len());
+    let mut included = Vec::new();
+    let mut origins = Vec::with_capacity(lines.len() + 1);
+    let mut output_line = 0;
+    let mut index = 0;
+    let mut ol_lines = None;
+    while index < lines.len() {
+        let line = lines[index];
+        index += 1;
+        text.push_str(line);
+        origins.push(index);
+        output_line += 1;
+        let Some((Marker::Include(target), warnings)) = marker(line) else {
+            continue;
+        };
+        for warning in warnings {
+            report.warn("marker-attribute", index, 1, warning);
+        }
+        if let Some(ol_lines) = ol_lines.get_or_insert_with(|| ol_region_lines(input, options))
+            && !ol_lines.iter().any(|lines| lines.contains(&index))
+        {
+            report.warn(
+                "include-failed",
+                index,
+                1,
+                "`include` is outside any `ol` region; leaving it unexpanded".to_string(),
+            );
+            report.event(
+                "includes",
+                index,
+                1,
+                "left the include unexpanded, outside any `ol` region".to_string(),
+            );
+            continue;
+        }
+
+        // Drop what an earlier run spliced in.
+        let end = lines[index..]
+            .iter()
+            .position(|line| matches!(marker(line), Some((Marker::IncludeEnd, _))))
+            .filter(|&end| {
+                lines[index..index + end].iter().all(|line| {
+                    !matches!(
+                        marker(line),
+                        Some((Marker::Open(..) | Marker::Close(_) | Marker::Include(_), _))
+                    )
+                })
+            });
+
+        let content = match read_region(&target, options) {
+            Ok(content) => content,
+            Err(message) => {
+                report.warn(
+                    "include-failed",
+                    index,
+                    1,
+                    format!("{}; leaving it unexpanded", message),
+                );
+                report.event(
+                    "includes",
+                    index,
+                    1,
+                    format!("left the include unexpanded: {}", message),
+                );
+                continue;
+            }
+        };
+        let marker_line = index;
+        if let Some(end) = end {
+            index += end + 1;
+        }
+        if !line.ends_with('\n') {
+            text.push('\n');
+        }
+        let first = output_line + 1;
+        for content_line in content.lines() {
+            text.push_str(content_line);
+            text.push('\n');
+            origins.push(marker_line);
+            output_line += 1;
+        }
+        included.push(first..=output_line);
+        report.event(
+            "includes",
+            marker_line,
+            1,
+            format!(
+                "spliced in {} lines of `{}`",
+                output_line + 1 - first,
+                target
+            ),
+        );
+        let syntax = marker_syntax(line.trim_end(), syntaxes).unwrap_or(MarkerSyntax::HtmlComment);
+        text.push_str(&syntax.wrap("/include"));
+        text.push('\n');
+        origins.push(marker_line);
+        output_line += 1;
+    }
+    // Diagnostics at the end of the document point one line past it.
+    origins.push(lines.len() + 1);
+    Expanded {
+        text,
+        included,
+        origins,
+    }
+}
+
+/// Returns the lines of the `ol` regions of `input`, from their opening
+/// markers to their ends, or `None` if every list of the document is
+/// numbered.
+fn ol_region_lines(input: &str, options: &TransformOptions) -> Option<Vec<RangeInclusive<usize>>> {
+    let arena = Arena::new();
+    let root = parse(&arena, input, &options.parse).ok()?;
+    if converts_whole_file(root) {
+        return None;
+    }
+    let ol_lines = root
+        .descendants()
+        .flat_map(|parent| {
+            regions(
+                parent,
+                &options.marker_syntaxes,
+                false,
+                &mut TransformReport::default(),
+            )
+        })
+        .filter(|region| region.kind == RegionKind::Ol)
+        .filter_map(|region| Some(region.position?.line..=region.end_line))
+        .collect();
+    Some(ol_lines)
+}
+
+/// Reads the lines from the first to the last list of the region `target`,
+/// written `path#name`, points at.
+fn read_region(target: &str, options: &TransformOptions) -> Result<String, String> {
+    let Some((path, name)) = target.split_once('#') else {
+        return Err(format!(
+            "invalid value `{}` for `include`: expected `path#region-name`",
+            target
+        ));
+    };
+    let Some(directory) = &options.include_dir else {
+        return Err("`include` needs `TransformOptions::include_dir` to read files".to_string());
+    };
+    let source = fs::read_to_string(directory.join(path))
+        .map_err(|error| format!("cannot read `{}`: {}", path, error))?;
+
+    let arena = Arena::new();
+    let root = parse(&arena, &source, &options.parse).map_err(|_| {
+        format!(
+            "`{}` is {}",
+            path,
+            nesting_message(options.parse.max_nesting)
+        )
+    })?;
+    let region = root
+        .descendants()
+        .flat_map(|parent| {
+            regions(
+                parent,
+                &options.marker_syntaxes,
+                false,
+                &mut TransformReport::default(),
+            )
+        })
+        .find(|region| region.attributes.name.as_deref() == Some(name))
+        .ok_or_else(|| format!("`{}` has no region named `{}`", path, name))?;
+    let (Some(first), Some(last)) = (region.lists.first(), region.lists.last()) else {
+        return Err(format!("region `{}` in `{}` has no lists", name, path));
+    };
+    let start = first.data.borrow().sourcepos.start.line;
+    let end = last.data.borrow().sourcepos.end.line;
+    let lines: Vec<&str> = source.lines().collect();
+    Ok(lines[start - 1..end.min(lines.len())]
+        .join("\n")
+        .trim_end()
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::transform_with_report;
+    use std::path::PathBuf;
+
+    fn options(directory: &str) -> TransformOptions {
+        TransformOptions {
+            include_dir: Some(PathBuf::from(directory)),
+            ..TransformOptions::default()
+        }
+    }
+
+    fn shared_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "md_ol_util-include-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::create_dir_all(directory.join("common")).unwrap();
+        fs::write(
+            directory.join("common/setup.md"),
+            "# Setup\n\n<!-- ol name=setup -->\n1. Install the tool\n2. Configure it, see (1)<!-- cur-1 -->\n<!-- /ol -->\n",
+        )
+        .unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_include_is_numbered_with_the_enclosing_region() {
+        let directory = shared_directory("numbered");
+        let input = "<!-- ol -->\n- Read the guide\n<!-- ol include=common/setup.md#setup -->\n- Run it, after (cur-1)\n<!-- /ol -->\n";
+        let expected = "<!-- ol -->\n1. Read the guide\n<!-- ol include=common/setup.md#setup -->\n2. Install the tool\n3. Configure it, see (2)<!-- cur-1 -->\n<!-- /include -->\n4. Run it, after (3)\n<!-- /ol -->\n";
+        let options = options(directory.to_str().unwrap());
+        let (output, report) = transform_with_report(input, &options);
+        assert_eq!(output, expected);
+        assert!(report.diagnostics.is_empty());
+        let event = &report.events[0];
+        assert_eq!(
+            (event.pass, event.line, event.message.as_str()),
+            (
+                "includes",
+                3,
+                "spliced in 2 lines of `common/setup.md#setup`"
+            )
+        );
+
+        // Running again replaces the spliced-in steps instead of adding more.
+        let (again, _) = transform_with_report(&output, &options);
+        assert_eq!(again, expected);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_include_errors_are_reported() {
+        let directory = shared_directory("errors");
+        let options = options(directory.to_str().unwrap());
+        for (target, message) in [
+            (
+                "common/setup.md",
+                "invalid value `common/setup.md` for `include`: expected `path#region-name`; leaving it unexpanded",
+            ),
+            (
+                "common/setup.md#teardown",
+                "`common/setup.md` has no region named `teardown`; leaving it unexpanded",
+            ),
+        ] {
+            let input = format!(
+                "<!-- ol -->\n- One\n<!-- ol include={} -->\n<!-- /ol -->\n",
+                target
+            );
+            let (output, report) = transform_with_report(&input, &options);
+            assert!(output.contains(&format!("<!-- ol include={} -->\n<!-- /ol -->", target)));
+            assert_eq!(report.diagnostics[0].line, 3);
+            assert_eq!(report.diagnostics[0].message, message);
+        }
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_include_outside_ol_region_is_rejected() {
+        let directory = shared_directory("outside");
+        let options = options(directory.to_str().unwrap());
+        for (input, line) in [
+            ("Intro.\n\n<!-- ol include=common/setup.md#setup -->\n", 3),
+            (
+                "<!-- ul -->\n- One\n<!-- ol include=common/setup.md#setup -->\n<!-- /ul -->\n",
+                3,
+            ),
+            (
+                "<!-- ol -->\n- One\n<!-- /ol -->\n<!-- ol include=common/setup.md#setup -->\n",
+                4,
+            ),
+        ] {
+            let (output, report) = transform_with_report(input, &options);
+            assert!(!output.contains("Install the tool"), "{}", output);
+            let diagnostic = report.diagnostics.last().unwrap();
+            assert_eq!(diagnostic.code, "include-failed");
+            assert_eq!(diagnostic.line, line);
+            assert_eq!(
+                diagnostic.message,
+                "`include` is outside any `ol` region; leaving it unexpanded"
+            );
+        }
+
+        // Lists outside regions are numbered with `md_ol: all`.
+        let input = "---\nmd_ol: all\n---\n\n<!-- ol include=common/setup.md#setup -->\n";
+        let (output, report) = transform_with_report(input, &options);
+        assert!(output.contains("1. Install the tool"), "{}", output);
+        assert!(report.diagnostics.is_empty());
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
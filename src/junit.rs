@@ -0,0 +1,109 @@
+/// Writes a JUnit XML report with one test case per file, for test report
+/// viewers. `cases` pairs each file's name with the reasons it failed, so a
+/// file with none passes. The first reason becomes the failure's message and
+/// all of them its text.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::to_junit;
+///
+/// let report = to_junit([
+///     ("a.md", vec![]),
+///     ("b.md", vec!["not transformed: line 3 would change".to_string()]),
+/// ]);
+/// assert!(report.contains(r#"<testsuite name="md_ol_util" tests="2" failures="1">"#));
+/// assert!(report.contains(r#"<testcase name="a.md" classname="md_ol_util"/>"#));
+/// ```
+pub fn to_junit<'a>(cases: impl IntoIterator<Item = (&'a str, Vec<String>)>) -> String {
+    let cases: Vec<_> = cases.into_iter().collect();
+    let failures = cases
+        .iter()
+        .filter(|(_, reasons)| !reasons.is_empty())
+        .count();
+    let name = env!("CARGO_PKG_NAME");
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+        name,
+        cases.len(),
+        failures
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+        name,
+        cases.len(),
+        failures
+    ));
+    for (file, reasons) in &cases {
+        let Some(first) = reasons.first() else {
+            xml.push_str(&format!(
+                "    <testcase name=\"{}\" classname=\"{}\"/>\n",
+                escape(file),
+                name
+            ));
+            continue;
+        };
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\">\n",
+            escape(file),
+            name
+        ));
+        xml.push_str(&format!(
+            "      <failure message=\"{}\">{}</failure>\n",
+            escape(first),
+            escape(&reasons.join("\n"))
+        ));
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// Escapes `text` for use in XML text and attribute values, dropping the
+/// control characters XML can't hold.
+fn escape(text: &str) -> String {
+    text.chars()
+        .filter(|&c| !c.is_control() || matches!(c, '\n' | '\t'))
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\n' => "&#10;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_junit_report() {
+        let report = to_junit([
+            ("docs/a.md", vec![]),
+            (
+                "docs/<b>.md",
+                vec![
+                    "not transformed: line 2 would change".to_string(),
+                    "1:1: error: `ol` region has no closing `<!-- /ol -->`".to_string(),
+                ],
+            ),
+        ]);
+        assert_eq!(
+            report,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="md_ol_util" tests="2" failures="1">
+  <testsuite name="md_ol_util" tests="2" failures="1">
+    <testcase name="docs/a.md" classname="md_ol_util"/>
+    <testcase name="docs/&lt;b&gt;.md" classname="md_ol_util">
+      <failure message="not transformed: line 2 would change">not transformed: line 2 would change&#10;1:1: error: `ol` region has no closing `&lt;!-- /ol --&gt;`</failure>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
@@ -1,4 +1,57 @@
+mod anchors;
+mod captions;
+mod counters;
+mod diff;
+mod emit;
+mod extract;
+mod footnotes;
+mod headings;
+mod http;
+mod include;
+mod junit;
+mod line_endings;
+mod lint;
+mod lsp;
+mod marker;
+mod options;
 mod printer;
+mod progress;
+mod project;
+mod publish;
+mod reference;
+mod renumber;
+mod report;
+mod restore;
+mod sarif;
+mod stats;
+mod strip;
+mod table;
 mod transform;
 
-pub use transform::transform;
+pub use diff::unified_diff;
+pub use emit::{render_html, render_text};
+pub use extract::{
+    DataFormat, ExtractedItem, ExtractedReference, ExtractedRegion, extract, generate,
+    regions_from_str, regions_to_string,
+};
+pub use http::serve_http;
+pub use junit::to_junit;
+pub use lint::{Config, LintConfig, lint};
+pub use lsp::serve_language_server;
+pub use marker::{MarkerSyntax, RegionAttributes, RegionKind};
+pub use options::{
+    AnchorStyle, Dedupe, HeadingNumbers, ItemOrder, LineEndings, NumberStyle, ParseOptions,
+    PublishOptions, RenderOptions, TransformOptions, UnclosedRegionPolicy, Wrap,
+};
+pub use printer::MarkdownPrinter;
+pub use project::{Manifest, transform_project};
+pub use publish::publish;
+pub use renumber::{RenumberScope, renumber};
+pub use report::{Diagnostic, Event, RegionInfo, Severity, TransformReport};
+pub use restore::{FilterDirection, filter, restore, restore_with_options};
+pub use sarif::to_sarif;
+pub use stats::{DocumentStats, stats};
+pub use transform::{
+    Transformer, format_range, region_markdown, transform, transform_with_options,
+    transform_with_report,
+};
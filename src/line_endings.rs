@@ -0,0 +1,70 @@
+use crate::diff::common_lines;
+
+/// Gives the lines of `output` that are unchanged from `input` the ending
+/// they had there, and the other lines the ending most lines of `input`
+/// have. `output` is expected to end its lines with `\n`.
+pub(crate) fn preserve_line_endings(input: &str, output: &str) -> String {
+    let input_lines: Vec<(&str, &str)> = input.split_inclusive('\n').map(split_ending).collect();
+    let crlf = input_lines
+        .iter()
+        .filter(|(_, ending)| *ending == "\r\n")
+        .count();
+    let lf = input_lines
+        .iter()
+        .filter(|(_, ending)| *ending == "\n")
+        .count();
+    let usual = if crlf > lf { "\r\n" } else { "\n" };
+
+    let output_lines: Vec<(&str, &str)> = output.split_inclusive('\n').map(split_ending).collect();
+    let mut endings: Vec<&str> = output_lines
+        .iter()
+        .map(|(_, ending)| if ending.is_empty() { "" } else { usual })
+        .collect();
+    let input_text: Vec<&str> = input_lines.iter().map(|(text, _)| *text).collect();
+    let output_text: Vec<&str> = output_lines.iter().map(|(text, _)| *text).collect();
+    for (input_index, output_index) in common_lines(&input_text, &output_text) {
+        let ending = input_lines[input_index].1;
+        if !ending.is_empty() && !endings[output_index].is_empty() {
+            endings[output_index] = ending;
+        }
+    }
+
+    let mut preserved = String::with_capacity(output.len() + crlf);
+    for ((text, _), ending) in output_lines.iter().zip(endings) {
+        preserved.push_str(text);
+        preserved.push_str(ending);
+    }
+    preserved
+}
+
+/// Splits a line into its text and its ending.
+fn split_ending(line: &str) -> (&str, &str) {
+    match line.strip_suffix("\r\n") {
+        Some(text) => (text, "\r\n"),
+        None => match line.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (line, ""),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::{LineEndings, TransformOptions};
+    use crate::transform::transform_with_options;
+
+    #[test]
+    fn test_preserved_line_endings() {
+        let options = TransformOptions {
+            line_endings: LineEndings::Preserve,
+            ..TransformOptions::default()
+        };
+        let input =
+            "Intro\r\n\r\n<!-- ol -->\r\n- A\n- B (cur-1)\r\n<!-- /ol -->\n\nOther\ntext\r\n";
+        assert_eq!(
+            transform_with_options(input, &options),
+            "Intro\r\n\r\n<!-- ol -->\r\n1. A\r\n2. B (1)\r\n<!-- /ol -->\n\nOther\ntext\r\n"
+        );
+        assert_eq!(transform_with_options("- A\n- B", &options), "- A\n- B\n");
+    }
+}
@@ -0,0 +1,562 @@
+use crate::extract::{DataFormat, parse_data};
+use crate::options::TransformOptions;
+use crate::reference::{ItemNumbers, REFERENCE_LIKE, Reference};
+use crate::report::{Diagnostic, TransformReport};
+use crate::transform::{converts_whole_file, parse, regions, transform_with_report};
+use comrak::Arena;
+use comrak::nodes::{AstNode, ListDelimType, ListType, NodeList, NodeValue};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashSet;
+
+/// The longest an item may be by default, in characters.
+const DEFAULT_ITEM_LENGTH: usize = 200;
+
+/// Which rules [`lint`] checks. Every rule is on by default.
+///
+/// A config file sets rules under a `rules` key, by the names the
+/// diagnostics carry; rules it leaves out keep their defaults:
+///
+/// ```yaml
+/// rules:
+///   unnamed-regions: false
+///   item-length: 120
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LintConfig {
+    /// `inconsistent-bullets`: bullet lists should use the same bullet
+    /// character as the first bullet list of the document.
+    pub inconsistent_bullets: bool,
+    /// `mixed-delimiters`: ordered lists should use the same delimiter, `.`
+    /// or `)`, as the first ordered list of the document.
+    pub mixed_delimiters: bool,
+    /// `non-sequential-numbers`: ordered lists outside regions should be
+    /// numbered one after another, or all with the same number.
+    pub non_sequential_numbers: bool,
+    /// `item-length`: the most characters an item's text may have, not
+    /// counting nested lists; `None` turns the rule off. In a config file,
+    /// `true` means the default of 200 and `false` turns it off.
+    #[serde(deserialize_with = "item_length")]
+    pub item_length: Option<usize>,
+    /// `unnamed-regions`: every region should have a `name` attribute.
+    pub unnamed_regions: bool,
+    /// `bad-references`: `(cur±N)` and `(item:N)` expressions should be
+    /// spelled correctly, be inside a region, and point at an item of their
+    /// list.
+    pub bad_references: bool,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            inconsistent_bullets: true,
+            mixed_delimiters: true,
+            non_sequential_numbers: true,
+            item_length: Some(DEFAULT_ITEM_LENGTH),
+            unnamed_regions: true,
+            bad_references: true,
+        }
+    }
+}
+
+/// The settings of a config file such as `.md_ol_util.yml`: the lint rules
+/// under `rules`, and the house style of the output.
+///
+/// ```yaml
+/// bullet: "*"
+/// rules:
+///   unnamed-regions: false
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The rules [`lint`] checks.
+    pub rules: LintConfig,
+    /// The marker every bullet list is written with; see
+    /// [`RenderOptions::bullet`](crate::RenderOptions::bullet).
+    pub bullet: Option<char>,
+}
+
+impl Config {
+    /// Reads a config file in the shape shown above, from JSON or YAML.
+    pub fn from_str(text: &str, format: DataFormat) -> Result<Self, String> {
+        let Some(value) = parse_data(text, format)? else {
+            return Ok(Self::default());
+        };
+        let config: Self = serde_json::from_value(value).map_err(|error| error.to_string())?;
+        if let Some(bullet) = config.bullet
+            && !matches!(bullet, '-' | '*' | '+')
+        {
+            return Err(format!(
+                "invalid value `{}` for `bullet`: expected `-`, `*` or `+`",
+                bullet
+            ));
+        }
+        Ok(config)
+    }
+}
+
+fn item_length<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Setting {
+        Enabled(bool),
+        Max(usize),
+    }
+    Ok(match Setting::deserialize(deserializer)? {
+        Setting::Enabled(true) => Some(DEFAULT_ITEM_LENGTH),
+        Setting::Enabled(false) => None,
+        Setting::Max(max) => Some(max),
+    })
+}
+
+impl LintConfig {
+    /// Reads the rules of a config file in the shape shown above, from JSON
+    /// or YAML.
+    pub fn from_str(text: &str, format: DataFormat) -> Result<Self, String> {
+        Config::from_str(text, format).map(|config| config.rules)
+    }
+}
+
+/// Checks `input` for list style problems, returning a warning for each,
+/// along with the diagnostics transforming it would give. Each warning
+/// names the rule that found it in [`Diagnostic::rule`].
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{LintConfig, TransformOptions, lint};
+///
+/// let input = "- One\n- Two\n\n* Three\n";
+/// let diagnostics = lint(input, &TransformOptions::default(), &LintConfig::default());
+/// assert_eq!(diagnostics.len(), 1);
+/// assert_eq!(diagnostics[0].rule, Some("inconsistent-bullets"));
+/// assert_eq!((diagnostics[0].line, diagnostics[0].column), (4, 1));
+/// ```
+pub fn lint(input: &str, options: &TransformOptions, config: &LintConfig) -> Vec<Diagnostic> {
+    let (_, mut report) = transform_with_report(input, options);
+
+    let arena = Arena::new();
+    // The transform has reported a document nested too deeply to check.
+    let Ok(root) = parse(&arena, input, &options.parse) else {
+        return report.diagnostics;
+    };
+    let convert_all = converts_whole_file(root);
+    let lines: Vec<&str> = input.lines().collect();
+
+    let mut in_regions = HashSet::new();
+    let mut checked = HashSet::new();
+    for parent in root.descendants() {
+        for region in regions(
+            parent,
+            &options.marker_syntaxes,
+            convert_all,
+            &mut TransformReport::default(),
+        ) {
+            in_regions.extend(region.lists.iter().map(|list| std::ptr::from_ref(*list)));
+            if config.bad_references {
+                for list in &region.lists {
+                    check_references(list, &region.id, &mut checked, &mut report);
+                }
+            }
+            if let Some(position) = region.position
+                && config.unnamed_regions
+                && region.attributes.name.is_none()
+                && !region.attributes.split
+            {
+                report
+                    .warn(
+                        "unnamed-regions",
+                        position.line,
+                        position.column,
+                        "region has no `name` attribute".to_string(),
+                    )
+                    .rule = Some("unnamed-regions");
+            }
+        }
+    }
+
+    if config.bad_references {
+        for node in root.descendants() {
+            if let NodeValue::Text(text) = &node.data.borrow().value
+                && !checked.contains(&std::ptr::from_ref(node))
+            {
+                for expression in REFERENCE_LIKE.find_iter(text) {
+                    let (line, column) = text_position(node, expression.start());
+                    report
+                        .warn(
+                            "bad-references",
+                            line,
+                            column,
+                            format!(
+                                "`{}` is outside any region and is left as it is",
+                                expression.as_str()
+                            ),
+                        )
+                        .rule = Some("bad-references");
+                }
+            }
+        }
+    }
+
+    let mut first_bullet = None;
+    let mut first_delimiter = None;
+    for node in root.descendants() {
+        let NodeValue::List(list) = node.data.borrow().value else {
+            continue;
+        };
+        let start = node.data.borrow().sourcepos.start;
+        match list.list_type {
+            ListType::Bullet => {
+                let first = *first_bullet.get_or_insert(list.bullet_char);
+                if config.inconsistent_bullets && list.bullet_char != first {
+                    report
+                        .warn(
+                            "inconsistent-bullets",
+                            start.line,
+                            start.column,
+                            format!(
+                                "list uses `{}` bullets; the first list of the document uses `{}`",
+                                list.bullet_char as char, first as char
+                            ),
+                        )
+                        .rule = Some("inconsistent-bullets");
+                }
+            }
+            ListType::Ordered => {
+                let first = *first_delimiter.get_or_insert(list.delimiter);
+                if config.mixed_delimiters && list.delimiter != first {
+                    report
+                        .warn(
+                            "mixed-delimiters",
+                            start.line,
+                            start.column,
+                            format!(
+                                "list uses `{}` after numbers; the first ordered list of the document uses `{}`",
+                                delimiter(list.delimiter),
+                                delimiter(first)
+                            ),
+                        )
+                        .rule = Some("mixed-delimiters");
+                }
+                let in_region = node
+                    .ancestors()
+                    .any(|ancestor| in_regions.contains(&std::ptr::from_ref(ancestor)));
+                if config.non_sequential_numbers && !in_region {
+                    check_numbers(node, &list, &lines, &mut report);
+                }
+            }
+        }
+    }
+
+    if let Some(max) = config.item_length {
+        for node in root.descendants() {
+            if !matches!(
+                node.data.borrow().value,
+                NodeValue::Item(_) | NodeValue::TaskItem(_)
+            ) {
+                continue;
+            }
+            let length = item_text(node).chars().count();
+            if length > max {
+                let start = node.data.borrow().sourcepos.start;
+                report
+                    .warn(
+                        "item-length",
+                        start.line,
+                        start.column,
+                        format!(
+                            "item is {} characters long, over the limit of {}",
+                            length, max
+                        ),
+                    )
+                    .rule = Some("item-length");
+            }
+        }
+    }
+
+    let mut diagnostics = report.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    diagnostics
+}
+
+fn delimiter(delimiter: ListDelimType) -> char {
+    match delimiter {
+        ListDelimType::Period => '.',
+        ListDelimType::Paren => ')',
+    }
+}
+
+/// Warns about the first item of `list` whose number, as written in the
+/// source, doesn't follow the one before it. Lists numbered with the same
+/// number throughout are fine.
+fn check_numbers<'a>(
+    list: &'a AstNode<'a>,
+    data: &NodeList,
+    lines: &[&str],
+    report: &mut TransformReport,
+) {
+    let numbers: Vec<_> = list
+        .children()
+        .map(|item| {
+            let start = item.data.borrow().sourcepos.start;
+            let number = lines
+                .get(start.line.wrapping_sub(1))
+                .and_then(|line| line.get(start.column.saturating_sub(1)..))
+                .map(|rest| {
+                    rest.chars()
+                        .take_while(char::is_ascii_digit)
+                        .collect::<String>()
+                })
+                .and_then(|digits| digits.parse::<usize>().ok());
+            (start, number)
+        })
+        .collect();
+    if numbers.iter().all(|(_, number)| *number == numbers[0].1) {
+        return;
+    }
+    for (index, (start, number)) in numbers.iter().enumerate() {
+        let expected = data.start + index;
+        if let Some(number) = number
+            && *number != expected
+        {
+            report
+                .warn(
+                    "non-sequential-numbers",
+                    start.line,
+                    start.column,
+                    format!("item is numbered {}; expected {}", number, expected),
+                )
+                .rule = Some("non-sequential-numbers");
+            return;
+        }
+    }
+}
+
+/// Warns about misspelled reference expressions in the items of `list`, and
+/// about those pointing outside it, adding the nodes it looked at to
+/// `checked`.
+fn check_references<'a>(
+    list: &'a AstNode<'a>,
+    region: &Option<String>,
+    checked: &mut HashSet<*const AstNode<'a>>,
+    report: &mut TransformReport,
+) {
+    let len = list.children().count();
+    for (index, item) in list.children().enumerate() {
+        let numbers = ItemNumbers::new(0, index, len);
+        for node in item.descendants() {
+            if !checked.insert(std::ptr::from_ref(node)) {
+                continue;
+            }
+            let NodeValue::Text(text) = &node.data.borrow().value else {
+                continue;
+            };
+            for expression in REFERENCE_LIKE.find_iter(text) {
+                let message = match Reference::parse_expression(expression.as_str())
+                    .map(|reference| reference.resolve(numbers))
+                {
+                    Some(Some(target)) if numbers.contains(target) => continue,
+                    Some(_) => format!(
+                        "`{}` in item {} points outside its list of {} items",
+                        expression.as_str(),
+                        index + 1,
+                        len
+                    ),
+                    None => format!(
+                        "`{}` is not a reference; write `(cur+N)`, `(cur-N)`, `(item:N)` or `(item:last)` without spaces",
+                        expression.as_str()
+                    ),
+                };
+                let (line, column) = text_position(node, expression.start());
+                let diagnostic = report.warn("bad-references", line, column, message);
+                diagnostic.region = region.clone();
+                diagnostic.rule = Some("bad-references");
+            }
+        }
+    }
+}
+
+/// Returns the line and column of the byte at `offset` in the text of
+/// `node`, which never spans lines.
+fn text_position<'a>(node: &'a AstNode<'a>, offset: usize) -> (usize, usize) {
+    let start = node.data.borrow().sourcepos.start;
+    (start.line, start.column + offset)
+}
+
+/// Returns the text of `item`, leaving out nested lists.
+fn item_text<'a>(item: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+    for block in item.children() {
+        if matches!(block.data.borrow().value, NodeValue::List(_)) {
+            continue;
+        }
+        for node in block.descendants() {
+            match &node.data.borrow().value {
+                NodeValue::Text(literal) => text.push_str(literal),
+                NodeValue::Code(code) => text.push_str(&code.literal),
+                NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+                _ => {}
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(input: &str, config: &LintConfig) -> Vec<(usize, Option<&'static str>)> {
+        lint(input, &TransformOptions::default(), config)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.rule))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_rules() {
+        let input = r#"- One
+- Two
+
+* Three
+
+1. First
+3. Second
+
+1) Again
+1) Again
+
+<!-- ol -->
+- Unnamed
+<!-- /ol -->
+
+<!-- ol name=setup -->
+1. Renumbered anyway
+5. By the transform
+<!-- /ol -->
+"#;
+        assert_eq!(
+            rules(input, &LintConfig::default()),
+            vec![
+                (4, Some("inconsistent-bullets")),
+                (7, Some("non-sequential-numbers")),
+                (9, Some("mixed-delimiters")),
+                (12, Some("unnamed-regions")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bad_references() {
+        let input = r#"Outside (cur-1)
+
+<!-- ol name=steps -->
+- One
+- Two, after (cur-1) and (cur + 1)
+- Three, before (cur+1) and (item:4), like (item:last)
+<!-- /ol -->
+"#;
+        let diagnostics = lint(input, &TransformOptions::default(), &LintConfig::default());
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.line,
+                    diagnostic.column,
+                    diagnostic.message.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, 9, "`(cur-1)` is outside any region and is left as it is"),
+                (
+                    5,
+                    26,
+                    "`(cur + 1)` is not a reference; write `(cur+N)`, `(cur-N)`, `(item:N)` or `(item:last)` without spaces"
+                ),
+                (
+                    6,
+                    17,
+                    "`(cur+1)` in item 3 points outside its list of 3 items"
+                ),
+                (
+                    6,
+                    29,
+                    "`(item:4)` in item 3 points outside its list of 3 items"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_item_length() {
+        let input = "- Short\n- A much longer item\n  - Nested\n";
+        let config = LintConfig {
+            item_length: Some(10),
+            ..LintConfig::default()
+        };
+        assert_eq!(rules(input, &config), vec![(2, Some("item-length"))]);
+    }
+
+    #[test]
+    fn test_transform_diagnostics_are_included() {
+        let input = "<!-- ol name=steps -->\n- One\n";
+        let diagnostics = lint(input, &TransformOptions::default(), &LintConfig::default());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].rule, None);
+    }
+
+    #[test]
+    fn test_config_from_str() {
+        let yaml = "rules:\n  unnamed-regions: false\n  item-length: 80\n";
+        assert_eq!(
+            LintConfig::from_str(yaml, DataFormat::Yaml),
+            Ok(LintConfig {
+                unnamed_regions: false,
+                item_length: Some(80),
+                ..LintConfig::default()
+            })
+        );
+
+        let json = r#"{"rules": {"item-length": false, "mixed-delimiters": false}}"#;
+        assert_eq!(
+            LintConfig::from_str(json, DataFormat::Json),
+            Ok(LintConfig {
+                item_length: None,
+                mixed_delimiters: false,
+                ..LintConfig::default()
+            })
+        );
+
+        assert_eq!(
+            LintConfig::from_str("", DataFormat::Yaml),
+            Ok(LintConfig::default())
+        );
+        assert!(LintConfig::from_str("rules:\n  no-such-rule: true\n", DataFormat::Yaml).is_err());
+
+        assert_eq!(
+            Config::from_str(
+                "bullet: \"*\"\nrules:\n  item-length: 80\n",
+                DataFormat::Yaml
+            ),
+            Ok(Config {
+                rules: LintConfig {
+                    item_length: Some(80),
+                    ..LintConfig::default()
+                },
+                bullet: Some('*'),
+            })
+        );
+        assert_eq!(
+            LintConfig::from_str("bullet: \"-\"\n", DataFormat::Yaml),
+            Ok(LintConfig::default())
+        );
+        assert_eq!(
+            Config::from_str(r#"{"bullet": "x"}"#, DataFormat::Json),
+            Err("invalid value `x` for `bullet`: expected `-`, `*` or `+`".to_string())
+        );
+    }
+}
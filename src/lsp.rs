@@ -0,0 +1,574 @@
+use crate::lint::{LintConfig, lint};
+use crate::marker::MarkerSyntax;
+use crate::options::TransformOptions;
+use crate::report::{Diagnostic, Severity, TransformReport};
+use crate::transform::{converts_whole_file, format_range, parse, regions};
+use comrak::Arena;
+use comrak::nodes::NodeValue;
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// `TextDocumentSyncKind.Full`: clients send the whole document on every
+/// change.
+const FULL_SYNC: u8 = 1;
+
+/// The JSON-RPC error code for requests the server doesn't handle.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The JSON-RPC error code for messages that aren't valid JSON.
+const PARSE_ERROR: i64 = -32700;
+
+/// Runs a minimal language server on `input` and `output`, speaking the
+/// Language Server Protocol until the client sends `exit` or closes
+/// `input`.
+///
+/// The server publishes the transform's diagnostics and those of the
+/// `bad-references` lint rule for open documents, formats documents and
+/// ranges with the transform, and offers a code action wrapping the
+/// selected lists in `<!-- ol -->` markers. Documents are transformed with
+/// `options`, except that the includes of documents with `file:` URIs are
+/// read relative to the document's directory. Messages that aren't valid
+/// JSON are answered with a parse error.
+pub fn serve_language_server(
+    input: impl BufRead,
+    output: impl Write,
+    options: &TransformOptions,
+) -> io::Result<()> {
+    Server {
+        output,
+        options,
+        documents: HashMap::new(),
+    }
+    .run(input)
+}
+
+struct Server<'o, W> {
+    output: W,
+    options: &'o TransformOptions,
+    /// The text of each open document, by URI.
+    documents: HashMap<String, String>,
+}
+
+impl<W: Write> Server<'_, W> {
+    fn run(&mut self, mut input: impl BufRead) -> io::Result<()> {
+        while let Some(message) = read_message(&mut input)? {
+            let message = match message {
+                Ok(message) => message,
+                Err(error) => {
+                    self.send(json!({
+                        "jsonrpc": "2.0",
+                        "id": null,
+                        "error": { "code": PARSE_ERROR, "message": error.to_string() },
+                    }))?;
+                    continue;
+                }
+            };
+            let method = message["method"].as_str().unwrap_or_default();
+            let params = &message["params"];
+            let result = match method {
+                "initialize" => Some(json!({
+                    "capabilities": {
+                        "textDocumentSync": FULL_SYNC,
+                        "documentFormattingProvider": true,
+                        "documentRangeFormattingProvider": true,
+                        "codeActionProvider": true,
+                    },
+                    "serverInfo": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                })),
+                "shutdown" => Some(Value::Null),
+                "exit" => return Ok(()),
+                "textDocument/didOpen" => {
+                    let document = &params["textDocument"];
+                    self.update(&document["uri"], &document["text"])?;
+                    None
+                }
+                "textDocument/didChange" => {
+                    let changes = params["contentChanges"].as_array();
+                    if let Some(change) = changes.and_then(|changes| changes.last()) {
+                        self.update(&params["textDocument"]["uri"], &change["text"])?;
+                    }
+                    None
+                }
+                "textDocument/didClose" => {
+                    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                    self.documents.remove(uri);
+                    self.notify(
+                        "textDocument/publishDiagnostics",
+                        json!({ "uri": uri, "diagnostics": [] }),
+                    )?;
+                    None
+                }
+                "textDocument/formatting" => Some(self.format(params, None)),
+                "textDocument/rangeFormatting" => Some(self.format(params, Some(&params["range"]))),
+                "textDocument/codeAction" => Some(self.code_actions(params)),
+                _ => {
+                    if !message["id"].is_null() {
+                        self.send(json!({
+                            "jsonrpc": "2.0",
+                            "id": message["id"],
+                            "error": {
+                                "code": METHOD_NOT_FOUND,
+                                "message": format!("unsupported method `{}`", method),
+                            },
+                        }))?;
+                    }
+                    None
+                }
+            };
+            if let Some(result) = result
+                && !message["id"].is_null()
+            {
+                self.send(json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the new text of a document and publishes its diagnostics.
+    fn update(&mut self, uri: &Value, text: &Value) -> io::Result<()> {
+        let (Some(uri), Some(text)) = (uri.as_str(), text.as_str()) else {
+            return Ok(());
+        };
+        let config = LintConfig {
+            inconsistent_bullets: false,
+            mixed_delimiters: false,
+            non_sequential_numbers: false,
+            item_length: None,
+            unnamed_regions: false,
+            bad_references: true,
+        };
+        let lines: Vec<&str> = text.lines().collect();
+        let diagnostics: Vec<_> = lint(text, &self.options_for(uri), &config)
+            .iter()
+            .map(|diagnostic| lsp_diagnostic(diagnostic, &lines))
+            .collect();
+        self.documents.insert(uri.to_string(), text.to_string());
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    /// Returns the edits formatting a document, or the lines `range`
+    /// touches. A range is transformed on its own, so it should include the
+    /// markers of the regions in it.
+    fn format(&self, params: &Value, range: Option<&Value>) -> Value {
+        let Some(text) = self.document(params) else {
+            return Value::Null;
+        };
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let options = self.options_for(uri);
+        let lines: Vec<&str> = text.split_inclusive('\n').collect();
+        let (first, last) = match range {
+            Some(range) => {
+                let first = line_number(&range["start"]);
+                let end = &range["end"];
+                // A range ending at the start of a line doesn't touch it.
+                let last = match line_number(end) {
+                    line if line > first && end["character"].as_u64() == Some(0) => line - 1,
+                    line => line,
+                };
+                (first.min(lines.len()), (last + 1).min(lines.len()))
+            }
+            None => (0, lines.len()),
+        };
+        let offset = |line: usize| lines[..line].iter().map(|line| line.len()).sum::<usize>();
+        let (range, formatted) = format_range(text, offset(first)..offset(last), &options);
+        if formatted == text[range.clone()] {
+            return json!([]);
+        }
+        let first = text[..range.start].matches('\n').count();
+        let last = first + text[range].split_inclusive('\n').count();
+        json!([{
+            "range": {
+                "start": { "line": first, "character": 0 },
+                "end": end_position(&lines, last),
+            },
+            "newText": formatted,
+        }])
+    }
+
+    /// Offers to wrap the lists the selection touches in `<!-- ol -->`
+    /// markers, if they aren't in a region already.
+    fn code_actions(&self, params: &Value) -> Value {
+        let Some(text) = self.document(params) else {
+            return json!([]);
+        };
+        let range = &params["range"];
+        let (first, last) = (
+            line_number(&range["start"]) + 1,
+            line_number(&range["end"]) + 1,
+        );
+
+        let arena = Arena::new();
+        let Ok(root) = parse(&arena, text, &self.options.parse) else {
+            return json!([]);
+        };
+        let in_regions: HashSet<_> = regions(
+            root,
+            &self.options.marker_syntaxes,
+            converts_whole_file(root),
+            &mut TransformReport::default(),
+        )
+        .into_iter()
+        .flat_map(|region| region.lists)
+        .map(std::ptr::from_ref)
+        .collect();
+        let lists: Vec<_> = root
+            .children()
+            .filter(|node| matches!(node.data.borrow().value, NodeValue::List(_)))
+            .filter(|node| !in_regions.contains(&std::ptr::from_ref(*node)))
+            .map(|node| {
+                let sourcepos = node.data.borrow().sourcepos;
+                (sourcepos.start.line, sourcepos.end.line)
+            })
+            .filter(|(start, end)| *start <= last && first <= *end)
+            .collect();
+        let (Some((start, _)), Some((_, end))) = (lists.first(), lists.last()) else {
+            return json!([]);
+        };
+
+        let syntax = self
+            .options
+            .marker_syntaxes
+            .first()
+            .copied()
+            .unwrap_or(MarkerSyntax::HtmlComment);
+        // An MDX marker right after a list would become part of its last item.
+        let close = match syntax {
+            MarkerSyntax::HtmlComment => syntax.wrap("/ol"),
+            MarkerSyntax::MdxComment => format!("\n{}", syntax.wrap("/ol")),
+        };
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let insert = |line: usize, text: String| {
+            json!({
+                "range": {
+                    "start": { "line": line, "character": 0 },
+                    "end": { "line": line, "character": 0 },
+                },
+                "newText": text + "\n",
+            })
+        };
+        let mut closing = insert(*end, close.clone());
+        if *end >= text.lines().count() && !text.ends_with('\n') {
+            // The list ends the document without a final newline.
+            let lines: Vec<&str> = text.split_inclusive('\n').collect();
+            closing["range"]["start"] = end_position(&lines, lines.len());
+            closing["range"]["end"] = closing["range"]["start"].clone();
+            closing["newText"] = Value::String(format!("\n{}\n", close));
+        }
+        json!([{
+            "title": "Wrap in <!-- ol --> markers",
+            "kind": "refactor.rewrite",
+            "edit": {
+                "changes": {
+                    uri: [insert(start - 1, syntax.wrap("ol")), closing],
+                },
+            },
+        }])
+    }
+
+    /// Returns the options a document is transformed with: the server's,
+    /// with includes read relative to the document's directory.
+    fn options_for(&self, uri: &str) -> TransformOptions {
+        let directory = file_path(uri).and_then(|path| path.parent().map(Path::to_path_buf));
+        TransformOptions {
+            include_dir: directory.or_else(|| self.options.include_dir.clone()),
+            ..self.options.clone()
+        }
+    }
+
+    fn document(&self, params: &Value) -> Option<&str> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        self.documents.get(uri).map(String::as_str)
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn send(&mut self, message: Value) -> io::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+}
+
+/// Reads one message, or `None` at the end of `input`. A message that
+/// isn't valid JSON is returned as the error parsing it.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<serde_json::Result<Value>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; length.unwrap_or_default()];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)))
+}
+
+/// Returns the path of a `file:` URI, decoding its percent-escapes.
+fn file_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?.as_bytes();
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut index = 0;
+    while index < path.len() {
+        let escaped = (path[index] == b'%')
+            .then(|| path.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(path[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+fn line_number(position: &Value) -> usize {
+    position["line"].as_u64().unwrap_or_default() as usize
+}
+
+/// Returns the position just past the first `count` of `lines`, which keep
+/// their line endings.
+fn end_position(lines: &[&str], count: usize) -> Value {
+    match lines[..count].last() {
+        Some(last) if !last.ends_with('\n') => {
+            json!({ "line": count - 1, "character": last.encode_utf16().count() })
+        }
+        _ => json!({ "line": count, "character": 0 }),
+    }
+}
+
+fn lsp_diagnostic(diagnostic: &Diagnostic, lines: &[&str]) -> Value {
+    let line = diagnostic.line.saturating_sub(1);
+    // Columns count bytes; LSP counts UTF-16 code units.
+    let character = lines.get(line).map_or(0, |text| {
+        text.char_indices()
+            .take_while(|(index, _)| *index + 1 < diagnostic.column)
+            .map(|(_, char)| char.len_utf16())
+            .sum::<usize>()
+    });
+    let position = json!({ "line": line, "character": character });
+    json!({
+        "range": { "start": position, "end": position },
+        "severity": match diagnostic.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        },
+        "source": env!("CARGO_PKG_NAME"),
+        "message": diagnostic.message,
+        "code": diagnostic.code,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends `messages` to a server and returns what it wrote back.
+    fn exchange(messages: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for message in messages {
+            let body = message.to_string();
+            write!(input, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        }
+        let mut output = Vec::new();
+        serve_language_server(&input[..], &mut output, &TransformOptions::default()).unwrap();
+
+        let mut output = &output[..];
+        let mut responses = Vec::new();
+        while let Some(response) = read_message(&mut output).unwrap() {
+            responses.push(response.unwrap());
+        }
+        responses
+    }
+
+    fn open(text: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": { "uri": "file:///a.md", "languageId": "markdown", "version": 1, "text": text },
+            },
+        })
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn range(start: u64, end: u64) -> Value {
+        json!({
+            "start": { "line": start, "character": 0 },
+            "end": { "line": end, "character": 0 },
+        })
+    }
+
+    #[test]
+    fn test_initialize_and_shutdown() {
+        let responses = exchange(&[
+            request(1, "initialize", json!({ "capabilities": {} })),
+            request(2, "shutdown", Value::Null),
+            request(3, "textDocument/hover", json!({})),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+            request(4, "shutdown", Value::Null),
+        ]);
+        assert_eq!(responses.len(), 3);
+        assert_eq!(
+            responses[0]["result"]["capabilities"]["documentFormattingProvider"],
+            true
+        );
+        assert_eq!(
+            responses[1],
+            json!({ "jsonrpc": "2.0", "id": 2, "result": null })
+        );
+        assert_eq!(responses[2]["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_invalid_json_is_answered_with_parse_error() {
+        let mut input = b"Content-Length: 5\r\n\r\n{oops".to_vec();
+        let body = request(1, "shutdown", Value::Null).to_string();
+        write!(input, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        let mut output = Vec::new();
+        serve_language_server(&input[..], &mut output, &TransformOptions::default()).unwrap();
+
+        let mut output = &output[..];
+        let error = read_message(&mut output).unwrap().unwrap().unwrap();
+        assert_eq!(error["error"]["code"], PARSE_ERROR);
+        assert_eq!(error["id"], Value::Null);
+        let response = read_message(&mut output).unwrap().unwrap().unwrap();
+        assert_eq!(response["id"], 1);
+    }
+
+    #[test]
+    fn test_options_for_document() {
+        let server = Server {
+            output: Vec::new(),
+            options: &TransformOptions::default(),
+            documents: HashMap::new(),
+        };
+        assert_eq!(
+            server
+                .options_for("file:///docs/My%20Guide/a.md")
+                .include_dir,
+            Some(PathBuf::from("/docs/My Guide"))
+        );
+        assert_eq!(server.options_for("untitled:1").include_dir, None);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let responses = exchange(&[open("<!-- ol -->\n- One, after (cur-1)\n- Two\n")]);
+        assert_eq!(responses[0]["method"], "textDocument/publishDiagnostics");
+        let diagnostics = &responses[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 2);
+        assert_eq!(
+            diagnostics[0]["range"]["start"],
+            json!({ "line": 0, "character": 0 })
+        );
+        assert_eq!(diagnostics[1]["code"], "bad-references");
+        assert_eq!(
+            diagnostics[1]["range"]["start"],
+            json!({ "line": 1, "character": 13 })
+        );
+    }
+
+    #[test]
+    fn test_formatting() {
+        let text = "Intro\n\n<!-- ol -->\n- One\n- Two\n<!-- /ol -->\n";
+        let document = json!({ "uri": "file:///a.md" });
+        let responses = exchange(&[
+            open(text),
+            request(
+                1,
+                "textDocument/formatting",
+                json!({ "textDocument": document }),
+            ),
+            request(
+                2,
+                "textDocument/rangeFormatting",
+                json!({ "textDocument": document, "range": range(2, 6) }),
+            ),
+            request(
+                3,
+                "textDocument/rangeFormatting",
+                json!({ "textDocument": document, "range": range(0, 1) }),
+            ),
+        ]);
+        assert_eq!(
+            responses[1]["result"],
+            json!([{
+                "range": range(0, 6),
+                "newText": "Intro\n\n<!-- ol -->\n1. One\n2. Two\n<!-- /ol -->\n",
+            }])
+        );
+        assert_eq!(
+            responses[2]["result"],
+            json!([{
+                "range": range(2, 6),
+                "newText": "<!-- ol -->\n1. One\n2. Two\n<!-- /ol -->\n",
+            }])
+        );
+        assert_eq!(responses[3]["result"], json!([]));
+    }
+
+    #[test]
+    fn test_wrap_code_action() {
+        let text = "<!-- ol -->\n- In a region\n<!-- /ol -->\n\n- One\n- Two";
+        let document = json!({ "uri": "file:///a.md" });
+        let responses = exchange(&[
+            open(text),
+            request(
+                1,
+                "textDocument/codeAction",
+                json!({ "textDocument": document, "range": range(5, 5), "context": { "diagnostics": [] } }),
+            ),
+            request(
+                2,
+                "textDocument/codeAction",
+                json!({ "textDocument": document, "range": range(1, 1), "context": { "diagnostics": [] } }),
+            ),
+        ]);
+        let edits = &responses[1]["result"][0]["edit"]["changes"]["file:///a.md"];
+        assert_eq!(edits[0]["range"], range(4, 4));
+        assert_eq!(edits[0]["newText"], "<!-- ol -->\n");
+        assert_eq!(
+            edits[1]["range"]["start"],
+            json!({ "line": 5, "character": 5 })
+        );
+        assert_eq!(edits[1]["newText"], "\n<!-- /ol -->\n");
+        assert_eq!(responses[2]["result"], json!([]));
+    }
+}
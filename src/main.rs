@@ -1,7 +1,26 @@
-use clap::Parser;
-use md_ol_util::transform;
+use clap::builder::FalseyValueParser;
+use clap::{Parser, Subcommand, ValueEnum};
+use md_ol_util::{
+    AnchorStyle, Config, DataFormat, Diagnostic, DocumentStats, FilterDirection, HeadingNumbers,
+    LineEndings, LintConfig, Manifest, MarkerSyntax, NumberStyle, ParseOptions, PublishOptions,
+    RenderOptions, RenumberScope, Severity, TransformOptions, TransformReport,
+    UnclosedRegionPolicy, Wrap, extract, filter, format_range, generate, lint, publish,
+    region_markdown, regions_from_str, regions_to_string, render_html, render_text, renumber,
+    serve_http, serve_language_server, stats, to_junit, to_sarif, transform_project,
+    transform_with_report, unified_diff,
+};
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::io::{self, Read};
+use std::net::TcpListener;
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/// The config file used when `--config` isn't given, if it exists.
+const DEFAULT_CONFIG: &str = ".md_ol_util.yml";
 
 #[derive(Parser)]
 #[command(name = "md_ol_util")]
@@ -10,24 +29,1569 @@ use std::io::{self, Read};
 )]
 #[command(version)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(help = "Input markdown file. If not provided, reads from stdin")]
     file: Option<String>,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_ANNOTATE",
+        value_parser = FalseyValueParser::new(),
+        help = "Follow resolved numbers with a comment holding the original expression"
+    )]
+    annotate: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_RESOLVE_INLINE_HTML",
+        value_parser = FalseyValueParser::new(),
+        help = "Also resolve expressions inside inline HTML"
+    )]
+    resolve_inline_html: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_UNCLOSED",
+        value_enum,
+        default_value_t = UnclosedArg::Convert,
+        help = "What to do with a region missing its closing marker"
+    )]
+    unclosed: UnclosedArg,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_NORMALIZE_MARKERS",
+        value_parser = FalseyValueParser::new(),
+        help = "Rewrite magic comments in their canonical spelling"
+    )]
+    normalize_markers: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_MARKERS",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "html",
+        help = "Comment syntaxes recognized as markers"
+    )]
+    markers: Vec<MarkerArg>,
+
+    #[arg(
+        long,
+        help = "List the document's regions instead of printing the output"
+    )]
+    list_regions: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_PAD_TABLES",
+        value_parser = FalseyValueParser::new(),
+        help = "Pad table cells so that columns line up"
+    )]
+    pad_tables: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_AUTOLINKS",
+        value_parser = FalseyValueParser::new(),
+        help = "Recognize bare URLs as links, like GitHub does"
+    )]
+    autolinks: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_WIKILINKS",
+        value_parser = FalseyValueParser::new(),
+        help = "Recognize [[Page Name]] wikilinks"
+    )]
+    wikilinks: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_FOOTNOTES",
+        value_parser = FalseyValueParser::new(),
+        help = "Recognize [^1] footnotes"
+    )]
+    footnotes: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_RENUMBER_FOOTNOTES",
+        value_parser = FalseyValueParser::new(),
+        help = "Renumber [^1] footnotes in order of first reference (implies --footnotes)"
+    )]
+    renumber_footnotes: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_RENUMBER_LINKS",
+        value_parser = FalseyValueParser::new(),
+        help = "Renumber [1]-style reference links in order of appearance and sort their definitions"
+    )]
+    renumber_links: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_TASK_PROGRESS",
+        value_parser = FalseyValueParser::new(),
+        help = "Keep a <!-- progress --> summary under every task list in an ol region"
+    )]
+    task_progress: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_TASKS",
+        value_parser = FalseyValueParser::new(),
+        help = "Turn converted list items into [ ] task items"
+    )]
+    tasks: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_SUPERSCRIPT",
+        value_parser = FalseyValueParser::new(),
+        help = "Recognize ^superscript^ text"
+    )]
+    superscript: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_SUBSCRIPT",
+        value_parser = FalseyValueParser::new(),
+        help = "Recognize ~subscript~ text"
+    )]
+    subscript: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_SPOILERS",
+        value_parser = FalseyValueParser::new(),
+        help = "Recognize ||spoiler|| text"
+    )]
+    spoilers: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_MAX_NESTING",
+        value_name = "DEPTH",
+        default_value_t = 128,
+        help = "Leave documents nested more deeply than this as they are"
+    )]
+    max_nesting: usize,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_SETEXT_HEADINGS",
+        value_parser = FalseyValueParser::new(),
+        help = "Keep setext headings instead of rewriting them as ATX headings"
+    )]
+    setext_headings: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_TAB_WIDTH",
+        default_value_t = 4,
+        help = "Columns per tab when re-indenting tab-indented lists"
+    )]
+    tab_width: usize,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_WRAP",
+        value_name = "WIDTH|preserve",
+        default_value = "preserve",
+        value_parser = parse_wrap,
+        help = "Reflow paragraphs and list items to a maximum line width"
+    )]
+    wrap: Wrap,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_LINE_ENDINGS",
+        value_enum,
+        default_value_t = LineEndingsArg::Lf,
+        help = "How output lines end"
+    )]
+    line_endings: LineEndingsArg,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_PRESERVE_SOFT_BREAKS",
+        value_parser = FalseyValueParser::new(),
+        help = "Keep line breaks inside list items instead of joining their lines"
+    )]
+    preserve_soft_breaks: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_PAD_NUMBERS",
+        value_parser = FalseyValueParser::new(),
+        help = "Right-align list numbers so that markers line up"
+    )]
+    pad_numbers: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_NUMBER_STYLE",
+        value_enum,
+        default_value_t = NumberStyleArg::Sequential,
+        help = "How items of converted lists are numbered"
+    )]
+    number_style: NumberStyleArg,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_JOIN_LISTS",
+        value_parser = FalseyValueParser::new(),
+        help = "Number the lists of a region as one sequence, e.g. around code blocks"
+    )]
+    join_lists: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_NUMBER_HEADINGS",
+        value_parser = FalseyValueParser::new(),
+        help = "Number headings throughout the file, not only after <!-- toc-numbers -->"
+    )]
+    number_headings: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_HEADING_LEVELS",
+        value_name = "FIRST-LAST",
+        default_value = "2-6",
+        value_parser = parse_heading_levels,
+        help = "Heading levels to number"
+    )]
+    heading_levels: RangeInclusive<u8>,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_HEADING_SEPARATOR",
+        value_name = "SEPARATOR",
+        default_value = ".",
+        help = "What goes between the parts of a heading number"
+    )]
+    heading_separator: String,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_STRIP_HEADING_NUMBERS",
+        value_parser = FalseyValueParser::new(),
+        help = "Remove heading numbers instead of adding them"
+    )]
+    strip_heading_numbers: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_NUMBER_CAPTIONS",
+        value_parser = FalseyValueParser::new(),
+        help = "Number `Figure:` and `Table:` captions and resolve (fig:label) references to them"
+    )]
+    number_captions: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_NESTED_INDENT",
+        value_name = "2|3|4",
+        value_parser = clap::value_parser!(u8).range(2..=4),
+        help = "Indent nested lists by this many columns from their parent item's marker"
+    )]
+    nested_indent: Option<u8>,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_FIX_INDENT",
+        value_parser = FalseyValueParser::new(),
+        help = "Indent list markers by nesting level, fixing inconsistent source indentation"
+    )]
+    fix_indent: bool,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_BULLET",
+        value_name = "-|*|+",
+        allow_hyphen_values = true,
+        value_parser = parse_bullet,
+        help = "Write every bullet list with this marker [default: `bullet` of .md_ol_util.yml, else as written]"
+    )]
+    bullet: Option<char>,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_CONTINUE_FROM",
+        value_name = "N",
+        help = "Start `continue` regions with no list before them at N, e.g. where the previous file left off"
+    )]
+    continue_from: Option<usize>,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_ANCHORS",
+        value_enum,
+        help = "Give items of named regions an anchor such as setup-step-3"
+    )]
+    anchors: Option<AnchorArg>,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_STRIP_MARKERS",
+        value_parser = FalseyValueParser::new(),
+        help = "Remove magic comments and labels from the output, for publishing"
+    )]
+    strip_markers: bool,
+
+    #[arg(long, value_enum, default_value_t = EmitArg::Markdown, help = "Output format")]
+    emit: EmitArg,
+
+    #[arg(
+        long,
+        value_name = "NAME|INDEX",
+        conflicts_with = "list_regions",
+        help = "Only output the region with this name or ID, or at this 1-based position"
+    )]
+    region: Option<String>,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_VERIFY_IDEMPOTENT",
+        value_parser = FalseyValueParser::new(),
+        help = "Fail if transforming the output again would change it (for debugging)"
+    )]
+    verify_idempotent: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Run as a git filter driver: smudge resolves and annotates numbers, clean restores (cur±N)"
+    )]
+    filter: Option<FilterArg>,
+
+    #[arg(
+        long,
+        value_name = "START:END",
+        value_parser = parse_byte_range,
+        help = "Only transform the lines the byte range touches, printing them and their range as JSON"
+    )]
+    range_bytes: Option<Range<usize>>,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_DIAGNOSTIC_FORMAT",
+        value_enum,
+        default_value_t = DiagnosticFormat::Short,
+        global = true,
+        help = "How to print diagnostics"
+    )]
+    diagnostic_format: DiagnosticFormat,
+
+    #[arg(
+        short,
+        long,
+        action = clap::ArgAction::Count,
+        global = true,
+        help = "Log what was converted to stderr: -v per file, -vv per region"
+    )]
+    verbose: u8,
+
+    #[arg(
+        long,
+        env = "MD_OL_UTIL_LOG_FORMAT",
+        value_enum,
+        default_value_t = LogFormat::Text,
+        global = true,
+        help = "Format of the -v log"
+    )]
+    log_format: LogFormat,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        global = true,
+        help = "Path of the file read from stdin, used to find includes and the lint config and to name it in diagnostics"
+    )]
+    stdin_filepath: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "VERSION",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "v1",
+        global = true,
+        help = "Print diagnostics, check results, regions and stats as stable tab-separated records for scripts"
+    )]
+    porcelain: Option<PorcelainVersion>,
+
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        help = "Print the results of check, lint or the transform as a report on stdout instead"
+    )]
+    report: Option<ReportArg>,
 }
 
-fn main() -> io::Result<()> {
-    let args = Args::parse();
+impl Args {
+    /// The path of the input: the file argument, or the file that stdin is
+    /// said to hold.
+    fn input_path(&self) -> Option<&str> {
+        self.file.as_deref().or(self.stdin_filepath.as_deref())
+    }
+
+    /// How to print diagnostics: as porcelain records with `--porcelain`,
+    /// and as `--diagnostic-format` says otherwise.
+    fn diagnostic_format(&self) -> DiagnosticFormat {
+        match self.porcelain {
+            Some(_) => DiagnosticFormat::Porcelain,
+            None => self.diagnostic_format,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Renumber ordered lists sequentially, fixing stale step numbers and
+    /// the annotated references to them
+    Renumber {
+        #[arg(help = "Input markdown file. If not provided, reads from stdin")]
+        file: Option<String>,
+
+        #[arg(long, help = "Renumber every ordered list, not only those in regions")]
+        all: bool,
+    },
+    /// Print the items of each region as JSON or YAML, with their numbers,
+    /// nesting depth and resolved references
+    Extract {
+        #[arg(help = "Input markdown file. If not provided, reads from stdin")]
+        file: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = FormatArg::Json, help = "Output format")]
+        format: FormatArg,
+    },
+    /// Write numbered regions from JSON or YAML in the shape `extract` prints
+    Generate {
+        #[arg(help = "Input data file. If not provided, reads from stdin")]
+        file: Option<String>,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Input format [default: yaml for .yaml and .yml files, json otherwise]"
+        )]
+        format: Option<FormatArg>,
+    },
+    /// Count the regions, lists, items, nesting depth and references of each
+    /// file, and their totals
+    Stats {
+        #[arg(help = "Input markdown files. If not provided, reads from stdin")]
+        files: Vec<String>,
+    },
+    /// List the regions of each file with their lines, attributes, and
+    /// list and item counts, without transforming anything
+    Regions {
+        #[arg(help = "Input markdown files. If not provided, reads from stdin")]
+        files: Vec<String>,
+
+        #[arg(long, value_enum, default_value_t = RegionsFormatArg::Table, help = "Output format")]
+        format: RegionsFormatArg,
+    },
+    /// Check that files are already transformed and have no errors, without
+    /// changing them
+    Check {
+        #[arg(help = "Input markdown files. If not provided, reads from stdin")]
+        files: Vec<String>,
+    },
+    /// Check list style: bullet characters, delimiters, numbering outside
+    /// regions, item length and region names
+    Lint {
+        #[arg(help = "Input markdown files. If not provided, reads from stdin")]
+        files: Vec<String>,
+
+        #[arg(
+            long,
+            help = "Rule config file, JSON or YAML [default: the .md_ol_util.yml closest to each file]"
+        )]
+        config: Option<String>,
+    },
+    /// Number the files listed in a JSON or YAML manifest as one document,
+    /// continuing `continue` regions from the files before them
+    Project {
+        #[arg(help = "Manifest listing the files in order under `files`")]
+        manifest: String,
+
+        #[arg(
+            long,
+            help = "Write the output back to the files instead of printing it"
+        )]
+        write: bool,
+    },
+    /// Turn a source file into the form readers see: convert lists, resolve
+    /// references, add item anchors and strip magic comments
+    Publish {
+        #[arg(help = "Input markdown file. If not provided, reads from stdin")]
+        file: Option<String>,
+
+        #[arg(long, help = "Render the published document as HTML")]
+        html: bool,
+    },
+    /// Turn output written with --annotate back into the editable source
+    /// form, failing unless transforming it again gives the same output
+    #[command(visible_alias = "unpublish")]
+    Restore {
+        #[arg(help = "Input markdown file. If not provided, reads from stdin")]
+        file: Option<String>,
+    },
+    /// Run a language server on stdin and stdout, for editors: diagnostics,
+    /// formatting, and wrapping lists in markers
+    Lsp,
+    /// Serve a JSON API over HTTP: POST /transform and POST /analyze
+    Serve {
+        #[arg(long, default_value_t = 8080, help = "Port to listen on")]
+        port: u16,
+
+        #[arg(
+            long,
+            default_value = "127.0.0.1",
+            help = "Address to listen on; 0.0.0.0 accepts connections from other hosts"
+        )]
+        host: String,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum EmitArg {
+    /// The transformed Markdown
+    Markdown,
+    /// The transformed document rendered as HTML
+    Html,
+    /// The transformed document as plain text, keeping list numbers
+    Text,
+    /// A unified diff from the input to the transformed document, for git apply
+    Patch,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FilterArg {
+    /// Restore the (cur±N) source form, for storing in the repository
+    Clean,
+    /// Resolve and annotate numbers, for the working tree
+    Smudge,
+}
 
-    let input = match args.file {
-        Some(file_path) => fs::read_to_string(file_path)?,
+impl From<FilterArg> for FilterDirection {
+    fn from(filter: FilterArg) -> Self {
+        match filter {
+            FilterArg::Clean => FilterDirection::Clean,
+            FilterArg::Smudge => FilterDirection::Smudge,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DiagnosticFormat {
+    /// One line per diagnostic: file:line:column: severity: message
+    Short,
+    /// The line each diagnostic points at, with the problem underlined
+    Pretty,
+    /// One porcelain record per diagnostic, chosen with `--porcelain`
+    #[value(skip)]
+    Porcelain,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ReportArg {
+    /// A SARIF 2.1.0 log, for code scanning dashboards
+    Sarif,
+    /// A JUnit XML report with one test case per file
+    Junit,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PorcelainVersion {
+    /// The format documented under "Porcelain output" in the README
+    V1,
+}
+
+/// The diagnostics in `report` that are errors, as JUnit failure reasons.
+fn errors(report: &TransformReport) -> Vec<String> {
+    report
+        .diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .map(Diagnostic::to_string)
+        .collect()
+}
+
+/// The 1-based line of `old` at which `new` first differs from it.
+fn first_changed_line(old: &str, new: &str) -> usize {
+    let (old_lines, new_lines) = (old.lines().count(), new.lines().count());
+    let same = old
+        .lines()
+        .zip(new.lines())
+        .take_while(|(old, new)| old == new)
+        .count();
+    (same + 1).min(old_lines.max(new_lines).max(1))
+}
+
+/// Formats one porcelain record: `fields` separated by tabs, with any
+/// backslashes, tabs and newlines in them escaped as `\\`, `\t` and `\n`.
+fn porcelain_record(fields: &[&dyn fmt::Display]) -> String {
+    let fields: Vec<_> = fields
+        .iter()
+        .map(|field| {
+            field
+                .to_string()
+                .replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+        })
+        .collect();
+    fields.join("\t") + "\n"
+}
+
+/// Formats a diagnostic found in `source`, read from `name`.
+fn format_diagnostic(
+    format: DiagnosticFormat,
+    name: &str,
+    source: &str,
+    diagnostic: &Diagnostic,
+) -> String {
+    match format {
+        DiagnosticFormat::Short => format!("{}:{}\n", name, diagnostic),
+        DiagnosticFormat::Pretty => format!("{}\n", diagnostic.render(name, source)),
+        DiagnosticFormat::Porcelain => porcelain_record(&[
+            &"diagnostic",
+            &name,
+            &diagnostic.line,
+            &diagnostic.column,
+            &diagnostic.severity,
+            &diagnostic.rule.unwrap_or(""),
+            &diagnostic.region.as_deref().unwrap_or(""),
+            &diagnostic.message,
+        ]),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    /// One human-readable line per message
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Writes the `-v` log to stderr.
+struct Logger {
+    verbosity: u8,
+    format: LogFormat,
+}
+
+impl Logger {
+    /// Logs what the transform of `file` did, and how long it took if known:
+    /// a summary at `-v`, and every region and the decisions made about it at
+    /// `-vv`.
+    fn report(&self, file: &str, report: &TransformReport, elapsed: Option<Duration>) {
+        if self.verbosity >= 1 {
+            let items: usize = report.regions.iter().map(|region| region.items).sum();
+            self.log(
+                "info",
+                &format!(
+                    "{}: {} regions, {} items, {} diagnostics{}",
+                    file,
+                    report.regions.len(),
+                    items,
+                    report.diagnostics.len(),
+                    elapsed.map_or_else(String::new, |elapsed| format!(" in {:.1?}", elapsed))
+                ),
+                serde_json::json!({
+                    "file": file,
+                    "regions": report.regions.len(),
+                    "items": items,
+                    "diagnostics": report.diagnostics.len(),
+                    "elapsed_ms": elapsed.map(|elapsed| elapsed.as_secs_f64() * 1000.0),
+                }),
+            );
+        }
+        if self.verbosity >= 2 {
+            for region in &report.regions {
+                let outcome = if region.lists == 0 {
+                    "no lists, nothing converted".to_string()
+                } else {
+                    format!("{} lists, {} items", region.lists, region.items)
+                };
+                self.log(
+                    "debug",
+                    &format!(
+                        "{}:{}:{}: region `{}`: {}",
+                        file, region.line, region.column, region.id, outcome
+                    ),
+                    serde_json::json!({
+                        "file": file,
+                        "line": region.line,
+                        "column": region.column,
+                        "region": region.id,
+                        "lists": region.lists,
+                        "items": region.items,
+                    }),
+                );
+            }
+            for event in &report.events {
+                let region = event
+                    .region
+                    .as_ref()
+                    .map_or_else(String::new, |region| format!("region `{}`: ", region));
+                self.log(
+                    "debug",
+                    &format!(
+                        "{}:{}:{}: {}: {}{}",
+                        file, event.line, event.column, event.pass, region, event.message
+                    ),
+                    serde_json::json!({
+                        "file": file,
+                        "line": event.line,
+                        "column": event.column,
+                        "pass": event.pass,
+                        "region": event.region,
+                        "event": event.message,
+                    }),
+                );
+            }
+        }
+    }
+
+    fn log(&self, level: &str, message: &str, fields: serde_json::Value) {
+        match self.format {
+            LogFormat::Text => eprintln!("{}: {}", level, message),
+            LogFormat::Json => {
+                let mut entry = serde_json::json!({ "level": level, "message": message });
+                if let (Some(entry), serde_json::Value::Object(fields)) =
+                    (entry.as_object_mut(), fields)
+                {
+                    entry.extend(fields);
+                }
+                eprintln!("{}", entry);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AnchorArg {
+    /// <a id="setup-step-3"></a> at the start of each item
+    Html,
+    /// {#setup-step-3} at the end of each item's first paragraph
+    Attribute,
+}
+
+impl From<AnchorArg> for AnchorStyle {
+    fn from(arg: AnchorArg) -> Self {
+        match arg {
+            AnchorArg::Html => AnchorStyle::Html,
+            AnchorArg::Attribute => AnchorStyle::Attribute,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum RegionsFormatArg {
+    /// Tab-separated columns with a header
+    Table,
+    /// A JSON array of regions
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatArg {
+    Json,
+    Yaml,
+}
+
+impl From<FormatArg> for DataFormat {
+    fn from(arg: FormatArg) -> Self {
+        match arg {
+            FormatArg::Json => DataFormat::Json,
+            FormatArg::Yaml => DataFormat::Yaml,
+        }
+    }
+}
+
+fn parse_wrap(value: &str) -> Result<Wrap, String> {
+    if value == "preserve" {
+        return Ok(Wrap::Preserve);
+    }
+    match value.parse() {
+        Ok(width) if width > 0 => Ok(Wrap::Width(width)),
+        _ => Err(format!(
+            "expected a positive width or `preserve`, got `{}`",
+            value
+        )),
+    }
+}
+
+fn parse_heading_levels(value: &str) -> Result<RangeInclusive<u8>, String> {
+    let error = || format!("expected levels like `2-4`, got `{}`", value);
+    let (first, last) = value.split_once('-').unwrap_or((value, value));
+    let first: u8 = first.parse().map_err(|_| error())?;
+    let last: u8 = last.parse().map_err(|_| error())?;
+    if !(1..=6).contains(&first) || !(first..=6).contains(&last) {
+        return Err(error());
+    }
+    Ok(first..=last)
+}
+
+fn parse_bullet(value: &str) -> Result<char, String> {
+    match value {
+        "-" | "*" | "+" => Ok(value.chars().next().unwrap()),
+        _ => Err("expected `-`, `*` or `+`".to_string()),
+    }
+}
+
+fn parse_byte_range(value: &str) -> Result<Range<usize>, String> {
+    let error = || format!("expected a byte range like `120:480`, got `{}`", value);
+    let (start, end) = value.split_once(':').ok_or_else(error)?;
+    let start: usize = start.parse().map_err(|_| error())?;
+    let end: usize = end.parse().map_err(|_| error())?;
+    if start > end {
+        return Err(error());
+    }
+    Ok(start..end)
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum UnclosedArg {
+    /// Fail without printing output
+    Error,
+    /// Warn and convert to the end of the enclosing block
+    Convert,
+    /// Warn and leave the region unconverted
+    Skip,
+}
+
+impl From<UnclosedArg> for UnclosedRegionPolicy {
+    fn from(arg: UnclosedArg) -> Self {
+        match arg {
+            UnclosedArg::Error => UnclosedRegionPolicy::Error,
+            UnclosedArg::Convert => UnclosedRegionPolicy::ConvertToEnd,
+            UnclosedArg::Skip => UnclosedRegionPolicy::Skip,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LineEndingsArg {
+    /// End every line with \n
+    Lf,
+    /// Keep the ending of each unchanged line; rewritten lines end like most of the input
+    Preserve,
+}
+
+impl From<LineEndingsArg> for LineEndings {
+    fn from(arg: LineEndingsArg) -> Self {
+        match arg {
+            LineEndingsArg::Lf => LineEndings::Lf,
+            LineEndingsArg::Preserve => LineEndings::Preserve,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum NumberStyleArg {
+    /// 1. 2. 3.
+    Sequential,
+    /// 1. 1. 1., leaving the counting to the renderer
+    Lazy,
+}
+
+impl From<NumberStyleArg> for NumberStyle {
+    fn from(arg: NumberStyleArg) -> Self {
+        match arg {
+            NumberStyleArg::Sequential => NumberStyle::Sequential,
+            NumberStyleArg::Lazy => NumberStyle::Lazy,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MarkerArg {
+    /// <!-- ol --> … <!-- /ol -->
+    Html,
+    /// {/* ol */} … {/* /ol */}
+    Mdx,
+}
+
+impl From<MarkerArg> for MarkerSyntax {
+    fn from(arg: MarkerArg) -> Self {
+        match arg {
+            MarkerArg::Html => MarkerSyntax::HtmlComment,
+            MarkerArg::Mdx => MarkerSyntax::MdxComment,
+        }
+    }
+}
+
+fn read_input(file: Option<&str>) -> io::Result<String> {
+    match file {
+        Some(file_path) => fs::read_to_string(file_path),
         None => {
             let mut buffer = String::new();
             io::stdin().read_to_string(&mut buffer)?;
-            buffer
+            Ok(buffer)
         }
+    }
+}
+
+/// Finds the config for the file at `path` in its directory or the closest
+/// parent directory that has one.
+fn find_config(path: &str) -> Option<PathBuf> {
+    Path::new(path)
+        .ancestors()
+        .skip(1)
+        .map(|directory| directory.join(DEFAULT_CONFIG))
+        .find(|config| config.exists())
+}
+
+/// Builds the transform options from the command-line flags.
+fn transform_options(args: &Args) -> TransformOptions {
+    TransformOptions {
+        annotate: args.annotate,
+        resolve_inline_html: args.resolve_inline_html,
+        unclosed_regions: args.unclosed.into(),
+        normalize_markers: args.normalize_markers,
+        marker_syntaxes: args.markers.iter().copied().map(Into::into).collect(),
+        parse: ParseOptions {
+            autolinks: args.autolinks,
+            wikilinks: args.wikilinks,
+            footnotes: args.footnotes || args.renumber_footnotes,
+            superscript: args.superscript,
+            subscript: args.subscript,
+            spoilers: args.spoilers,
+            max_nesting: args.max_nesting,
+            ..ParseOptions::default()
+        },
+        render: RenderOptions {
+            tab_width: args.tab_width,
+            wrap: args.wrap,
+            nested_indent: args.nested_indent.map(usize::from),
+            fix_indent: args.fix_indent,
+            bullet: args.bullet,
+            pad_numbers: args.pad_numbers,
+            pad_tables: args.pad_tables,
+            preserve_setext_headings: args.setext_headings,
+            preserve_soft_breaks: args.preserve_soft_breaks,
+            renumber_links: args.renumber_links,
+            ..RenderOptions::default()
+        },
+        number_style: args.number_style.into(),
+        join_lists: args.join_lists,
+        renumber_footnotes: args.renumber_footnotes,
+        task_progress: args.task_progress,
+        tasks: args.tasks,
+        heading_numbers: HeadingNumbers {
+            all: args.number_headings,
+            levels: args.heading_levels.clone(),
+            separator: args.heading_separator.clone(),
+            strip: args.strip_heading_numbers,
+        },
+        number_captions: args.number_captions,
+        continue_from: args.continue_from,
+        item_anchors: args.anchors.map(Into::into),
+        strip_markers: args.strip_markers,
+        include_dir: Some(
+            args.input_path()
+                .and_then(|file| Path::new(file).parent())
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+        ),
+        line_endings: args.line_endings.into(),
+    }
+}
+
+fn main() -> io::Result<ExitCode> {
+    let args = Args::parse();
+
+    if let Some(Command::Renumber { file, all }) = &args.command {
+        let input = read_input(file.as_deref())?;
+        let scope = if *all {
+            RenumberScope::All
+        } else {
+            RenumberScope::Regions
+        };
+        print!("{}", renumber(&input, scope));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut options = transform_options(&args);
+    if options.render.bullet.is_none() {
+        // The config next to the input sets the house style, if any.
+        let discovered = match args.input_path() {
+            Some(path) => find_config(path),
+            None => Path::new(DEFAULT_CONFIG)
+                .exists()
+                .then(|| PathBuf::from(DEFAULT_CONFIG)),
+        };
+        if let Some(path) = discovered {
+            match Config::from_str(&fs::read_to_string(&path)?, DataFormat::Yaml) {
+                Ok(config) => options.render.bullet = config.bullet,
+                Err(error) => {
+                    eprintln!("{}: {}", path.display(), error);
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        }
+    }
+    let logger = Logger {
+        verbosity: args.verbose,
+        format: args.log_format,
     };
 
-    let transformed = transform(&input);
-    print!("{}", transformed);
+    if let Some(Command::Extract { file, format }) = &args.command {
+        let input = read_input(file.as_deref())?;
+        let regions = extract(&input, &options);
+        print!("{}", regions_to_string(&regions, (*format).into()));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Generate { file, format }) = &args.command {
+        let input = read_input(file.as_deref())?;
+        let format = match format {
+            Some(format) => (*format).into(),
+            None if file
+                .as_deref()
+                .is_some_and(|file| file.ends_with(".yaml") || file.ends_with(".yml")) =>
+            {
+                DataFormat::Yaml
+            }
+            None => DataFormat::Json,
+        };
+        return match regions_from_str(&input, format) {
+            Ok(regions) => {
+                print!("{}", generate(&regions, &options));
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(error) => {
+                eprintln!("{}: {}", file.as_deref().unwrap_or("<stdin>"), error);
+                Ok(ExitCode::FAILURE)
+            }
+        };
+    }
+
+    if let Some(Command::Stats { files }) = &args.command {
+        let porcelain = args.porcelain.is_some();
+        if !porcelain {
+            println!("file\tregions\tlists\titems\tdepth\tcur\todd");
+        }
+        let print_row = |name: &str, stats: &DocumentStats| {
+            if porcelain {
+                print!(
+                    "{}",
+                    porcelain_record(&[
+                        &"stats",
+                        &name,
+                        &stats.regions,
+                        &stats.lists,
+                        &stats.items,
+                        &stats.max_depth,
+                        &stats.cur_expressions,
+                        &stats.odd_references,
+                    ])
+                );
+                return;
+            }
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                name,
+                stats.regions,
+                stats.lists,
+                stats.items,
+                stats.max_depth,
+                stats.cur_expressions,
+                stats.odd_references
+            );
+        };
+        if files.is_empty() {
+            let name = args.stdin_filepath.as_deref().unwrap_or("<stdin>");
+            print_row(name, &stats(&read_input(None)?, &options));
+            return Ok(ExitCode::SUCCESS);
+        }
+        let mut total = DocumentStats::default();
+        for file in files {
+            let file_stats = stats(&read_input(Some(file))?, &options);
+            print_row(file, &file_stats);
+            total += file_stats;
+        }
+        // Scripts add up porcelain records themselves.
+        if files.len() > 1 && !porcelain {
+            print_row("total", &total);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Regions { files, format }) = &args.command {
+        let inputs = if files.is_empty() {
+            let name = args.stdin_filepath.as_deref().unwrap_or("<stdin>");
+            vec![(name, read_input(None)?)]
+        } else {
+            files
+                .iter()
+                .map(|file| Ok((file.as_str(), read_input(Some(file))?)))
+                .collect::<io::Result<_>>()?
+        };
+        let mut found = Vec::new();
+        for (name, input) in &inputs {
+            let options = TransformOptions {
+                include_dir: Some(
+                    Path::new(name)
+                        .parent()
+                        .unwrap_or(Path::new(""))
+                        .to_path_buf(),
+                ),
+                ..options.clone()
+            };
+            let (_, report) = transform_with_report(input, &options);
+            found.extend(report.regions.into_iter().map(|region| (*name, region)));
+        }
+        if args.porcelain.is_some() {
+            for (file, region) in &found {
+                print!(
+                    "{}",
+                    porcelain_record(&[
+                        &"region",
+                        file,
+                        &region.id,
+                        &region.kind.keyword(),
+                        &region.line,
+                        &region.end_line,
+                        &region.lists,
+                        &region.items,
+                        &region.attributes,
+                    ])
+                );
+            }
+        } else if *format == RegionsFormatArg::Json {
+            let regions: Vec<_> = found
+                .iter()
+                .map(|(file, region)| {
+                    serde_json::json!({
+                        "file": file,
+                        "name": region.id,
+                        "kind": region.kind.keyword(),
+                        "start_line": region.line,
+                        "end_line": region.end_line,
+                        "attributes": region.attributes,
+                        "lists": region.lists,
+                        "items": region.items,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::Value::Array(regions));
+        } else {
+            println!("file\tname\tkind\tlines\tlists\titems\tattributes");
+            for (file, region) in &found {
+                println!(
+                    "{}\t{}\t{}\t{}-{}\t{}\t{}\t{}",
+                    file,
+                    region.id,
+                    region.kind.keyword(),
+                    region.line,
+                    region.end_line,
+                    region.lists,
+                    region.items,
+                    region.attributes
+                );
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Restore { file }) = &args.command {
+        let input = read_input(file.as_deref())?;
+        let source_name = file
+            .as_deref()
+            .or(args.stdin_filepath.as_deref())
+            .unwrap_or("<stdin>");
+        return Ok(match filter(&input, FilterDirection::Clean, &options) {
+            Ok(source) => {
+                print!("{}", source);
+                ExitCode::SUCCESS
+            }
+            Err(message) => {
+                eprintln!("{}: {}", source_name, message);
+                ExitCode::FAILURE
+            }
+        });
+    }
+
+    if let Some(Command::Publish { file, html }) = &args.command {
+        let input = read_input(file.as_deref())?;
+        let path = file.as_deref().or(args.input_path());
+        let source_name = path.unwrap_or("<stdin>");
+        let options = PublishOptions {
+            transform: TransformOptions {
+                number_captions: true,
+                item_anchors: options.item_anchors.or(Some(AnchorStyle::Html)),
+                include_dir: Some(
+                    path.and_then(|path| Path::new(path).parent())
+                        .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+                ),
+                ..options.clone()
+            },
+            html: *html,
+        };
+        let (output, report) = publish(&input, &options);
+        for diagnostic in &report.diagnostics {
+            eprint!(
+                "{}",
+                format_diagnostic(args.diagnostic_format(), source_name, &input, diagnostic)
+            );
+        }
+        if report.has_errors() {
+            return Ok(ExitCode::FAILURE);
+        }
+        print!("{}", output);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Project { manifest, write }) = &args.command {
+        let format = if manifest.ends_with(".json") {
+            DataFormat::Json
+        } else {
+            DataFormat::Yaml
+        };
+        let files = match Manifest::from_str(&fs::read_to_string(manifest)?, format) {
+            Ok(parsed) => {
+                let base = Path::new(manifest).parent().unwrap_or(Path::new(""));
+                parsed
+                    .files
+                    .iter()
+                    .map(|file| base.join(file))
+                    .collect::<Vec<_>>()
+            }
+            Err(error) => {
+                eprintln!("{}: {}", manifest, error);
+                return Ok(ExitCode::FAILURE);
+            }
+        };
+        let inputs = files
+            .iter()
+            .map(fs::read_to_string)
+            .collect::<io::Result<Vec<_>>>()?;
+        let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+        let base = Path::new(manifest).parent().unwrap_or(Path::new(""));
+        let options = TransformOptions {
+            include_dir: Some(base.to_path_buf()),
+            ..options.clone()
+        };
+        let outputs = transform_project(&inputs, &options);
+        let mut failed = false;
+        for ((file, input), (_, report)) in files.iter().zip(&inputs).zip(&outputs) {
+            let name = file.display().to_string();
+            logger.report(&name, report, None);
+            for diagnostic in &report.diagnostics {
+                eprint!(
+                    "{}",
+                    format_diagnostic(args.diagnostic_format(), &name, input, diagnostic)
+                );
+            }
+            failed |= report.has_errors();
+        }
+        if failed {
+            return Ok(ExitCode::FAILURE);
+        }
+        for ((file, input), (output, _)) in files.iter().zip(&inputs).zip(&outputs) {
+            if args.emit == EmitArg::Patch && !*write {
+                print!("{}", unified_diff(&file.to_string_lossy(), input, output));
+            } else if *write {
+                fs::write(file, output)?;
+            } else {
+                println!("==> {} <==", file.display());
+                print!("{}", output);
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Serve { port, host }) = &args.command {
+        let listener = TcpListener::bind((host.as_str(), *port))?;
+        logger.log(
+            "info",
+            &format!("listening on http://{}", listener.local_addr()?),
+            serde_json::json!({}),
+        );
+        serve_http(listener, &options)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Lsp) = &args.command {
+        serve_language_server(io::stdin().lock(), io::stdout().lock(), &options)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Check { files }) = &args.command {
+        let inputs = if files.is_empty() {
+            let name = args.stdin_filepath.as_deref().unwrap_or("<stdin>");
+            vec![(name, read_input(None)?)]
+        } else {
+            files
+                .iter()
+                .map(|file| Ok((file.as_str(), read_input(Some(file))?)))
+                .collect::<io::Result<_>>()?
+        };
+        let mut checked = Vec::new();
+        for (name, input) in &inputs {
+            let options = TransformOptions {
+                include_dir: Some(
+                    Path::new(name)
+                        .parent()
+                        .unwrap_or(Path::new(""))
+                        .to_path_buf(),
+                ),
+                // Source files keep their markers, whatever is published.
+                strip_markers: false,
+                ..options.clone()
+            };
+            let (output, report) = transform_with_report(input, &options);
+            let changed = (output != *input).then(|| first_changed_line(input, &output));
+            checked.push((*name, input, report, changed));
+        }
+        let failed = checked
+            .iter()
+            .any(|(_, _, report, changed)| report.has_errors() || changed.is_some());
+
+        match args.report {
+            Some(ReportArg::Sarif) => print!(
+                "{}",
+                to_sarif(
+                    checked
+                        .iter()
+                        .map(|(name, _, report, _)| (*name, report.diagnostics.as_slice()))
+                )
+            ),
+            Some(ReportArg::Junit) => print!(
+                "{}",
+                to_junit(checked.iter().map(|(name, _, report, changed)| {
+                    let mut reasons: Vec<_> = changed
+                        .iter()
+                        .map(|line| format!("not transformed: line {} would change", line))
+                        .collect();
+                    reasons.extend(errors(report));
+                    (*name, reasons)
+                }))
+            ),
+            None => {
+                for (name, input, report, changed) in &checked {
+                    for diagnostic in &report.diagnostics {
+                        eprint!(
+                            "{}",
+                            format_diagnostic(args.diagnostic_format(), name, input, diagnostic)
+                        );
+                    }
+                    let status = match (report.has_errors(), changed) {
+                        (true, _) => "error",
+                        (false, Some(_)) => "unformatted",
+                        (false, None) => "ok",
+                    };
+                    if args.porcelain.is_some() {
+                        let line = changed.map_or(String::new(), |line| line.to_string());
+                        print!("{}", porcelain_record(&[&"check", name, &status, &line]));
+                    } else if let Some(line) = changed {
+                        println!("{}: not transformed: line {} would change", name, line);
+                    }
+                }
+            }
+        }
+        return Ok(if failed {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        });
+    }
+
+    if let Some(Command::Lint { files, config }) = &args.command {
+        let inputs = if files.is_empty() {
+            let name = args.stdin_filepath.as_deref().unwrap_or("<stdin>");
+            vec![(name, args.stdin_filepath.as_deref(), read_input(None)?)]
+        } else {
+            files
+                .iter()
+                .map(|file| Ok((file.as_str(), Some(file.as_str()), read_input(Some(file))?)))
+                .collect::<io::Result<_>>()?
+        };
+        // `--config` applies to every input; otherwise each one uses the
+        // config next to it, as the transform does.
+        let mut configs: HashMap<PathBuf, LintConfig> = HashMap::new();
+        let default_config = LintConfig::default();
+        let mut found = Vec::new();
+        for (name, path, input) in &inputs {
+            let discovered = match (config, path) {
+                (Some(config), _) => Some(PathBuf::from(config)),
+                (None, Some(path)) => find_config(path),
+                (None, None) => Path::new(DEFAULT_CONFIG)
+                    .exists()
+                    .then(|| PathBuf::from(DEFAULT_CONFIG)),
+            };
+            if let Some(config_path) = &discovered
+                && !configs.contains_key(config_path)
+            {
+                let format = if config_path
+                    .extension()
+                    .is_some_and(|extension| extension == "json")
+                {
+                    DataFormat::Json
+                } else {
+                    DataFormat::Yaml
+                };
+                match LintConfig::from_str(&fs::read_to_string(config_path)?, format) {
+                    Ok(config) => configs.insert(config_path.clone(), config),
+                    Err(error) => {
+                        eprintln!("{}: {}", config_path.display(), error);
+                        return Ok(ExitCode::FAILURE);
+                    }
+                };
+            }
+            let rules = discovered
+                .and_then(|config_path| configs.get(&config_path))
+                .unwrap_or(&default_config);
+            found.push((*name, lint(input, &options, rules)));
+        }
+        let clean = found.iter().all(|(_, diagnostics)| diagnostics.is_empty());
+        match args.report {
+            Some(ReportArg::Sarif) => print!(
+                "{}",
+                to_sarif(
+                    found
+                        .iter()
+                        .map(|(name, diagnostics)| (*name, diagnostics.as_slice()))
+                )
+            ),
+            Some(ReportArg::Junit) => print!(
+                "{}",
+                to_junit(found.iter().map(|(name, diagnostics)| {
+                    (
+                        *name,
+                        diagnostics.iter().map(Diagnostic::to_string).collect(),
+                    )
+                }))
+            ),
+            None => {
+                for ((name, diagnostics), (_, _, input)) in found.iter().zip(&inputs) {
+                    for diagnostic in diagnostics {
+                        print!(
+                            "{}",
+                            format_diagnostic(args.diagnostic_format(), name, input, diagnostic)
+                        );
+                    }
+                }
+            }
+        }
+        return Ok(if clean {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+
+    if args.emit == EmitArg::Patch && args.region.is_some() {
+        eprintln!("`--emit patch` can't be combined with `--region`");
+        return Ok(ExitCode::FAILURE);
+    }
+    if options.strip_markers && args.region.is_some() {
+        eprintln!("`--strip-markers` can't be combined with `--region`");
+        return Ok(ExitCode::FAILURE);
+    }
+    let input = read_input(args.file.as_deref())?;
+    if let Some(direction) = args.filter {
+        // A failing filter would stop git checking files out, so anything
+        // that can't be converted safely is passed through as it is.
+        match filter(&input, direction.into(), &options) {
+            Ok(output) => print!("{}", output),
+            Err(message) => {
+                eprintln!("{}: {}", args.input_path().unwrap_or("<stdin>"), message);
+                print!("{}", input);
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(bytes) = args.range_bytes.clone() {
+        let (range, text) = format_range(&input, bytes, &options);
+        println!(
+            "{}",
+            serde_json::json!({ "start": range.start, "end": range.end, "text": text })
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+    let started = Instant::now();
+    let (transformed, report) = transform_with_report(&input, &options);
+    let source_name = args.input_path().unwrap_or("<stdin>");
+    logger.report(source_name, &report, Some(started.elapsed()));
+    if let Some(format) = args.report {
+        match format {
+            ReportArg::Sarif => print!(
+                "{}",
+                to_sarif([(source_name, report.diagnostics.as_slice())])
+            ),
+            ReportArg::Junit => print!("{}", to_junit([(source_name, errors(&report))])),
+        }
+        return Ok(if report.has_errors() {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        });
+    }
+    for diagnostic in &report.diagnostics {
+        eprint!(
+            "{}",
+            format_diagnostic(args.diagnostic_format(), source_name, &input, diagnostic)
+        );
+    }
+    if report.has_errors() {
+        return Ok(ExitCode::FAILURE);
+    }
+
+    if args.verify_idempotent {
+        let (again, _) = transform_with_report(&transformed, &options);
+        if let Some((line, _)) = transformed
+            .lines()
+            .zip(again.lines())
+            .enumerate()
+            .find(|(_, (first, second))| first != second)
+            .or_else(|| (transformed != again).then_some((transformed.lines().count(), ("", ""))))
+        {
+            eprintln!(
+                "{}: output is not idempotent: transforming it again changes line {}",
+                source_name,
+                line + 1
+            );
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    if args.list_regions {
+        for region in &report.regions {
+            println!(
+                "{}:{}:{}\t{}\t{} lists\t{} items",
+                source_name, region.line, region.column, region.id, region.lists, region.items
+            );
+        }
+    } else {
+        let output = match &args.region {
+            Some(region) => match region_markdown(&transformed, &report, region, &options) {
+                Ok(markdown) => markdown,
+                Err(message) => {
+                    eprintln!("{}: {}", source_name, message);
+                    return Ok(ExitCode::FAILURE);
+                }
+            },
+            None => transformed,
+        };
+        match args.emit {
+            EmitArg::Markdown => print!("{}", output),
+            EmitArg::Html => print!("{}", render_html(&output, &options.parse)),
+            EmitArg::Text => print!("{}", render_text(&output, &options.parse)),
+            EmitArg::Patch => print!("{}", unified_diff(source_name, &input, &output)),
+        }
+    }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,832 @@
+use crate::options::{Dedupe, ItemOrder, NumberStyle};
+use comrak::nodes::{AstNode, ListDelimType, NodeValue};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+/// The largest number an ordered list can start from: CommonMark allows at
+/// most nine digits, and anything larger wouldn't be read back as a list.
+pub(crate) const MAX_LIST_NUMBER: usize = 999_999_999;
+
+/// The name of a counter, as in `<!-- counter example -->`: words joined by
+/// hyphens, none starting with a digit, so that `(counter:example-1)` reads
+/// as an offset.
+pub(crate) const COUNTER_NAME: &str = r"[A-Za-z_][A-Za-z0-9_]*(?:-[A-Za-z_][A-Za-z0-9_]*)*";
+
+static COUNTER_NAME_ONLY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!("^{}$", COUNTER_NAME)).unwrap());
+
+/// What a region does to the lists inside it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionKind {
+    /// `<!-- ol -->`: bullet lists become ordered lists and `(cur±N)`
+    /// expressions are resolved.
+    Ol,
+    /// `<!-- ul -->`: ordered lists become bullet lists and references to their
+    /// step numbers become `(cur±N)` expressions.
+    Ul,
+}
+
+impl RegionKind {
+    /// The keyword opening a region of this kind, e.g. `ol`.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            RegionKind::Ol => "ol",
+            RegionKind::Ul => "ul",
+        }
+    }
+}
+
+/// A magic comment recognized by the transform.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Marker {
+    /// `<!-- ol ... -->` or `<!-- ul ... -->`, opening a region.
+    Open(RegionKind, RegionAttributes),
+    /// `<!-- /ol -->` or `<!-- /ul -->`, closing a region.
+    Close(RegionKind),
+    /// `<!-- ol-skip -->`, leaving the list right after it unconverted.
+    Skip,
+    /// `<!-- toc-numbers -->`, numbering the headings after it.
+    TocNumbers,
+    /// `<!-- /toc-numbers -->`, ending a `toc-numbers` scope.
+    TocNumbersEnd,
+    /// `<!-- progress -->`, or a filled-in `<!-- progress: 2 of 5 complete
+    /// (40%) -->`, summarizing the task list right before it.
+    Progress,
+    /// `<!-- ol include=path#name -->`, splicing in the region `name` of
+    /// another file.
+    Include(String),
+    /// `<!-- /include -->`, ending the content spliced in by an `include`.
+    IncludeEnd,
+    /// `<!-- counter name -->`, declaring the counter `name` and setting it
+    /// to 0, or with `increment`, `<!-- counter name ++ -->`, adding 1 to it.
+    Counter { name: String, increment: bool },
+}
+
+/// Per-region configuration parsed from the attributes of an opening marker,
+/// e.g. `<!-- ol start=4 delim=paren name=setup -->`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegionAttributes {
+    /// `name=...`: an identifier for the region.
+    pub name: Option<String>,
+    /// `start=N`: the number the region's lists start from.
+    pub start: Option<usize>,
+    /// `delim=period|paren`: the delimiter following each item number.
+    pub delimiter: Option<ListDelimType>,
+    /// `section`: the region needs no closing marker and ends at the next
+    /// heading of the same or a higher level than the one it appears under.
+    pub section: bool,
+    /// `continue`: the region's lists continue numbering from the previous
+    /// converted list under the same parent instead of restarting.
+    pub continue_numbering: bool,
+    /// `join`: the region's lists are numbered as one sequence, each
+    /// continuing from the one before it, as when a list is interrupted by an
+    /// unindented code block.
+    pub join: bool,
+    /// `style=sequential|lazy`: how the region's items are numbered,
+    /// overriding [`TransformOptions::number_style`](crate::TransformOptions::number_style).
+    pub style: Option<NumberStyle>,
+    /// `progress`: a `<!-- progress -->` comment summarizing how many items
+    /// are checked is kept under each of the region's task lists.
+    pub progress: bool,
+    /// `tasks`: the region's bullet items become unchecked task items, e.g.
+    /// `1. [ ] Back up`, keeping the state of those that already are.
+    pub tasks: bool,
+    /// `sort=alpha|numeric|reverse`: the region's items are sorted by the
+    /// text of their first line before they are numbered.
+    pub sort: Option<ItemOrder>,
+    /// `dedupe[=remove|warn]`: items with the same text as an earlier item of
+    /// their list, ignoring case and spacing, are removed or reported.
+    pub dedupe: Option<Dedupe>,
+    /// `reverse`: the items of the region's bullet lists are put in reverse
+    /// order as they are converted, e.g. for newest-first changelogs.
+    pub reverse: bool,
+    /// `merge`: bullet lists separated only by blank lines or HTML comments
+    /// are merged into one list, dropping the comments between them.
+    pub merge: bool,
+    /// `split`: the marker ends the region it appears in and opens one with
+    /// the same attributes, apart from `name`, `start`, `continue` and
+    /// `section`, which it sets itself. Written inside a list, it splits the
+    /// list in two after the item containing it.
+    pub split: bool,
+    /// `table=HEADER,HEADER`: the region's lists become two-column tables
+    /// with these headers, numbering the items in the first column.
+    pub table: Option<[String; 2]>,
+    /// `untable[=HEADER,HEADER]`: the two-column tables in the region whose
+    /// first column numbers their rows become lists again. Once a table is
+    /// converted, the marker records its headers as the value.
+    pub untable: bool,
+    /// `prefix="Step {n}: "`: text put at the start of each item, with `{n}`
+    /// replaced by the item's number. In `ul` regions the prefix numbers
+    /// bullet items instead of an ordered marker.
+    pub prefix: Option<String>,
+}
+
+/// A comment syntax magic comments can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkerSyntax {
+    /// HTML comments: `<!-- ol -->` … `<!-- /ol -->`.
+    HtmlComment,
+    /// MDX expression comments: `{/* ol */}` … `{/* /ol */}`, for MDX
+    /// documents whose compiler strips HTML comments. Like any paragraph, an
+    /// MDX marker needs a blank line before it when it follows a list item, or
+    /// it becomes part of the item's text.
+    MdxComment,
+}
+
+impl MarkerSyntax {
+    fn strip(self, text: &str) -> Option<&str> {
+        let text = text.trim();
+        match self {
+            MarkerSyntax::HtmlComment => text.strip_prefix("<!--")?.strip_suffix("-->"),
+            MarkerSyntax::MdxComment => text.strip_prefix("{/*")?.strip_suffix("*/}"),
+        }
+    }
+
+    pub(crate) fn wrap(self, inner: &str) -> String {
+        match self {
+            MarkerSyntax::HtmlComment => format!("<!-- {} -->", inner),
+            MarkerSyntax::MdxComment => format!("{{/* {} */}}", inner),
+        }
+    }
+}
+
+/// Returns the text of a block that may hold a magic comment: the literal of
+/// an HTML block, or the text of a paragraph made of a single text node.
+pub(crate) fn marker_text<'a>(node: &'a AstNode<'a>) -> Option<String> {
+    match &node.data.borrow().value {
+        NodeValue::HtmlBlock(html_block) => Some(html_block.literal.clone()),
+        NodeValue::Paragraph => {
+            let child = node.first_child()?;
+            if child.next_sibling().is_some() {
+                return None;
+            }
+            match &child.data.borrow().value {
+                NodeValue::Text(text) | NodeValue::HtmlInline(text) => Some(text.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Replaces the text of a marker block, as returned by [`marker_text`].
+/// MDX markers are kept as inline HTML, which is written out as it is rather
+/// than escaped like text.
+pub(crate) fn set_marker_text<'a>(node: &'a AstNode<'a>, text: String) {
+    let mut data = node.data.borrow_mut();
+    match &mut data.value {
+        NodeValue::HtmlBlock(html_block) => html_block.literal = text,
+        NodeValue::Paragraph => {
+            if let Some(child) = node.first_child() {
+                child.data.borrow_mut().value = NodeValue::HtmlInline(text);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Parses the text of a block into a [`Marker`], trying each of `syntaxes`.
+///
+/// Returns `None` when the text is not a magic comment. Matching tolerates
+/// hand-typed variations: the space inside the comment delimiters is optional,
+/// keywords and attribute names are case-insensitive, and surrounding
+/// whitespace including carriage returns is ignored.
+///
+/// Attribute problems never reject the marker; they are returned as warning
+/// messages alongside it so a typo in one attribute doesn't silently disable a
+/// whole region.
+pub(crate) fn parse_marker(text: &str, syntaxes: &[MarkerSyntax]) -> Option<(Marker, Vec<String>)> {
+    let (_, keyword, rest) = split_marker(text, syntaxes)?;
+
+    match keyword.as_str() {
+        "ol" if rest.to_ascii_lowercase().starts_with("include=") => {
+            let mut tokens = tokenize(rest).into_iter();
+            let target = tokens
+                .next()
+                .and_then(|(_, value)| value)
+                .unwrap_or_default();
+            let warnings = tokens
+                .map(|(key, _)| format!("`{}` has no effect on an `include` marker", key))
+                .collect();
+            Some((Marker::Include(target), warnings))
+        }
+        "ol" | "ul" => {
+            let kind = if keyword == "ol" {
+                RegionKind::Ol
+            } else {
+                RegionKind::Ul
+            };
+            let (attributes, warnings) = RegionAttributes::parse(rest);
+            Some((Marker::Open(kind, attributes), warnings))
+        }
+        "/ol" if rest.is_empty() => Some((Marker::Close(RegionKind::Ol), Vec::new())),
+        "/ul" if rest.is_empty() => Some((Marker::Close(RegionKind::Ul), Vec::new())),
+        "ol-skip" if rest.is_empty() => Some((Marker::Skip, Vec::new())),
+        "toc-numbers" if rest.is_empty() => Some((Marker::TocNumbers, Vec::new())),
+        "/toc-numbers" if rest.is_empty() => Some((Marker::TocNumbersEnd, Vec::new())),
+        "progress" | "progress:" => Some((Marker::Progress, Vec::new())),
+        "/include" if rest.is_empty() => Some((Marker::IncludeEnd, Vec::new())),
+        "counter" => {
+            let (name, increment) = match rest.split_once(char::is_whitespace) {
+                Some((name, increment)) if increment.trim() == "++" => (name, true),
+                Some(_) => return None,
+                None => (rest, false),
+            };
+            COUNTER_NAME_ONLY.is_match(name).then(|| {
+                let name = name.to_string();
+                (Marker::Counter { name, increment }, Vec::new())
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Returns the syntax the magic comment in `text` is written in.
+pub(crate) fn marker_syntax(text: &str, syntaxes: &[MarkerSyntax]) -> Option<MarkerSyntax> {
+    parse_marker(text, syntaxes)?;
+    split_marker(text, syntaxes).map(|(syntax, _, _)| syntax)
+}
+
+/// Returns the canonical spelling of a magic comment in its own syntax, e.g.
+/// `<!-- ol -->` for `<!--OL-->`, or `None` if `text` is not a magic comment.
+pub(crate) fn normalize_marker(text: &str, syntaxes: &[MarkerSyntax]) -> Option<String> {
+    parse_marker(text, syntaxes)?;
+    let (syntax, keyword, rest) = split_marker(text, syntaxes)?;
+    if rest.is_empty() {
+        Some(syntax.wrap(&keyword))
+    } else {
+        Some(syntax.wrap(&format!("{} {}", keyword, rest)))
+    }
+}
+
+/// Returns the attributes of a recognized marker as written, e.g.
+/// `start=3 name=setup` for `<!-- ol start=3 name=setup -->`.
+pub(crate) fn marker_attributes(text: &str, syntaxes: &[MarkerSyntax]) -> Option<String> {
+    parse_marker(text, syntaxes)?;
+    split_marker(text, syntaxes).map(|(_, _, rest)| rest.to_string())
+}
+
+/// Splits a comment into the syntax it is written in, its lowercased keyword,
+/// and the trimmed text after the keyword.
+fn split_marker<'t>(
+    text: &'t str,
+    syntaxes: &[MarkerSyntax],
+) -> Option<(MarkerSyntax, String, &'t str)> {
+    let (syntax, inner) = syntaxes
+        .iter()
+        .find_map(|syntax| Some((*syntax, syntax.strip(text)?.trim())))?;
+    let (keyword, rest) = match inner.split_once(char::is_whitespace) {
+        Some((keyword, rest)) => (keyword, rest.trim()),
+        None => (inner, ""),
+    };
+    Some((syntax, keyword.to_ascii_lowercase(), rest))
+}
+
+impl RegionAttributes {
+    /// Parses a whitespace-separated list of `key=value` pairs and bare flags.
+    /// Values may be wrapped in double or single quotes to include spaces.
+    pub(crate) fn parse(input: &str) -> (Self, Vec<String>) {
+        let mut attributes = Self::default();
+        let mut warnings = Vec::new();
+
+        for (key, value) in tokenize(input) {
+            let key = key.to_ascii_lowercase();
+            if let Err(warning) = attributes.apply(&key, value.as_deref()) {
+                warnings.push(warning);
+            }
+        }
+
+        (attributes, warnings)
+    }
+
+    fn apply(&mut self, key: &str, value: Option<&str>) -> Result<(), String> {
+        match key {
+            "name" => {
+                let value = required_value(key, value)?;
+                if value.is_empty()
+                    || !value
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                {
+                    return Err(format!(
+                        "invalid region name `{}`: use letters, digits, `-` and `_`",
+                        value
+                    ));
+                }
+                self.name = Some(value.to_string());
+            }
+            "start" => {
+                let value = required_value(key, value)?;
+                let start = value
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid value `{}` for `start`", value))?;
+                if start > MAX_LIST_NUMBER {
+                    return Err(format!(
+                        "`start` can be at most {}, the largest number a list can start from",
+                        MAX_LIST_NUMBER
+                    ));
+                }
+                self.start = Some(start);
+            }
+            "delim" => {
+                self.delimiter = Some(match required_value(key, value)? {
+                    "period" | "." => ListDelimType::Period,
+                    "paren" | ")" => ListDelimType::Paren,
+                    other => {
+                        return Err(format!(
+                            "invalid value `{}` for `delim`: expected `period` or `paren`",
+                            other
+                        ));
+                    }
+                });
+            }
+            "section" => {
+                no_value(key, value)?;
+                self.section = true;
+            }
+            "continue" => {
+                no_value(key, value)?;
+                self.continue_numbering = true;
+            }
+            "join" => {
+                no_value(key, value)?;
+                self.join = true;
+            }
+            "table" => {
+                self.table = Some(table_headers(key, required_value(key, value)?)?);
+            }
+            "untable" => {
+                if let Some(value) = value {
+                    table_headers(key, value)?;
+                }
+                self.untable = true;
+            }
+            "prefix" => {
+                let value = required_value(key, value)?;
+                if value.is_empty() {
+                    return Err("attribute `prefix` requires a value".to_string());
+                }
+                self.prefix = Some(value.to_string());
+            }
+            "split" => {
+                no_value(key, value)?;
+                self.split = true;
+            }
+            "merge" => {
+                no_value(key, value)?;
+                self.merge = true;
+            }
+            "reverse" => {
+                no_value(key, value)?;
+                self.reverse = true;
+            }
+            "dedupe" => {
+                self.dedupe = Some(match value {
+                    None | Some("remove") => Dedupe::Remove,
+                    Some("warn") => Dedupe::Warn,
+                    Some(other) => {
+                        return Err(format!(
+                            "invalid value `{}` for `dedupe`: expected `remove` or `warn`",
+                            other
+                        ));
+                    }
+                });
+            }
+            "sort" => {
+                self.sort = Some(match required_value(key, value)? {
+                    "alpha" => ItemOrder::Alpha,
+                    "numeric" => ItemOrder::Numeric,
+                    "reverse" => ItemOrder::Reverse,
+                    other => {
+                        return Err(format!(
+                            "invalid value `{}` for `sort`: expected `alpha`, `numeric` or `reverse`",
+                            other
+                        ));
+                    }
+                });
+            }
+            "tasks" => {
+                no_value(key, value)?;
+                self.tasks = true;
+            }
+            "progress" => {
+                no_value(key, value)?;
+                self.progress = true;
+            }
+            "style" => {
+                self.style = Some(match required_value(key, value)? {
+                    "sequential" => NumberStyle::Sequential,
+                    "lazy" => NumberStyle::Lazy,
+                    other => {
+                        return Err(format!(
+                            "invalid value `{}` for `style`: expected `sequential` or `lazy`",
+                            other
+                        ));
+                    }
+                });
+            }
+            _ => {
+                return Err(match suggest_attribute(key) {
+                    Some(known) => {
+                        format!("unknown attribute `{}`; did you mean `{}`?", key, known)
+                    }
+                    None => format!("unknown attribute `{}`", key),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The attributes [`RegionAttributes::apply`] accepts.
+const ATTRIBUTES: &[&str] = &[
+    "name", "start", "delim", "section", "continue", "join", "table", "untable", "prefix", "split",
+    "merge", "reverse", "dedupe", "sort", "tasks", "progress", "style",
+];
+
+/// Returns the attribute `key` is most likely a typo of, if any is close
+/// enough to it: one edit away for short keys, two for longer ones.
+fn suggest_attribute(key: &str) -> Option<&'static str> {
+    let allowed = (key.chars().count() + 1) / 3;
+    ATTRIBUTES
+        .iter()
+        .map(|known| (edit_distance(key, known), *known))
+        .filter(|(distance, _)| *distance <= allowed)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+impl RegionAttributes {
+    /// Fills in the attributes a `split` marker takes over from the region
+    /// it splits, keeping those it sets itself.
+    pub(crate) fn inherit(&mut self, parent: &RegionAttributes) {
+        self.delimiter = self.delimiter.or(parent.delimiter);
+        self.style = self.style.or(parent.style);
+        self.table = self.table.take().or_else(|| parent.table.clone());
+        self.prefix = self.prefix.take().or_else(|| parent.prefix.clone());
+        self.sort = self.sort.or(parent.sort);
+        self.dedupe = self.dedupe.or(parent.dedupe);
+        self.join |= parent.join;
+        self.progress |= parent.progress;
+        self.tasks |= parent.tasks;
+        self.reverse |= parent.reverse;
+        self.merge |= parent.merge;
+        self.untable |= parent.untable;
+    }
+}
+
+/// Parses the two column headers of `table` or `untable`, e.g.
+/// `step,description`.
+fn table_headers(key: &str, value: &str) -> Result<[String; 2], String> {
+    let headers: Vec<_> = value.split(',').map(str::trim).collect();
+    let [number, text] = headers[..] else {
+        return Err(format!(
+            "invalid value `{}` for `{}`: expected two column headers, e.g. `step,description`",
+            value, key
+        ));
+    };
+    Ok([number.to_string(), text.to_string()])
+}
+
+fn required_value<'v>(key: &str, value: Option<&'v str>) -> Result<&'v str, String> {
+    value.ok_or_else(|| format!("attribute `{}` requires a value", key))
+}
+
+fn no_value(key: &str, value: Option<&str>) -> Result<(), String> {
+    match value {
+        Some(_) => Err(format!("attribute `{}` takes no value", key)),
+        None => Ok(()),
+    }
+}
+
+fn tokenize(input: &str) -> Vec<(String, Option<String>)> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+
+        let value = if chars.next_if_eq(&'=').is_some() {
+            let mut value = String::new();
+            match chars.next_if(|c| *c == '"' || *c == '\'') {
+                Some(quote) => {
+                    for c in chars.by_ref() {
+                        if c == quote {
+                            break;
+                        }
+                        value.push(c);
+                    }
+                }
+                None => {
+                    while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                        value.push(c);
+                    }
+                }
+            }
+            Some(value)
+        } else {
+            None
+        };
+
+        tokens.push((key, value));
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTML: &[MarkerSyntax] = &[MarkerSyntax::HtmlComment];
+
+    #[test]
+    fn test_parse_plain_markers() {
+        assert_eq!(
+            parse_marker("<!-- ol -->", HTML),
+            Some((
+                Marker::Open(RegionKind::Ol, RegionAttributes::default()),
+                Vec::new()
+            ))
+        );
+        assert_eq!(
+            parse_marker("<!-- /ol -->\n", HTML),
+            Some((Marker::Close(RegionKind::Ol), Vec::new()))
+        );
+        assert_eq!(
+            parse_marker("<!-- ol-skip -->", HTML),
+            Some((Marker::Skip, Vec::new()))
+        );
+        assert_eq!(parse_marker("<!-- a comment -->", HTML), None);
+        assert_eq!(parse_marker("<!-- olive -->", HTML), None);
+    }
+
+    #[test]
+    fn test_parse_tolerates_spacing_and_case() {
+        for literal in [
+            "<!--ol-->",
+            "<!-- OL -->",
+            "<!--   ol   -->",
+            "<!-- ol --> \r\n",
+            "<!--\tOl\t-->",
+        ] {
+            assert_eq!(
+                parse_marker(literal, HTML),
+                Some((
+                    Marker::Open(RegionKind::Ol, RegionAttributes::default()),
+                    Vec::new()
+                )),
+                "{:?}",
+                literal
+            );
+        }
+        assert_eq!(
+            parse_marker("<!--/OL-->\r", HTML),
+            Some((Marker::Close(RegionKind::Ol), Vec::new()))
+        );
+        assert_eq!(
+            parse_marker("<!--OL START=3-->", HTML),
+            Some((
+                Marker::Open(
+                    RegionKind::Ol,
+                    RegionAttributes {
+                        start: Some(3),
+                        ..RegionAttributes::default()
+                    }
+                ),
+                Vec::new()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_normalize_marker() {
+        assert_eq!(
+            normalize_marker("<!--OL-->", HTML),
+            Some("<!-- ol -->".to_string())
+        );
+        assert_eq!(
+            normalize_marker("<!--  ol   start=3 -->\r\n", HTML),
+            Some("<!-- ol start=3 -->".to_string())
+        );
+        assert_eq!(
+            normalize_marker("<!--/ol -->", HTML),
+            Some("<!-- /ol -->".to_string())
+        );
+        assert_eq!(normalize_marker("<!-- a comment -->", HTML), None);
+    }
+
+    #[test]
+    fn test_parse_attributes() {
+        let (marker, warnings) =
+            parse_marker("<!-- ol start=4 delim=paren name=setup -->", HTML).unwrap();
+        assert_eq!(
+            marker,
+            Marker::Open(
+                RegionKind::Ol,
+                RegionAttributes {
+                    name: Some("setup".to_string()),
+                    start: Some(4),
+                    delimiter: Some(ListDelimType::Paren),
+                    section: false,
+                    continue_numbering: false,
+                    join: false,
+                    style: None,
+                    progress: false,
+                    tasks: false,
+                    sort: None,
+                    dedupe: None,
+                    reverse: false,
+                    merge: false,
+                    split: false,
+                    table: None,
+                    untable: false,
+                    prefix: None,
+                }
+            )
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_parse_flag_attributes() {
+        let (marker, warnings) = parse_marker("<!-- ol section -->", HTML).unwrap();
+        assert_eq!(
+            marker,
+            Marker::Open(
+                RegionKind::Ol,
+                RegionAttributes {
+                    section: true,
+                    ..RegionAttributes::default()
+                }
+            )
+        );
+        assert!(warnings.is_empty());
+
+        let (_, warnings) = parse_marker("<!-- ol section=yes -->", HTML).unwrap();
+        assert_eq!(
+            warnings,
+            vec!["attribute `section` takes no value".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_parse_style_attribute() {
+        let (marker, warnings) = parse_marker("<!-- ol style=lazy -->", HTML).unwrap();
+        assert_eq!(
+            marker,
+            Marker::Open(
+                RegionKind::Ol,
+                RegionAttributes {
+                    style: Some(NumberStyle::Lazy),
+                    ..RegionAttributes::default()
+                }
+            )
+        );
+        assert!(warnings.is_empty());
+
+        let (_, warnings) = parse_marker("<!-- ol style=roman -->", HTML).unwrap();
+        assert_eq!(
+            warnings,
+            vec!["invalid value `roman` for `style`: expected `sequential` or `lazy`".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_quoted_values_and_flags() {
+        assert_eq!(
+            tokenize(r#"a="x y" b='z' flag c=d"#),
+            vec![
+                ("a".to_string(), Some("x y".to_string())),
+                ("b".to_string(), Some("z".to_string())),
+                ("flag".to_string(), None),
+                ("c".to_string(), Some("d".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_include_markers() {
+        assert_eq!(
+            parse_marker("<!-- ol include=common/setup.md#setup -->", HTML),
+            Some((
+                Marker::Include("common/setup.md#setup".to_string()),
+                Vec::new()
+            ))
+        );
+        assert_eq!(
+            parse_marker("<!-- ol include=a.md#b start=2 -->", HTML),
+            Some((
+                Marker::Include("a.md#b".to_string()),
+                vec!["`start` has no effect on an `include` marker".to_string()]
+            ))
+        );
+        assert_eq!(
+            parse_marker("<!-- /include -->", HTML),
+            Some((Marker::IncludeEnd, Vec::new()))
+        );
+    }
+
+    #[test]
+    fn test_parse_prefix_attribute() {
+        let (marker, warnings) = parse_marker(r#"<!-- ol prefix="Step {n}: " -->"#, HTML).unwrap();
+        assert_eq!(
+            marker,
+            Marker::Open(
+                RegionKind::Ol,
+                RegionAttributes {
+                    prefix: Some("Step {n}: ".to_string()),
+                    ..RegionAttributes::default()
+                }
+            )
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_and_invalid_attributes_warn() {
+        let (marker, warnings) =
+            parse_marker("<!-- ol type=alpha start=x name strat=2 -->", HTML).unwrap();
+        assert_eq!(
+            marker,
+            Marker::Open(RegionKind::Ol, RegionAttributes::default())
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "unknown attribute `type`".to_string(),
+                "invalid value `x` for `start`".to_string(),
+                "attribute `name` requires a value".to_string(),
+                "unknown attribute `strat`; did you mean `start`?".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_mdx_markers() {
+        let syntaxes = &[MarkerSyntax::HtmlComment, MarkerSyntax::MdxComment];
+        assert_eq!(
+            parse_marker("{/* ol */}", syntaxes),
+            Some((
+                Marker::Open(RegionKind::Ol, RegionAttributes::default()),
+                Vec::new()
+            ))
+        );
+        assert_eq!(
+            parse_marker("{/*/OL*/}", syntaxes),
+            Some((Marker::Close(RegionKind::Ol), Vec::new()))
+        );
+        assert_eq!(
+            normalize_marker("{/*ol  start=2*/}", syntaxes),
+            Some("{/* ol start=2 */}".to_string())
+        );
+        assert_eq!(parse_marker("{/* ol */}", HTML), None);
+        assert_eq!(parse_marker("{ol}", syntaxes), None);
+    }
+
+    #[test]
+    fn test_parse_ul_markers() {
+        assert_eq!(
+            parse_marker("<!-- ul name=notes -->", HTML),
+            Some((
+                Marker::Open(
+                    RegionKind::Ul,
+                    RegionAttributes {
+                        name: Some("notes".to_string()),
+                        ..RegionAttributes::default()
+                    }
+                ),
+                Vec::new()
+            ))
+        );
+        assert_eq!(
+            parse_marker("<!--/UL-->", HTML),
+            Some((Marker::Close(RegionKind::Ul), Vec::new()))
+        );
+    }
+}
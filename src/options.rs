@@ -0,0 +1,385 @@
+use crate::marker::MarkerSyntax;
+use comrak::nodes::ListDelimType;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+/// What to do with an `<!-- ol -->` region that has no `<!-- /ol -->` before
+/// the end of the block containing it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnclosedRegionPolicy {
+    /// Report an error and leave the region unconverted.
+    Error,
+    /// Warn and convert up to the end of the containing block.
+    #[default]
+    ConvertToEnd,
+    /// Warn and leave the region unconverted.
+    Skip,
+}
+
+/// How the lines of a transformed document end.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEndings {
+    /// Every line ends with `\n`.
+    #[default]
+    Lf,
+    /// Lines the transform leaves as they are keep their own ending, `\n`
+    /// or `\r\n`, so files mixing both only change where they are
+    /// rewritten. Rewritten lines end the way most lines of the input do.
+    Preserve,
+}
+
+/// How [`MarkdownPrinter`](crate::MarkdownPrinter) writes a document, and
+/// how [`TransformOptions::render`] writes a transformed one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// The number of columns a tab stands for when the source is indented
+    /// with tabs, in which case list indentation is written with tabs too.
+    /// Defaults to 4, the tab stop CommonMark parses with.
+    pub tab_width: usize,
+    /// How the text of paragraphs and list items is laid out.
+    pub wrap: Wrap,
+    /// Indent nested lists by this many columns from the marker of the item
+    /// containing them, e.g. 2 or 4, instead of as written in the source.
+    /// Lists are never indented less than their parent item's content, so
+    /// that they stay nested.
+    pub nested_indent: Option<usize>,
+    /// Indent list markers by nesting level alone, so that items indented
+    /// inconsistently in the source, or too little or too much for the level
+    /// they were parsed at, line up. Combine with `nested_indent` to also set
+    /// the width of each level.
+    pub fix_indent: bool,
+    /// Write every bullet list with this marker, `-`, `*` or `+`, instead of
+    /// the one it was written with, including lists the transform leaves
+    /// alone.
+    pub bullet: Option<char>,
+    /// The delimiter of every ordered list, instead of the one each list was
+    /// written with.
+    pub delimiter: Option<ListDelimType>,
+    /// Right-align the numbers of ordered lists so that their markers line
+    /// up once a list reaches 10 or 100 items, e.g. ` 9.` above `10.`.
+    pub pad_numbers: bool,
+    /// Pad table cells so that the columns line up.
+    pub pad_tables: bool,
+    /// Keep setext headings (underlined with `===` or `---`) in that style
+    /// instead of rewriting them as ATX `#` headings.
+    pub preserve_setext_headings: bool,
+    /// Keep the line breaks inside list items instead of joining each
+    /// paragraph of an item onto one line. Has no effect with
+    /// [`Wrap::Width`].
+    pub preserve_soft_breaks: bool,
+    /// Treat `[^label]:` lines as footnote definitions, for documents parsed
+    /// with footnotes. The transform sets it from
+    /// [`ParseOptions::footnotes`].
+    pub footnotes: bool,
+    /// Renumber reference-style links with numeric labels, such as `[3]`, in
+    /// order of their first appearance, and sort their definitions to match.
+    pub renumber_links: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            wrap: Wrap::default(),
+            nested_indent: None,
+            fix_indent: false,
+            bullet: None,
+            delimiter: None,
+            pad_numbers: false,
+            pad_tables: false,
+            preserve_setext_headings: false,
+            preserve_soft_breaks: false,
+            footnotes: false,
+            renumber_links: false,
+        }
+    }
+}
+
+/// How the text of paragraphs and list items is laid out in the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Wrap {
+    /// Leave line lengths alone: paragraphs keep their line breaks, and list
+    /// items are joined onto one line unless
+    /// [`RenderOptions::preserve_soft_breaks`] is set.
+    #[default]
+    Preserve,
+    /// Reflow the text to lines of at most this many columns, where words
+    /// allow it.
+    Width(usize),
+}
+
+/// How the items of converted lists are numbered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NumberStyle {
+    /// `1.`, `2.`, `3.`, ...
+    #[default]
+    Sequential,
+    /// Every item repeats the list's first number, e.g. `1.`, `1.`, `1.`,
+    /// leaving the counting to the Markdown renderer, so that inserting an
+    /// item does not renumber the ones after it.
+    Lazy,
+}
+
+/// How [`TransformOptions::item_anchors`] marks each item, for an item
+/// numbered 3 in a region named `setup`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnchorStyle {
+    /// `<a id="setup-step-3"></a>` at the start of the item.
+    Html,
+    /// `{#setup-step-3}` at the end of the item's first paragraph, for
+    /// renderers that read heading-style attributes.
+    Attribute,
+}
+
+/// How the `sort` attribute orders a region's items, by the text of their
+/// first line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemOrder {
+    /// `sort=alpha`: alphabetically, ignoring case.
+    Alpha,
+    /// `sort=numeric`: by the number the text starts with, e.g. `2 GB` before
+    /// `16 GB`; items not starting with a number come last, alphabetically.
+    Numeric,
+    /// `sort=reverse`: alphabetically in reverse, ignoring case.
+    Reverse,
+}
+
+/// What the `dedupe` attribute does with items repeating an earlier item of
+/// the same list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dedupe {
+    /// `dedupe` or `dedupe=remove`: the repeated items are removed.
+    Remove,
+    /// `dedupe=warn`: the repeated items are kept and reported as warnings.
+    Warn,
+}
+
+/// How headings are numbered, e.g. `## 2.1 Installation`.
+///
+/// Headings are numbered after a `<!-- toc-numbers -->` marker, up to a
+/// `<!-- /toc-numbers -->` marker or the end of the document, or throughout
+/// the document with [`all`](Self::all).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadingNumbers {
+    /// Number every heading, without a `<!-- toc-numbers -->` marker.
+    pub all: bool,
+    /// The heading levels numbered, `2..=6` by default so that a `#` title
+    /// stays unnumbered. A heading above these levels restarts the count.
+    pub levels: RangeInclusive<u8>,
+    /// What goes between the parts of a number, `.` by default.
+    pub separator: String,
+    /// Remove the numbers from headings instead of adding them.
+    pub strip: bool,
+}
+
+impl Default for HeadingNumbers {
+    fn default() -> Self {
+        Self {
+            all: false,
+            levels: 2..=6,
+            separator: ".".to_string(),
+            strip: false,
+        }
+    }
+}
+
+/// Which Markdown extensions are recognized when parsing a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// GitHub-flavored tables. On by default.
+    pub tables: bool,
+    /// `- [ ]` and `- [x]` task list items. On by default.
+    pub task_lists: bool,
+    /// `~~strikethrough~~` text. On by default.
+    pub strikethrough: bool,
+    /// Bare URLs such as `https://example.com` as links, like GitHub does.
+    /// Off by default.
+    pub autolinks: bool,
+    /// `[^1]` footnote references and their definitions. Off by default.
+    pub footnotes: bool,
+    /// `$inline$` and `$$display$$` math. On by default.
+    pub math: bool,
+    /// `term` / `: details` description lists. On by default.
+    pub description_lists: bool,
+    /// `[[Page Name]]` and `[[Page Name|title]]` wikilinks, as used by
+    /// Obsidian and Foam. Off by default.
+    pub wikilinks: bool,
+    /// `^superscript^` text. Off by default.
+    pub superscript: bool,
+    /// `~subscript~` text, leaving `~~` for strikethrough. Off by default.
+    pub subscript: bool,
+    /// Discord-style `||spoiler||` text. Off by default.
+    pub spoilers: bool,
+    /// GitHub `> [!NOTE]` alerts, kept as alerts rather than block quotes.
+    /// On by default.
+    pub alerts: bool,
+    /// The delimiter of front matter at the start of a document, `---` by
+    /// default. `None` parses such a block as ordinary Markdown.
+    pub front_matter_delimiter: Option<String>,
+    /// How deeply nodes may nest, counting every block quote, list, item,
+    /// paragraph and inline span. Documents nested deeper are reported and
+    /// left as they are, as processing them could run out of stack. 128 by
+    /// default.
+    pub max_nesting: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            tables: true,
+            task_lists: true,
+            strikethrough: true,
+            autolinks: false,
+            footnotes: false,
+            math: true,
+            description_lists: true,
+            wikilinks: false,
+            superscript: false,
+            subscript: false,
+            spoilers: false,
+            alerts: true,
+            front_matter_delimiter: Some("---".to_string()),
+            max_nesting: 128,
+        }
+    }
+}
+
+/// Options controlling [`transform_with_options`](crate::transform_with_options).
+#[derive(Debug, Clone)]
+pub struct TransformOptions {
+    /// Follow every resolved `(cur±N)` expression with an HTML comment holding
+    /// the original expression, e.g. `(3)<!-- cur-1 -->`, so the output can be
+    /// turned back into its source form with [`restore`](crate::restore).
+    pub annotate: bool,
+
+    /// Also resolve `(cur±N)` expressions inside inline HTML within converted
+    /// items, such as attribute values in `<a title="step (cur-1)">`.
+    pub resolve_inline_html: bool,
+    /// What to do with regions missing their closing marker.
+    pub unclosed_regions: UnclosedRegionPolicy,
+    /// Rewrite recognized magic comments in their canonical spelling, e.g.
+    /// `<!-- ol -->` for a hand-typed `<!--OL-->`.
+    pub normalize_markers: bool,
+    /// The comment syntaxes recognized as magic comments. Defaults to HTML
+    /// comments only.
+    pub marker_syntaxes: Vec<MarkerSyntax>,
+    /// Which Markdown extensions are recognized when parsing.
+    pub parse: ParseOptions,
+    /// How the transformed document is written.
+    pub render: RenderOptions,
+    /// How the items of lists in `ol` regions are numbered. Regions can
+    /// override it with `style=sequential` or `style=lazy`.
+    pub number_style: NumberStyle,
+    /// Number all lists within a region as one sequence, as if every region
+    /// had the `join` attribute.
+    pub join_lists: bool,
+    /// Renumber footnotes with numeric labels, such as `[^3]`, in order of
+    /// their first reference, and sort their definitions to match. Needs
+    /// [`ParseOptions::footnotes`].
+    pub renumber_footnotes: bool,
+    /// Add a `<!-- progress -->` comment under every task list in an `ol`
+    /// region, as if every region had the `progress` attribute. Existing
+    /// progress comments are kept up to date either way.
+    pub task_progress: bool,
+    /// Turn the items of lists converted in `ol` regions into unchecked task
+    /// items, as if every region had the `tasks` attribute.
+    pub tasks: bool,
+    /// How headings in `<!-- toc-numbers -->` scopes are numbered.
+    pub heading_numbers: HeadingNumbers,
+    /// Number the paragraphs starting with `Figure:` or `Table:` through the
+    /// document, e.g. `Figure 3: The pipeline`, and resolve references to
+    /// the captions labelled `<!-- fig:pipeline -->`, such as
+    /// `(fig:pipeline)`, to their numbers.
+    pub number_captions: bool,
+    /// The number `continue` regions start from when no list comes before
+    /// them in the document, e.g. the number following the last step of the
+    /// previous part of a tutorial. See [`TransformReport::next_number`].
+    ///
+    /// [`TransformReport::next_number`]: crate::TransformReport::next_number
+    pub continue_from: Option<usize>,
+    /// Give every item converted in a named region an anchor made of the
+    /// region's name and the item's number, e.g. `setup-step-3`, so that
+    /// other pages can link to it. Items of unnamed regions get none, as
+    /// their IDs change when lines are added above them.
+    pub item_anchors: Option<AnchorStyle>,
+    /// Remove the magic comments from the output once it is transformed:
+    /// the region markers, the other markers such as `<!-- ol-skip -->` and
+    /// `<!-- progress -->`, caption labels, and the comments of
+    /// [`annotate`](Self::annotate). The output can then no longer be
+    /// transformed again or restored.
+    pub strip_markers: bool,
+    /// The directory the paths of `<!-- ol include=path#name -->` markers
+    /// are relative to. When `None`, includes are left unexpanded with a
+    /// warning, so transforming a string never reads files unasked.
+    pub include_dir: Option<PathBuf>,
+    /// How the lines of the output end.
+    pub line_endings: LineEndings,
+}
+
+impl Default for TransformOptions {
+    fn default() -> Self {
+        Self {
+            annotate: false,
+            resolve_inline_html: false,
+            unclosed_regions: UnclosedRegionPolicy::default(),
+            normalize_markers: false,
+            marker_syntaxes: vec![MarkerSyntax::HtmlComment],
+            parse: ParseOptions::default(),
+            render: RenderOptions::default(),
+            number_style: NumberStyle::default(),
+            join_lists: false,
+            renumber_footnotes: false,
+            task_progress: false,
+            tasks: false,
+            heading_numbers: HeadingNumbers::default(),
+            number_captions: false,
+            continue_from: None,
+            item_anchors: None,
+            strip_markers: false,
+            include_dir: None,
+            line_endings: LineEndings::default(),
+        }
+    }
+}
+
+impl TransformOptions {
+    /// The options the transformed document is written with: [`render`],
+    /// reading footnote definitions when [`parse`] recognizes footnotes.
+    ///
+    /// [`render`]: Self::render
+    /// [`parse`]: Self::parse
+    pub fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            footnotes: self.render.footnotes || self.parse.footnotes,
+            ..self.render.clone()
+        }
+    }
+}
+
+/// Options controlling [`publish`](crate::publish), which turns a source file
+/// into the form readers see.
+#[derive(Debug, Clone)]
+pub struct PublishOptions {
+    /// How the source is transformed. Markers are always stripped and
+    /// resolved numbers never annotated, whatever
+    /// [`TransformOptions::strip_markers`] and [`TransformOptions::annotate`]
+    /// say. Defaults to numbering captions and giving the items of named
+    /// regions HTML anchors.
+    pub transform: TransformOptions,
+    /// Render the published document as HTML instead of Markdown. Item
+    /// anchors are then always written as HTML.
+    pub html: bool,
+}
+
+impl Default for PublishOptions {
+    fn default() -> Self {
+        Self {
+            transform: TransformOptions {
+                number_captions: true,
+                item_anchors: Some(AnchorStyle::Html),
+                ..TransformOptions::default()
+            },
+            html: false,
+        }
+    }
+}
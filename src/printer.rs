@@ -1,44 +1,401 @@
-use comrak::nodes::{AstNode, NodeValue};
+use crate::marker::MAX_LIST_NUMBER;
+use crate::options::{RenderOptions, Wrap};
+use comrak::nodes::{
+    AstNode, LineColumn, ListDelimType, ListType, NodeAlert, NodeHeading, NodeList, NodeTable,
+    NodeValue, TableAlignment,
+};
+use regex::Regex;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use std::sync::LazyLock;
+use unicode_width::UnicodeWidthStr;
 
-pub struct Printer {
+/// A single-line link reference definition, e.g. `[label]: https://example.com "Title"`.
+static LINK_DEFINITION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"^\s*\[(?:[^\]\\]|\\.)+\]:\s*(?:<[^>]*>|\S+)(?:\s+(?:"[^"]*"|'[^']*'|\([^)]*\)))?\s*$"#,
+    )
+    .unwrap()
+});
+
+/// The label of a link reference definition with a numeric label, e.g. `[3]:`.
+static NUMERIC_DEFINITION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[\s*(\d+)\s*\]:").unwrap());
+
+/// Writes a comrak syntax tree back out as Markdown, keeping the details of
+/// the source it was parsed from that the tree doesn't hold, such as which
+/// character delimited an emphasis and where blank lines were.
+///
+/// # Examples
+///
+/// ```
+/// use comrak::{Arena, Options, parse_document};
+/// use md_ol_util::{MarkdownPrinter, RenderOptions};
+///
+/// let source = "* One\n* Two\n";
+/// let arena = Arena::new();
+/// let root = parse_document(&arena, source, &Options::default());
+/// let options = RenderOptions {
+///     bullet: Some('-'),
+///     ..RenderOptions::default()
+/// };
+/// let mut printer = MarkdownPrinter::with_options(source, &options);
+/// printer.render_node(root);
+/// assert_eq!(printer.finish(), "- One\n- Two\n");
+/// ```
+pub struct MarkdownPrinter<'s> {
     output: String,
-    list_stack: Vec<i32>, // Track list item counters for nested lists
+    list_stack: Vec<ListMarker>, // Track list markers for nested lists
+    /// The lines of the parsed document, used to recover details the AST
+    /// does not keep, such as which character delimited an emphasis.
+    source_lines: Vec<&'s str>,
+    pad_tables: bool,
+    preserve_setext: bool,
+    /// Whether the source indents with tabs, in which case list indentation
+    /// is written with tabs too.
+    indent_with_tabs: bool,
+    /// The number of columns a tab stands for when indenting with tabs.
+    tab_width: usize,
+    wrap: Wrap,
+    preserve_soft_breaks: bool,
+    pad_numbers: bool,
+    /// The number of columns nested list markers are indented by relative to
+    /// the marker of the item containing them, or `None` to keep the source
+    /// indentation.
+    nested_indent: Option<usize>,
+    /// The indentation of lists nested in the item being rendered, relative
+    /// to its content, when `nested_indent` is set.
+    nested_offset: usize,
+    /// Whether list markers are indented by nesting level alone, ignoring
+    /// the indentation of the source.
+    fix_indent: bool,
+    /// Whether the document was parsed with footnotes, in which case
+    /// `[^label]:` lines are footnote definitions rather than link reference
+    /// definitions.
+    footnotes: bool,
+    /// The source lines of the document's footnote definitions, which may
+    /// have been moved away from their place in the source.
+    footnote_lines: Vec<RangeInclusive<usize>>,
+    /// Where the lists whose items all repeat the first number start.
+    lazy_lists: BTreeSet<LineColumn>,
+    /// The marker every bullet list is written with, if not its own.
+    bullet: Option<char>,
+    /// The delimiter every ordered list is written with, if not its own.
+    delimiter: Option<ListDelimType>,
+    /// Whether reference-style links with numeric labels, such as `[3]`, are
+    /// renumbered in order of appearance.
+    renumber_links: bool,
+    /// The new number of each numeric link label when `renumber_links` is set.
+    link_numbers: HashMap<String, usize>,
+    /// The column where the content of the innermost container starts, such
+    /// as the text after a list marker, used to fit wrapped lines in `wrap`.
+    content_column: usize,
+    /// Whether line breaks are printed as spaces, as in the text of an ATX
+    /// heading, which can't span lines.
+    in_atx_heading: bool,
+}
+
+/// Stands for one column of list indentation while rendering a document
+/// indented with tabs; [`MarkdownPrinter::finish`] turns runs of it into tabs. This
+/// keeps indentation apart from whitespace inside code blocks, which must be
+/// left as written.
+const INDENT_PLACEHOLDER: char = '\u{1f}';
+
+/// The marker state of a list being rendered.
+#[derive(Clone, Copy)]
+enum ListMarker {
+    Bullet(char),
+    /// `width` is the number of digits item numbers are right-aligned to,
+    /// or 0 for no padding.
+    /// `lazy` lists repeat their first number on every item.
+    Ordered {
+        next: i32,
+        delimiter: char,
+        width: usize,
+        lazy: bool,
+    },
 }
 
-impl Printer {
-    pub fn new() -> Self {
+impl<'s> MarkdownPrinter<'s> {
+    /// Creates a printer for a document parsed from `source`.
+    pub fn with_source(source: &'s str) -> Self {
         Self {
-            output: String::new(),
+            // The output is usually about the size of the source, so reserving
+            // that up front avoids most of the copying as it grows.
+            output: String::with_capacity(source.len() + source.len() / 8),
             list_stack: Vec::new(),
+            source_lines: source.lines().collect(),
+            pad_tables: false,
+            preserve_setext: false,
+            indent_with_tabs: source.lines().any(|line| {
+                line.chars()
+                    .take_while(|c| c.is_whitespace())
+                    .any(|c| c == '\t')
+            }),
+            tab_width: 4,
+            wrap: Wrap::default(),
+            preserve_soft_breaks: false,
+            pad_numbers: false,
+            nested_indent: None,
+            nested_offset: 0,
+            fix_indent: false,
+            footnotes: false,
+            footnote_lines: Vec::new(),
+            lazy_lists: BTreeSet::new(),
+            bullet: None,
+            delimiter: None,
+            renumber_links: false,
+            link_numbers: HashMap::new(),
+            in_atx_heading: false,
+            content_column: 0,
         }
     }
 
+    /// Creates a printer for a document parsed from `source`, set up with
+    /// `options`.
+    pub fn with_options(source: &'s str, options: &RenderOptions) -> Self {
+        Self::with_source(source)
+            .tab_width(options.tab_width)
+            .wrap(options.wrap)
+            .nested_indent(options.nested_indent)
+            .fix_indent(options.fix_indent)
+            .bullet(options.bullet)
+            .delimiter(options.delimiter)
+            .pad_numbers(options.pad_numbers)
+            .pad_tables(options.pad_tables)
+            .preserve_setext(options.preserve_setext_headings)
+            .preserve_soft_breaks(options.preserve_soft_breaks)
+            .footnotes(options.footnotes)
+            .renumber_links(options.renumber_links)
+    }
+
+    /// Writes every bullet list with `bullet`, which must be `-`, `*` or `+`,
+    /// instead of the marker it was written with.
+    pub fn bullet(mut self, bullet: Option<char>) -> Self {
+        self.bullet = bullet.filter(|bullet| matches!(bullet, '-' | '*' | '+'));
+        self
+    }
+
+    /// Writes every ordered list with `delimiter` instead of the one it was
+    /// written with.
+    pub fn delimiter(mut self, delimiter: Option<ListDelimType>) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Keeps the line breaks inside list items instead of joining their lines
+    /// with spaces.
+    pub fn preserve_soft_breaks(mut self, preserve_soft_breaks: bool) -> Self {
+        self.preserve_soft_breaks = preserve_soft_breaks;
+        self
+    }
+
+    /// Right-aligns the numbers of ordered lists so that their markers line
+    /// up, e.g. ` 9.` above `10.`.
+    pub fn pad_numbers(mut self, pad_numbers: bool) -> Self {
+        self.pad_numbers = pad_numbers;
+        self
+    }
+
+    /// Indents nested lists by `indent` columns from the marker of the item
+    /// containing them, or at least as far as the item's content, instead of
+    /// as in the source.
+    pub fn nested_indent(mut self, indent: Option<usize>) -> Self {
+        self.nested_indent = indent;
+        self
+    }
+
+    /// Indents list markers by their nesting level alone: top-level lists
+    /// start at the margin and nested lists line up with their parent item's
+    /// content, or follow `nested_indent` if it is set. Items indented
+    /// differently from their siblings are brought in line.
+    pub fn fix_indent(mut self, fix_indent: bool) -> Self {
+        self.fix_indent = fix_indent;
+        self
+    }
+
+    /// Treats `[^label]:` lines as footnote definitions, as a parser with
+    /// footnotes enabled does.
+    pub fn footnotes(mut self, footnotes: bool) -> Self {
+        self.footnotes = footnotes;
+        self
+    }
+
+    /// Numbers the lists starting at `lists` in the lazy style, repeating
+    /// their first number on every item.
+    pub fn lazy_lists(mut self, lists: BTreeSet<LineColumn>) -> Self {
+        self.lazy_lists = lists;
+        self
+    }
+
+    /// Renumbers reference-style links with numeric labels, such as `[3]`,
+    /// in order of their first appearance, and sorts their definitions to
+    /// match. Definitions that are never referenced are numbered after the
+    /// rest.
+    pub fn renumber_links(mut self, renumber_links: bool) -> Self {
+        self.renumber_links = renumber_links;
+        self
+    }
+
+    /// Sets how the text of paragraphs is laid out.
+    pub fn wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Sets the number of columns a tab stands for when the source is
+    /// indented with tabs. Defaults to 4, the tab stop CommonMark parses with.
+    pub fn tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width.max(1);
+        self
+    }
+
+    /// Pads table cells so that the columns line up.
+    pub fn pad_tables(mut self, pad_tables: bool) -> Self {
+        self.pad_tables = pad_tables;
+        self
+    }
+
+    /// Keeps setext headings (underlined with `===` or `---`) in that style
+    /// instead of rewriting them as ATX `#` headings.
+    pub fn preserve_setext(mut self, preserve_setext: bool) -> Self {
+        self.preserve_setext = preserve_setext;
+        self
+    }
+
     pub fn finish(self) -> String {
-        self.output.trim_end().to_string() + "\n"
+        let mut output = self.output;
+        output.truncate(output.trim_end().len());
+        output.push('\n');
+        if !self.indent_with_tabs {
+            return output;
+        }
+
+        let mut with_tabs = String::with_capacity(output.len());
+        for line in output.split_inclusive('\n') {
+            let content = line.trim_start_matches(INDENT_PLACEHOLDER);
+            let columns = line.len() - content.len();
+            with_tabs.push_str(&"\t".repeat(columns / self.tab_width));
+            with_tabs.push_str(&" ".repeat(columns % self.tab_width));
+            with_tabs.push_str(content);
+        }
+        with_tabs
+    }
+
+    /// Returns `columns` columns of list indentation.
+    fn indentation(&self, columns: usize) -> String {
+        let unit = if self.indent_with_tabs {
+            INDENT_PLACEHOLDER
+        } else {
+            ' '
+        };
+        unit.to_string().repeat(columns)
     }
 
     pub fn render_node<'a>(&mut self, node: &'a AstNode<'a>) {
         match &node.data.borrow().value {
             NodeValue::Document => {
+                if self.renumber_links {
+                    for descendant in node.descendants() {
+                        if !matches!(
+                            descendant.data.borrow().value,
+                            NodeValue::Link(_) | NodeValue::Image(_)
+                        ) {
+                            continue;
+                        }
+                        if let Some(label) = self.numeric_label(descendant) {
+                            let next = self.link_numbers.len() + 1;
+                            self.link_numbers.entry(label).or_insert(next);
+                        }
+                    }
+                }
+                self.footnote_lines = node
+                    .children()
+                    .filter(|child| {
+                        matches!(child.data.borrow().value, NodeValue::FootnoteDefinition(_))
+                    })
+                    .map(|child| {
+                        let sourcepos = child.data.borrow().sourcepos;
+                        sourcepos.start.line..=sourcepos.end.line
+                    })
+                    .collect();
+                let mut last_line = 0;
+                for child in node.children() {
+                    let sourcepos = child.data.borrow().sourcepos;
+                    let definitions = self.link_definitions(last_line + 1, sourcepos.start.line);
+                    if !definitions.is_empty() {
+                        self.push_blank_line();
+                        self.output.push_str(&definitions.join("\n"));
+                        self.output.push_str("\n\n");
+                    }
+                    last_line = sourcepos.end.line.max(sourcepos.start.line);
+                    self.render_node(child);
+                }
+                let definitions = self.link_definitions(last_line + 1, self.source_lines.len() + 1);
+                if !definitions.is_empty() {
+                    self.push_blank_line();
+                    self.output.push_str(&definitions.join("\n"));
+                    self.output.push('\n');
+                }
+            }
+            NodeValue::FrontMatter(front_matter) => {
+                self.output.push_str(front_matter.trim_end());
+                self.output.push_str("\n\n");
+            }
+            NodeValue::Heading(heading_data) if heading_data.setext && self.preserve_setext => {
+                let start = self.output.len();
                 for child in node.children() {
                     self.render_node(child);
                 }
+                let width = self.output[start..]
+                    .lines()
+                    .map(UnicodeWidthStr::width)
+                    .max()
+                    .unwrap_or(0);
+                let underline_char = if heading_data.level == 1 { '=' } else { '-' };
+                // Keep the underline as written when the source has it.
+                let end_line = node.data.borrow().sourcepos.end.line;
+                let underline = match self.source_lines.get(end_line.wrapping_sub(1)) {
+                    Some(line)
+                        if line.trim().chars().all(|c| c == underline_char)
+                            && !line.trim().is_empty() =>
+                    {
+                        line.trim().to_string()
+                    }
+                    _ => underline_char.to_string().repeat(width.max(3)),
+                };
+                self.output.push('\n');
+                self.output.push_str(&underline);
+                self.output.push('\n');
+                if self.should_add_blank_line_after_heading(node) {
+                    self.output.push('\n');
+                }
             }
             NodeValue::Heading(heading_data) => {
                 self.output.push_str(&"#".repeat(heading_data.level.into()));
                 self.output.push(' ');
+                // The lines of a setext heading are joined.
+                self.in_atx_heading = true;
                 for child in node.children() {
                     self.render_node(child);
                 }
+                self.in_atx_heading = false;
                 self.output.push('\n');
                 if self.should_add_blank_line_after_heading(node) {
                     self.output.push('\n');
                 }
             }
             NodeValue::Paragraph => {
+                let start = self.output.len();
                 for child in node.children() {
                     self.render_node(child);
                 }
+                if let Wrap::Width(width) = self.wrap {
+                    let text = self.output.split_off(start);
+                    let width = width.saturating_sub(self.content_column);
+                    self.output.push_str(&wrap_text(&text, width));
+                }
                 if !self.is_in_list() {
                     self.output.push('\n');
                     if self.should_add_blank_line_after_paragraph(node) {
@@ -47,61 +404,137 @@ impl Printer {
                 }
             }
             NodeValue::List(list_data) => {
-                match list_data.list_type {
-                    comrak::nodes::ListType::Ordered => {
-                        self.list_stack.push(list_data.start as i32);
-                        for child in node.children() {
-                            self.render_node(child);
-                        }
-                        self.list_stack.pop();
-                    }
-                    comrak::nodes::ListType::Bullet => {
-                        self.list_stack.push(-1); // Use -1 to indicate bullet list
-                        for child in node.children() {
-                            self.render_node(child);
-                        }
-                        self.list_stack.pop();
+                let marker = match list_data.list_type {
+                    ListType::Ordered => ListMarker::Ordered {
+                        next: list_data.start as i32,
+                        delimiter: match self.list_delimiter(list_data) {
+                            ListDelimType::Period => '.',
+                            ListDelimType::Paren => ')',
+                        },
+                        lazy: self
+                            .lazy_lists
+                            .contains(&node.data.borrow().sourcepos.start),
+                        width: if self.pad_numbers {
+                            let last = list_data.start + node.children().count().saturating_sub(1);
+                            last.min(MAX_LIST_NUMBER).to_string().len()
+                        } else {
+                            0
+                        },
+                    },
+                    ListType::Bullet => ListMarker::Bullet(self.list_bullet(list_data)),
+                };
+                self.list_stack.push(marker);
+                for (index, child) in node.children().enumerate() {
+                    // Loose lists keep a blank line between their items.
+                    if index > 0 && !list_data.tight {
+                        self.output.push('\n');
                     }
+                    self.render_node(child);
                 }
-                if !self.is_in_list() && self.should_add_blank_line_after_list(node) {
+                // Indented any further than the last item's marker, the
+                // comment would belong to the item.
+                let last_offset = node
+                    .last_child()
+                    .map_or(0, |item| self.item_marker_offset(item));
+                self.list_stack.pop();
+                if self.would_merge_with_next_list(node, list_data) {
+                    // Nothing but a comment keeps two lists with the same
+                    // markers apart.
+                    self.output.push('\n');
+                    self.output.push_str(&self.indentation(last_offset));
+                    self.output.push_str("<!-- end list -->\n\n");
+                } else if !self.is_in_list() && self.should_add_blank_line_after_list(node) {
                     self.output.push('\n');
                 }
             }
-            NodeValue::Item(_) => {
-                if let Some(counter_val) = self.list_stack.last().copied() {
-                    // Get indentation from source position if available
-                    let indent = self.get_item_indentation(node);
+            NodeValue::Item(_) | NodeValue::TaskItem(_) => {
+                if let Some(marker) = self.list_stack.last().copied() {
+                    let marker_offset = self.item_marker_offset(node);
+                    let indent = self.indentation(marker_offset);
 
-                    if counter_val == -1 {
-                        // Bullet list item
-                        self.output.push_str(&format!("{}- ", indent));
-                    } else {
-                        // Ordered list item
-                        self.output
-                            .push_str(&format!("{}{}. ", indent, counter_val));
-
-                        // Update counter after using it
-                        if let Some(counter) = self.list_stack.last_mut() {
-                            *counter += 1;
+                    self.output.push_str(&indent);
+                    // Written straight into the output, as this runs for
+                    // every item of a document.
+                    let marker_start = self.output.len();
+                    match marker {
+                        ListMarker::Bullet(bullet) => {
+                            self.output.push(bullet);
+                            self.output.push(' ');
+                        }
+                        ListMarker::Ordered {
+                            next,
+                            delimiter,
+                            width,
+                            lazy,
+                        } => {
+                            // Update counter after using it, stopping at the
+                            // largest number still read as a list marker.
+                            if let Some(ListMarker::Ordered { next, .. }) =
+                                self.list_stack.last_mut()
+                                && !lazy
+                            {
+                                *next = next.saturating_add(1).min(MAX_LIST_NUMBER as i32);
+                            }
+                            let _ = write!(self.output, "{:>width$}{} ", next, delimiter);
                         }
                     }
+                    let marker_width = self.output.len() - marker_start;
 
-                    for child in node.children() {
-                        self.render_node(child);
+                    // Continuation lines line up with the item's content,
+                    // which may contain nested lists and regions.
+                    let content_width = marker_offset + marker_width;
+                    let nested_offset = self.nested_offset;
+                    if let Some(indent) = self.nested_indent {
+                        self.nested_offset = indent.saturating_sub(marker_width);
+                    }
+                    self.content_column += content_width;
+                    let content = self.render_item_content(node);
+                    self.content_column -= content_width;
+                    self.nested_offset = nested_offset;
+                    if let NodeValue::TaskItem(symbol) = node.data.borrow().value {
+                        self.output.push('[');
+                        self.output.push(symbol.unwrap_or(' '));
+                        self.output.push_str("] ");
+                    }
+                    let content_indent = self.indentation(content_width);
+                    for (index, line) in content.lines().enumerate() {
+                        if index > 0 {
+                            self.output.push('\n');
+                            if !line.is_empty() {
+                                self.output.push_str(&content_indent);
+                            }
+                        }
+                        self.output.push_str(line);
                     }
                     self.output.push('\n');
                 }
             }
             NodeValue::Text(text) => {
-                self.output.push_str(text);
+                // Text that reads the same in the source, escapes aside, is
+                // written as it is there; anything else, such as resolved
+                // expressions, is escaped so that it parses back the same.
+                // Text continuing a paragraph may have been indented in the
+                // source, which kept it from starting a block.
+                let at_line_start = self.starts_line(node);
+                let source = self.source_text(node).filter(|source| {
+                    unescape(source) == *text
+                        && !(at_line_start && leading_escape(source).is_some())
+                });
+                match source {
+                    Some(source) => self.output.push_str(source),
+                    None => self.output.push_str(&escape_text(text, at_line_start)),
+                }
             }
             NodeValue::SoftBreak => {
-                if self.is_in_list() {
+                if self.joins_soft_breaks() {
                     self.output.push(' ');
                 } else {
                     self.output.push('\n');
                 }
             }
+            NodeValue::LineBreak if self.in_atx_heading => {
+                self.output.push(' ');
+            }
             NodeValue::LineBreak => {
                 self.output.push_str("  \n");
             }
@@ -110,38 +543,228 @@ impl Printer {
                 if !self.output.ends_with('\n') {
                     self.output.push('\n');
                 }
+                if !self.is_in_list()
+                    && self.should_add_blank_line_after_html_block(node, html_block.block_type)
+                {
+                    self.output.push('\n');
+                }
             }
             NodeValue::HtmlInline(html) => {
                 self.output.push_str(html);
             }
-            NodeValue::Image(link_data) => {
-                self.output.push_str("![");
-                for child in node.children() {
-                    self.render_node(child);
+            NodeValue::CodeBlock(code_block) => {
+                if code_block.fenced {
+                    let fence = (code_block.fence_char as char)
+                        .to_string()
+                        .repeat(code_block.fence_length);
+                    self.output.push_str(&fence);
+                    self.output.push_str(&code_block.info);
+                    self.output.push('\n');
+                    self.output.push_str(&code_block.literal);
+                    self.output.push_str(&fence);
+                    self.output.push('\n');
+                } else {
+                    for line in code_block.literal.lines() {
+                        if !line.is_empty() {
+                            self.output.push_str("    ");
+                        }
+                        self.output.push_str(line);
+                        self.output.push('\n');
+                    }
                 }
-                self.output.push_str("](");
-                self.output.push_str(&link_data.url);
-                if !link_data.title.is_empty() {
-                    self.output.push_str(" \"");
-                    self.output.push_str(&link_data.title);
-                    self.output.push('"');
+                if !self.is_in_list() && node.next_sibling().is_some() {
+                    self.output.push('\n');
+                }
+            }
+            NodeValue::ThematicBreak => {
+                // Keep the break as written: `***` may follow a paragraph
+                // where `---` would underline it as a heading.
+                let line = node.data.borrow().sourcepos.start.line;
+                let source = self
+                    .source_lines
+                    .get(line.wrapping_sub(1))
+                    .map(|line| line.trim_start_matches([' ', '\t', '>']).trim_end());
+                match source {
+                    Some(source) if is_thematic_break(source) => self.output.push_str(source),
+                    _ => self.output.push_str("***"),
+                }
+                self.output.push('\n');
+                if !self.is_in_list() && node.next_sibling().is_some() {
+                    self.output.push('\n');
+                }
+            }
+            NodeValue::Table(table) => {
+                self.render_table(node, table);
+                if !self.is_in_list() && node.next_sibling().is_some() {
+                    self.output.push('\n');
+                }
+            }
+            NodeValue::BlockQuote | NodeValue::Alert(_) => {
+                self.content_column += 2;
+                let mut content = self.render_blocks(node).join("\n\n");
+                if let NodeValue::Alert(alert) = &node.data.borrow().value {
+                    content.insert_str(0, &self.alert_heading(node, alert));
+                }
+                self.content_column -= 2;
+                if content.is_empty() {
+                    // An empty quote is still a block of its own.
+                    self.output.push_str(">\n");
+                }
+                for line in content.lines() {
+                    self.output.push('>');
+                    if !line.is_empty() {
+                        self.output.push(' ');
+                        // Tabs only indent from the start of a line.
+                        self.output.push_str(&line.replace(INDENT_PLACEHOLDER, " "));
+                    }
+                    self.output.push('\n');
+                }
+                if !self.is_in_list()
+                    && node.next_sibling().is_some_and(|next| {
+                        !interrupts_paragraph(next) || self.blank_line_before(next)
+                    })
+                {
+                    self.output.push('\n');
+                }
+            }
+            NodeValue::DescriptionList => {
+                for (index, item) in node.children().enumerate() {
+                    if index > 0 {
+                        self.output.push('\n');
+                    }
+                    self.render_node(item);
+                }
+                if !self.is_in_list() && node.next_sibling().is_some() {
+                    self.output.push('\n');
                 }
-                self.output.push(')');
             }
-            NodeValue::Link(link_data) => {
-                self.output.push('[');
+            NodeValue::DescriptionItem(item) => {
+                let separator = if item.tight { "\n" } else { "\n\n" };
+                let content = self.render_blocks(node).join(separator);
+                self.output.push_str(&content);
+                self.output.push('\n');
+            }
+            NodeValue::DescriptionTerm => {
+                let content = self.render_blocks(node).join("\n\n");
+                self.output.push_str(&content);
+            }
+            NodeValue::DescriptionDetails => {
+                let padding = node
+                    .parent()
+                    .map_or(2, |item| match &item.data.borrow().value {
+                        NodeValue::DescriptionItem(item) => item.padding.max(2),
+                        _ => 2,
+                    });
+                self.content_column += padding;
+                let content = self.render_blocks(node).join("\n\n");
+                self.content_column -= padding;
+                let indent = self.indentation(padding);
+                self.output.push(':');
+                self.output.push_str(&" ".repeat(padding - 1));
+                for (index, line) in content.lines().enumerate() {
+                    if index > 0 {
+                        self.output.push('\n');
+                        if !line.is_empty() {
+                            self.output.push_str(&indent);
+                        }
+                    }
+                    self.output.push_str(line);
+                }
+            }
+            NodeValue::WikiLink(wiki_link) => {
+                let start = self.output.len();
                 for child in node.children() {
                     self.render_node(child);
                 }
-                self.output.push_str("](");
-                self.output.push_str(&link_data.url);
-                if !link_data.title.is_empty() {
-                    self.output.push_str(" \"");
-                    self.output.push_str(&link_data.title);
-                    self.output.push('"');
+                let title = self.output.split_off(start);
+                self.output.push_str("[[");
+                self.output.push_str(&wiki_link.url);
+                if title != wiki_link.url {
+                    self.output.push('|');
+                    self.output.push_str(&title);
                 }
-                self.output.push(')');
+                self.output.push_str("]]");
+            }
+            NodeValue::FootnoteReference(reference) => {
+                self.output.push_str("[^");
+                self.output.push_str(&reference.name);
+                self.output.push(']');
+            }
+            NodeValue::FootnoteDefinition(definition) => {
+                self.push_blank_line();
+                self.content_column += 4;
+                let content = self.render_blocks(node).join("\n\n");
+                self.content_column -= 4;
+                self.output.push_str("[^");
+                self.output.push_str(&definition.name);
+                self.output.push_str("]: ");
+                let indent = self.indentation(4);
+                for (index, line) in content.lines().enumerate() {
+                    if index > 0 {
+                        self.output.push('\n');
+                        if !line.is_empty() {
+                            self.output.push_str(&indent);
+                        }
+                    }
+                    self.output.push_str(line);
+                }
+                self.output.push('\n');
+                if node.next_sibling().is_some() {
+                    self.output.push('\n');
+                }
+            }
+            NodeValue::Math(math) => {
+                let delimiter = if math.display_math { "$$" } else { "$" };
+                self.output.push_str(delimiter);
+                self.output.push_str(&math.literal);
+                self.output.push_str(delimiter);
+            }
+            NodeValue::Code(code) => {
+                self.render_code_span(&code.literal);
+            }
+            NodeValue::Emph => {
+                let delimiter = self.emphasis_delimiter(node).to_string();
+                self.render_delimited(node, &delimiter);
+            }
+            NodeValue::Strong => {
+                let delimiter = self.emphasis_delimiter(node).to_string().repeat(2);
+                self.render_delimited(node, &delimiter);
+            }
+            NodeValue::Strikethrough => {
+                let single =
+                    self.source_starts_with(node, "~") && !self.source_starts_with(node, "~~");
+                let delimiter = if single { "~" } else { "~~" };
+                self.render_delimited(node, delimiter);
+            }
+            NodeValue::Superscript => self.render_delimited(node, "^"),
+            NodeValue::Subscript => self.render_delimited(node, "~"),
+            NodeValue::SpoileredText => self.render_delimited(node, "||"),
+            NodeValue::Image(link_data) => {
+                self.output.push_str("![");
+                self.render_link_text(node);
+                self.render_link_target(node, &link_data.url, &link_data.title);
             }
+            NodeValue::Link(link_data) => match self.source_byte(node) {
+                // `<https://example.com>` autolinks
+                Some(b'<') => {
+                    self.output.push('<');
+                    for child in node.children() {
+                        self.render_node(child);
+                    }
+                    self.output.push('>');
+                }
+                // Bare URLs recognized by the autolink extension
+                Some(byte) if byte != b'[' => {
+                    for child in node.children() {
+                        self.render_node(child);
+                    }
+                }
+                _ => {
+                    self.output.push('[');
+                    self.render_link_text(node);
+                    self.render_link_target(node, &link_data.url, &link_data.title);
+                }
+            },
             _ => {
                 // Handle other node types as needed
                 for child in node.children() {
@@ -151,117 +774,782 @@ impl Printer {
         }
     }
 
-    fn is_in_list(&self) -> bool {
-        !self.list_stack.is_empty()
+    fn render_table<'a>(&mut self, node: &'a AstNode<'a>, table: &NodeTable) {
+        let outer = std::mem::take(&mut self.output);
+        let rows: Vec<Vec<String>> = node
+            .children()
+            .map(|row| {
+                row.children()
+                    .map(|cell| {
+                        for child in cell.children() {
+                            self.render_node(child);
+                        }
+                        std::mem::take(&mut self.output).replace('|', "\\|")
+                    })
+                    .collect()
+            })
+            .collect();
+        self.output = outer;
+
+        let mut widths = vec![3; table.num_columns];
+        if self.pad_tables {
+            for row in &rows {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.width());
+                }
+            }
+        }
+
+        let delimiter_row: Vec<String> = table
+            .alignments
+            .iter()
+            .zip(&widths)
+            .map(|(alignment, &width)| match alignment {
+                TableAlignment::None => "-".repeat(width),
+                TableAlignment::Left => format!(":{}", "-".repeat(width - 1)),
+                TableAlignment::Center => format!(":{}:", "-".repeat(width - 2)),
+                TableAlignment::Right => format!("{}:", "-".repeat(width - 1)),
+            })
+            .collect();
+
+        for (index, row) in rows.iter().enumerate() {
+            self.render_table_row(row, &widths, &table.alignments);
+            if index == 0 {
+                self.render_table_row(&delimiter_row, &widths, &table.alignments);
+            }
+        }
     }
 
-    fn should_add_blank_line_after_heading<'a>(&self, node: &'a AstNode<'a>) -> bool {
-        node.next_sibling().is_some()
+    fn render_table_row(
+        &mut self,
+        cells: &[String],
+        widths: &[usize],
+        alignments: &[TableAlignment],
+    ) {
+        self.output.push('|');
+        for (column, &width) in widths.iter().enumerate() {
+            let cell = cells.get(column).map_or("", String::as_str);
+            let padding = if self.pad_tables {
+                width.saturating_sub(cell.width())
+            } else {
+                0
+            };
+            let (left, right) = match alignments.get(column) {
+                Some(TableAlignment::Right) => (padding, 0),
+                Some(TableAlignment::Center) => (padding / 2, padding - padding / 2),
+                _ => (0, padding),
+            };
+            self.output.push(' ');
+            self.output.push_str(&" ".repeat(left));
+            self.output.push_str(cell);
+            self.output.push_str(&" ".repeat(right));
+            self.output.push_str(" |");
+        }
+        self.output.push('\n');
     }
 
-    fn should_add_blank_line_after_paragraph<'a>(&self, node: &'a AstNode<'a>) -> bool {
-        if let Some(next) = node.next_sibling() {
-            matches!(
-                next.data.borrow().value,
-                NodeValue::List(_) | NodeValue::Heading(_)
-            )
-        } else {
-            false
+    /// Renders what follows the text of a link or image: `(url "title")` for
+    /// inline links, or the label of a reference-style link as written in the
+    /// source.
+    fn render_link_target<'a>(&mut self, node: &'a AstNode<'a>, url: &str, title: &str) {
+        self.output.push(']');
+        match self.reference_label(node) {
+            Some(label) if label.len() > 2 => match self.link_number(node) {
+                Some(number) => self.output.push_str(&format!("[{}]", number)),
+                None => self.output.push_str(label),
+            },
+            Some(label) => self.output.push_str(label),
+            None => {
+                self.output.push('(');
+                self.render_link_destination(url, title);
+                self.output.push(')');
+            }
         }
     }
 
-    fn should_add_blank_line_after_list<'a>(&self, node: &'a AstNode<'a>) -> bool {
-        if let Some(next) = node.next_sibling() {
-            !matches!(next.data.borrow().value, NodeValue::List(_))
-        } else {
-            false
+    /// If `node` is a reference-style link or image in the source, returns
+    /// what follows its text: `[label]` for full references, `[]` for
+    /// collapsed ones, and nothing for shortcut references.
+    fn reference_label<'a>(&self, node: &'a AstNode<'a>) -> Option<&'s str> {
+        let end = node.data.borrow().sourcepos.end;
+        let line = self.source_lines.get(end.line.wrapping_sub(1))?;
+        let source = line.get(..end.column)?;
+        let before_bracket = source.strip_suffix(']')?;
+        if source.ends_with("][]") {
+            return Some("[]");
+        }
+        match before_bracket.rfind('[') {
+            Some(open) if source[..open].ends_with(']') => Some(&source[open..]),
+            _ => Some(""),
         }
     }
 
-    fn get_item_indentation<'a>(&self, node: &'a AstNode<'a>) -> String {
-        // Check if this item has source position info that indicates indentation
-        let start_column = node.data.borrow().sourcepos.start.column;
-        if start_column > 1 {
-            // Calculate indentation based on column position
-            // Assuming each indentation level is 2 spaces and list markers start at column 1, 3, 5, etc.
-            let indent_chars = if start_column > 1 {
-                start_column - 1
-            } else {
-                0
-            };
-            " ".repeat(indent_chars)
-        } else {
-            // Default to no indentation
-            String::new()
+    /// Renders the text of a link or image, which for a shortcut or collapsed
+    /// reference with a renumbered label is the new number.
+    fn render_link_text<'a>(&mut self, node: &'a AstNode<'a>) {
+        if let Some(number) = self.link_number(node)
+            && self
+                .reference_label(node)
+                .is_some_and(|label| label.len() <= 2)
+        {
+            self.output.push_str(&number.to_string());
+            return;
+        }
+        for child in node.children() {
+            self.render_node(child);
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use comrak::{Arena, Options, parse_document};
+    /// Returns the label of a reference-style link or image if it is a
+    /// number: the `[label]` of a full reference, or the text of a shortcut
+    /// or collapsed one.
+    fn numeric_label<'a>(&self, node: &'a AstNode<'a>) -> Option<String> {
+        let label = self.reference_label(node)?;
+        let label = match label
+            .strip_prefix('[')
+            .and_then(|label| label.strip_suffix(']'))
+        {
+            Some(inner) if !inner.is_empty() => inner.trim().to_string(),
+            _ => {
+                let text = node
+                    .first_child()
+                    .filter(|text| text.next_sibling().is_none())?;
+                match &text.data.borrow().value {
+                    NodeValue::Text(text) => text.trim().to_string(),
+                    _ => return None,
+                }
+            }
+        };
+        (!label.is_empty() && label.chars().all(|c| c.is_ascii_digit())).then_some(label)
+    }
 
-    fn test_printer_output(input: &str, expected: &str) {
-        let arena = Arena::new();
-        let options = Options::default();
-        let root = parse_document(&arena, input, &options);
+    /// Returns the new number of a reference-style link or image with a
+    /// numeric label, when links are renumbered.
+    fn link_number<'a>(&self, node: &'a AstNode<'a>) -> Option<usize> {
+        if !self.renumber_links {
+            return None;
+        }
+        self.link_numbers.get(&self.numeric_label(node)?).copied()
+    }
 
-        let mut printer = Printer::new();
-        printer.render_node(root);
-        let result = printer.finish();
+    /// Returns the link reference definitions on the lines from `start` up to
+    /// but excluding `end`, which the parser drops from the document.
+    ///
+    /// With footnotes, the lines of footnote definitions found there are
+    /// returned too: the parser drops definitions that are never referenced.
+    ///
+    /// When links are renumbered, numeric labels are rewritten and each run
+    /// of adjacent definitions is sorted by them.
+    fn link_definitions(&mut self, start: usize, end: usize) -> Vec<String> {
+        let lines = self
+            .source_lines
+            .get(start.saturating_sub(1)..end.saturating_sub(1).min(self.source_lines.len()))
+            .unwrap_or_default()
+            .to_vec();
+        let mut definitions = Vec::new();
+        let mut in_footnote = false;
+        for (index, line) in lines.iter().enumerate() {
+            let line_number = start + index;
+            if self
+                .footnote_lines
+                .iter()
+                .any(|lines| lines.contains(&line_number))
+            {
+                in_footnote = false;
+                continue;
+            }
+            if self.footnotes && line.trim_start().starts_with("[^") && line.contains("]:") {
+                in_footnote = true;
+                definitions.push((None, line.trim_end().to_string()));
+            } else if in_footnote
+                && (!line.trim().is_empty()
+                    || lines[index + 1..]
+                        .iter()
+                        .find(|line| !line.trim().is_empty())
+                        .is_some_and(|next| next.starts_with("    ")))
+            {
+                definitions.push((None, line.trim_end().to_string()));
+            } else {
+                in_footnote = false;
+                if LINK_DEFINITION.is_match(line) {
+                    definitions.push(self.renumber_definition(line.trim()));
+                }
+            }
+        }
+        for run in definitions.chunk_by_mut(|a, b| a.0.is_some() == b.0.is_some()) {
+            run.sort_by_key(|(number, _)| *number);
+        }
+        definitions
+            .into_iter()
+            .map(|(_, definition)| definition)
+            .collect()
+    }
 
-        assert_eq!(result, expected);
+    /// Rewrites the label of a link reference definition if it is a number
+    /// and links are renumbered, returning the number it sorts by along with
+    /// the definition.
+    fn renumber_definition(&mut self, definition: &str) -> (Option<usize>, String) {
+        if !self.renumber_links {
+            return (None, definition.to_string());
+        }
+        let Some(captures) = NUMERIC_DEFINITION.captures(definition) else {
+            return (Some(usize::MAX), definition.to_string());
+        };
+        let next = self.link_numbers.len() + 1;
+        let number = *self
+            .link_numbers
+            .entry(captures[1].to_string())
+            .or_insert(next);
+        let rest = &definition[captures[0].len()..];
+        (Some(number), format!("[{}]:{}", number, rest))
     }
 
-    #[test]
-    fn test_heading_node() {
-        let input = r#"# Main Heading"#;
-        let expected = r#"# Main Heading
-"#;
-        test_printer_output(input, expected);
+    /// Ends the output with a blank line, so that the next block cannot be
+    /// taken as a continuation of the previous one.
+    fn push_blank_line(&mut self) {
+        if self.output.is_empty() || self.output.ends_with("\n\n") {
+            return;
+        }
+        if !self.output.ends_with('\n') {
+            self.output.push('\n');
+        }
+        self.output.push('\n');
     }
 
-    #[test]
-    fn test_heading_with_blank_line() {
-        let input = r#"# Header
+    /// Renders the destination and optional title of an inline link or image,
+    /// escaping whatever would otherwise end them early.
+    fn render_link_destination(&mut self, url: &str, title: &str) {
+        let mut depth = 0i32;
+        let balanced = url.chars().all(|c| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            depth >= 0
+        }) && depth == 0;
+        if url.is_empty() || !balanced || url.contains(|c: char| c.is_whitespace() || c == '<') {
+            self.output.push('<');
+            self.output.push_str(&url.replace('>', "\\>"));
+            self.output.push('>');
+        } else {
+            self.output.push_str(url);
+        }
 
-Some content"#;
-        let expected = r#"# Header
+        if !title.is_empty() {
+            self.output.push_str(" \"");
+            self.output.push_str(&title.replace('"', "\\\""));
+            self.output.push('"');
+        }
+    }
 
-Some content
-"#;
-        test_printer_output(input, expected);
+    /// Renders a code span with a backtick string longer than any run of
+    /// backticks in `literal`.
+    fn render_code_span(&mut self, literal: &str) {
+        let longest_run = literal.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat(longest_run + 1);
+        // Parsing strips one space from each side of a span that starts and
+        // ends with one, and backticks next to the fence would extend it.
+        let padded = literal.starts_with('`')
+            || literal.ends_with('`')
+            || (literal.len() > 1
+                && literal.starts_with(' ')
+                && literal.ends_with(' ')
+                && !literal.trim().is_empty());
+        let padding = if padded { " " } else { "" };
+        self.output.push_str(&fence);
+        self.output.push_str(padding);
+        self.output.push_str(literal);
+        self.output.push_str(padding);
+        self.output.push_str(&fence);
     }
 
-    #[test]
-    fn test_paragraph_node() {
-        let input = r#"This is a simple paragraph."#;
-        let expected = r#"This is a simple paragraph.
-"#;
-        test_printer_output(input, expected);
+    fn render_delimited<'a>(&mut self, node: &'a AstNode<'a>, delimiter: &str) {
+        self.output.push_str(delimiter);
+        for child in node.children() {
+            self.render_node(child);
+        }
+        self.output.push_str(delimiter);
     }
 
-    #[test]
-    fn test_text_node() {
-        let input = r#"Plain text"#;
-        let expected = r#"Plain text
-"#;
-        test_printer_output(input, expected);
+    /// Returns the character `node` was delimited with in the source, `*` or
+    /// `_`, defaulting to `*` for nodes without a usable source position.
+    fn emphasis_delimiter<'a>(&self, node: &'a AstNode<'a>) -> char {
+        match self.source_byte(node) {
+            Some(b'_') => '_',
+            _ => '*',
+        }
     }
 
-    #[test]
-    fn test_ordered_list_node() {
-        let input = r#"1. First item
-2. Second item"#;
-        let expected = r#"1. First item
-2. Second item
-"#;
-        test_printer_output(input, expected);
+    /// Returns `true` if soft breaks are printed as spaces, joining the
+    /// lines of a paragraph.
+    fn joins_soft_breaks(&self) -> bool {
+        if self.in_atx_heading {
+            return true;
+        }
+        match self.wrap {
+            Wrap::Width(_) => true,
+            Wrap::Preserve => self.is_in_list() && !self.preserve_soft_breaks,
+        }
     }
 
-    #[test]
-    fn test_unordered_list_node() {
+    /// Returns `true` if `node` is printed as the first inline on its line.
+    fn starts_line<'a>(&self, node: &'a AstNode<'a>) -> bool {
+        let after_soft_break = node
+            .previous_sibling()
+            .is_some_and(|previous| matches!(previous.data.borrow().value, NodeValue::SoftBreak));
+        starts_line(node) && !self.in_atx_heading && !(after_soft_break && self.joins_soft_breaks())
+    }
+
+    /// Returns the source of `node` if it lies on a single line.
+    fn source_text<'a>(&self, node: &'a AstNode<'a>) -> Option<&'s str> {
+        let sourcepos = node.data.borrow().sourcepos;
+        if sourcepos.start.line != sourcepos.end.line {
+            return None;
+        }
+        self.source_lines
+            .get(sourcepos.start.line.wrapping_sub(1))?
+            .get(sourcepos.start.column.wrapping_sub(1)..sourcepos.end.column)
+    }
+
+    /// Returns `true` if the source at the start of `node` begins with
+    /// `prefix`.
+    fn source_starts_with<'a>(&self, node: &'a AstNode<'a>, prefix: &str) -> bool {
+        let start = node.data.borrow().sourcepos.start;
+        self.source_lines
+            .get(start.line.wrapping_sub(1))
+            .and_then(|line| line.get(start.column.wrapping_sub(1)..))
+            .is_some_and(|rest| rest.starts_with(prefix))
+    }
+
+    /// Returns the byte of the source `node` starts at, if the printer knows
+    /// the source.
+    fn source_byte<'a>(&self, node: &'a AstNode<'a>) -> Option<u8> {
+        let start = node.data.borrow().sourcepos.start;
+        self.source_lines
+            .get(start.line.wrapping_sub(1))
+            .and_then(|line| line.as_bytes().get(start.column.wrapping_sub(1)))
+            .copied()
+    }
+
+    fn is_in_list(&self) -> bool {
+        !self.list_stack.is_empty()
+    }
+
+    fn should_add_blank_line_after_heading<'a>(&self, node: &'a AstNode<'a>) -> bool {
+        node.next_sibling().is_some()
+    }
+
+    fn should_add_blank_line_after_paragraph<'a>(&self, node: &'a AstNode<'a>) -> bool {
+        if let Some(next) = node.next_sibling() {
+            // Consecutive paragraphs would merge without a blank line; HTML
+            // blocks that can interrupt a paragraph, such as region markers,
+            // may follow one directly if they do in the source.
+            !interrupts_paragraph(next) || self.blank_line_before(next)
+        } else {
+            false
+        }
+    }
+
+    fn should_add_blank_line_after_list<'a>(&self, node: &'a AstNode<'a>) -> bool {
+        if let Some(next) = node.next_sibling() {
+            // A closing marker or a list with other markers directly under a
+            // list stays there; the list ends before it either way.
+            let optional = interrupts_paragraph(next)
+                || matches!(next.data.borrow().value, NodeValue::List(_));
+            !optional || self.blank_line_before(next)
+        } else {
+            false
+        }
+    }
+
+    /// Whether the source has a blank line right before `next`, ignoring
+    /// block quote markers. Blocks without a source position, such as ones
+    /// added by a transform, are taken to have one.
+    fn blank_line_before<'a>(&self, next: &'a AstNode<'a>) -> bool {
+        let start = next.data.borrow().sourcepos.start.line;
+        start < 2
+            || self.source_lines.get(start - 2).is_none_or(|line| {
+                line.trim_start_matches(|c: char| c == '>' || c.is_whitespace())
+                    .is_empty()
+            })
+    }
+
+    /// Whether the list following `node`, if any, is written with the same
+    /// kind of markers, so that the two would be parsed as one list.
+    fn would_merge_with_next_list<'a>(&self, node: &'a AstNode<'a>, list: &NodeList) -> bool {
+        let Some(next) = node.next_sibling() else {
+            return false;
+        };
+        let NodeValue::List(next_list) = &next.data.borrow().value else {
+            return false;
+        };
+        next_list.list_type == list.list_type
+            && match list.list_type {
+                ListType::Ordered => self.list_delimiter(next_list) == self.list_delimiter(list),
+                ListType::Bullet => self.list_bullet(next_list) == self.list_bullet(list),
+            }
+    }
+
+    /// The marker `list` is written with.
+    fn list_bullet(&self, list: &NodeList) -> char {
+        self.bullet.unwrap_or(match list.bullet_char {
+            bullet @ (b'*' | b'+') => bullet as char,
+            _ => '-',
+        })
+    }
+
+    /// The delimiter `list` is written with.
+    fn list_delimiter(&self, list: &NodeList) -> ListDelimType {
+        self.delimiter.unwrap_or(list.delimiter)
+    }
+
+    fn should_add_blank_line_after_html_block<'a>(
+        &self,
+        node: &'a AstNode<'a>,
+        block_type: u8,
+    ) -> bool {
+        // Blocks of types 6 and 7, such as `<div>`, only end at a blank line.
+        node.next_sibling()
+            .is_some_and(|next| matches!(block_type, 6 | 7) || self.blank_line_before(next))
+    }
+
+    /// Renders each block child of `node` on its own, without trailing line
+    /// breaks.
+    fn render_blocks<'a>(&mut self, node: &'a AstNode<'a>) -> Vec<String> {
+        let outer = std::mem::take(&mut self.output);
+        let blocks = node
+            .children()
+            .map(|child| {
+                self.render_node(child);
+                std::mem::take(&mut self.output).trim_end().to_string()
+            })
+            .collect();
+        self.output = outer;
+        blocks
+    }
+
+    /// Returns the first line of an alert, `[!NOTE]` followed by the
+    /// alert's title if it has one, spelling the type as in the source.
+    fn alert_heading<'a>(&self, node: &'a AstNode<'a>, alert: &NodeAlert) -> String {
+        let start = node.data.borrow().sourcepos.start;
+        let source_type = self
+            .source_lines
+            .get(start.line.saturating_sub(1))
+            .and_then(|line| {
+                let open = line.find("[!")?;
+                let close = open + line[open..].find(']')?;
+                Some(line[open + 2..close].to_string())
+            })
+            .filter(|name| name.eq_ignore_ascii_case(&alert.alert_type.default_title()));
+        let mut heading = format!(
+            "[!{}]",
+            source_type.unwrap_or_else(|| alert.alert_type.default_title().to_uppercase())
+        );
+        if let Some(title) = &alert.title {
+            heading.push(' ');
+            heading.push_str(title);
+        }
+        heading.push('\n');
+        heading
+    }
+
+    /// Returns the number of spaces before the marker of `item`. Task items
+    /// do not record it, so they use their list's.
+    fn item_marker_offset<'a>(&self, item: &'a AstNode<'a>) -> usize {
+        if self.nested_indent.is_some() && self.list_stack.len() > 1 {
+            return self.nested_offset;
+        }
+        if self.fix_indent {
+            return 0;
+        }
+        // Padded numbers already moved the markers of shorter numbers to the
+        // right; the list starts where its leftmost marker does.
+        if self.pad_numbers
+            && let Some(list) = item.parent()
+            && matches!(
+                &list.data.borrow().value,
+                NodeValue::List(list_data) if list_data.list_type == ListType::Ordered
+            )
+        {
+            return list
+                .children()
+                .filter_map(|child| match &child.data.borrow().value {
+                    NodeValue::Item(item_data) => Some(item_data.marker_offset),
+                    _ => None,
+                })
+                .min()
+                .unwrap_or(0);
+        }
+        if let NodeValue::Item(item_data) = &item.data.borrow().value {
+            return item_data.marker_offset;
+        }
+        match item.parent().map(|list| list.data.borrow().value.clone()) {
+            Some(NodeValue::List(list_data)) => list_data.marker_offset,
+            _ => 0,
+        }
+    }
+
+    /// Renders the block children of a list item, separated by a line break,
+    /// or by a blank line if the list is loose.
+    fn render_item_content<'a>(&mut self, item: &'a AstNode<'a>) -> String {
+        let tight = item
+            .parent()
+            .is_none_or(|list| match &list.data.borrow().value {
+                NodeValue::List(list_data) => list_data.tight,
+                _ => true,
+            });
+        let separator = if tight { "\n" } else { "\n\n" };
+
+        let outer = std::mem::take(&mut self.output);
+        let mut blocks = Vec::new();
+        let mut last_line = item.data.borrow().sourcepos.start.line;
+        for child in item.children() {
+            let sourcepos = child.data.borrow().sourcepos;
+            let definitions = self.link_definitions(last_line + 1, sourcepos.start.line);
+            if !definitions.is_empty() {
+                blocks.push(definitions.join("\n"));
+            }
+            last_line = sourcepos.end.line.max(sourcepos.start.line);
+            self.render_node(child);
+            let mut block = std::mem::take(&mut self.output);
+            block.truncate(block.trim_end().len());
+            blocks.push(block);
+        }
+        let end_line = item.data.borrow().sourcepos.end.line;
+        let definitions = self.link_definitions(last_line + 1, end_line + 1);
+        if !definitions.is_empty() {
+            blocks.push(definitions.join("\n"));
+        }
+        self.output = outer;
+        blocks.join(separator)
+    }
+}
+
+/// Whether `node` is an HTML block that can interrupt a paragraph, and so can
+/// follow a paragraph or a list without a blank line.
+fn interrupts_paragraph<'a>(node: &'a AstNode<'a>) -> bool {
+    matches!(
+        &node.data.borrow().value,
+        NodeValue::HtmlBlock(html_block) if html_block.block_type != 7
+    )
+}
+
+/// Returns `true` if `line`, without its indentation, is a thematic break:
+/// three or more `*`, `-` or `_` and nothing else but spaces.
+fn is_thematic_break(line: &str) -> bool {
+    let marks: Vec<char> = line.chars().filter(|c| !matches!(c, ' ' | '\t')).collect();
+    marks.len() >= 3
+        && ['*', '-', '_']
+            .iter()
+            .any(|&mark| marks.iter().all(|&c| c == mark))
+}
+
+/// Breaks the lines of a rendered paragraph at spaces so that they fit in
+/// `width` columns where possible. Hard line breaks are kept, and no line is
+/// made to start with something that would begin a new block, such as `-` or
+/// `1.`.
+fn wrap_text(text: &str, width: usize) -> String {
+    let mut wrapped = String::with_capacity(text.len());
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            wrapped.push('\n');
+        }
+        let (line, hard_break) = match line.strip_suffix("  ") {
+            Some(line) => (line, "  "),
+            None => (line, ""),
+        };
+        let mut column = 0;
+        for (index, word) in line.split(' ').enumerate() {
+            let word_width = word.width();
+            if index > 0 {
+                if column > 0 && column + 1 + word_width > width && !starts_block(word) {
+                    wrapped.push('\n');
+                    column = 0;
+                } else {
+                    wrapped.push(' ');
+                    column += 1;
+                }
+            }
+            wrapped.push_str(word);
+            column += word_width;
+        }
+        wrapped.push_str(hard_break);
+    }
+    wrapped
+}
+
+/// Returns `true` if a line starting with `word` would not continue a
+/// paragraph.
+fn starts_block(word: &str) -> bool {
+    let digits = word.chars().take_while(char::is_ascii_digit).count();
+    word.is_empty()
+        || matches!(word, "-" | "+" | "*" | "=" | ">")
+        || word.starts_with(['#', '>', '|'])
+        || word.starts_with("```")
+        || word.starts_with("~~~")
+        || word
+            .chars()
+            .all(|c| c == '-' || c == '=' || c == '*' || c == '_')
+        || (digits > 0 && digits <= 9 && matches!(&word[digits..], "." | ")"))
+}
+
+/// Returns `true` if `node` is the first inline on its line.
+fn starts_line<'a>(node: &'a AstNode<'a>) -> bool {
+    match node.previous_sibling() {
+        Some(previous) => matches!(
+            previous.data.borrow().value,
+            NodeValue::SoftBreak | NodeValue::LineBreak
+        ),
+        // Nothing at the start of an ATX heading's text can begin a block.
+        None => node.parent().is_some_and(|parent| {
+            matches!(
+                parent.data.borrow().value,
+                NodeValue::Paragraph | NodeValue::Heading(NodeHeading { setext: true, .. })
+            )
+        }),
+    }
+}
+
+/// Returns the index of the character of `text` to escape so that it doesn't
+/// begin a block at the start of a line, such as the `.` of `1.` or a `#`.
+fn leading_escape(text: &str) -> Option<usize> {
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+    match text[digits..].chars().next() {
+        Some('.' | ')') if digits > 0 => Some(digits),
+        Some('#' | '>' | '-' | '+' | '=') if digits == 0 => Some(0),
+        _ => None,
+    }
+}
+
+/// Removes the backslashes that escape ASCII punctuation from `source`.
+fn unescape(source: &str) -> String {
+    let mut text = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next) if c == '\\' && next.is_ascii_punctuation() => {
+                text.push(next);
+                chars.next();
+            }
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+/// Backslash-escapes the characters of `text` that a CommonMark parser would
+/// otherwise take as markup. `at_line_start` also escapes what only has a
+/// meaning at the start of a line, such as `#` or `1.`.
+fn escape_text(text: &str, at_line_start: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut escaped = String::with_capacity(text.len());
+
+    let leading = if at_line_start {
+        leading_escape(text)
+    } else {
+        None
+    };
+
+    for (index, &c) in chars.iter().enumerate() {
+        let previous = index.checked_sub(1).map(|index| chars[index]);
+        let next = chars.get(index + 1).copied();
+        let needs_escape = match c {
+            '*' | '`' | '[' | ']' | '~' | '$' => true,
+            '\\' => next.is_none_or(|next| next.is_ascii_punctuation()),
+            // Underscores inside words never start emphasis.
+            '_' => {
+                !(previous.is_some_and(char::is_alphanumeric)
+                    && next.is_some_and(char::is_alphanumeric))
+            }
+            '<' => next.is_some_and(|next| next.is_ascii_alphabetic() || "/!?".contains(next)),
+            '&' => {
+                let rest: String = chars[index + 1..].iter().take(32).collect();
+                rest.split_once(';').is_some_and(|(name, _)| {
+                    let name = name.strip_prefix('#').unwrap_or(name);
+                    !name.is_empty() && name.chars().all(char::is_alphanumeric)
+                })
+            }
+            _ => leading == Some(index),
+        };
+        if needs_escape {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comrak::{Arena, Options, parse_document};
+
+    fn test_printer_output(input: &str, expected: &str) {
+        let arena = Arena::new();
+        let mut options = Options::default();
+        options.extension.table = true;
+        options.extension.strikethrough = true;
+        options.extension.tasklist = true;
+        options.extension.math_dollars = true;
+        options.extension.description_lists = true;
+        let root = parse_document(&arena, input, &options);
+
+        let mut printer = MarkdownPrinter::with_source(input);
+        printer.render_node(root);
+        let result = printer.finish();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_heading_node() {
+        let input = r#"# Main Heading"#;
+        let expected = r#"# Main Heading
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_heading_with_blank_line() {
+        let input = r#"# Header
+
+Some content"#;
+        let expected = r#"# Header
+
+Some content
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_paragraph_node() {
+        let input = r#"This is a simple paragraph."#;
+        let expected = r#"This is a simple paragraph.
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_text_node() {
+        let input = r#"Plain text"#;
+        let expected = r#"Plain text
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_ordered_list_node() {
+        let input = r#"1. First item
+2. Second item"#;
+        let expected = r#"1. First item
+2. Second item
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_unordered_list_node() {
         let input = r#"- First item
 - Second item
 - Third item"#;
@@ -458,4 +1746,716 @@ HTML comment
 "#;
         test_printer_output(input, expected);
     }
+
+    #[test]
+    fn test_ordered_list_paren_delimiter() {
+        let input = r#"1) First item
+2) Second item"#;
+        let expected = r#"1) First item
+2) Second item
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_nested_lists() {
+        let input = r#"1. First item
+   - Nested bullet
+   - Another bullet
+2. Second item
+    1) Deeply indented"#;
+        let expected = r#"1. First item
+   - Nested bullet
+   - Another bullet
+2. Second item
+    1) Deeply indented
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_loose_item_with_several_blocks() {
+        let input = r#"- First item
+
+  <!-- comment -->
+
+  - Nested
+- Second item"#;
+        let expected = r#"- First item
+
+  <!-- comment -->
+
+  - Nested
+
+- Second item
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_emphasis_and_strong() {
+        let input = r#"Some *emphasis*, _more emphasis_, **strong** and __more strong__."#;
+        let expected = r#"Some *emphasis*, _more emphasis_, **strong** and __more strong__.
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_nested_emphasis_in_list() {
+        let input = r#"- ***Both*** and *emphasis with **strong** inside*
+- _Spanning
+  lines_"#;
+        let expected = r#"- ***Both*** and *emphasis with **strong** inside*
+- _Spanning lines_
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_emphasis_without_source_defaults_to_asterisk() {
+        let arena = Arena::new();
+        let root = parse_document(&arena, "_word_ and __word__", &Options::default());
+        let mut printer = MarkdownPrinter::with_source("");
+        printer.render_node(root);
+        assert_eq!(printer.finish(), "*word* and **word**\n");
+    }
+
+    #[test]
+    fn test_fenced_code_block() {
+        let input = r#"Install it:
+
+~~~~shell title="install"
+cargo install md_ol_util
+```
+~~~~
+
+Done."#;
+        let expected = r#"Install it:
+
+~~~~shell title="install"
+cargo install md_ol_util
+```
+~~~~
+
+Done.
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_code_block_in_list_item() {
+        let input = r#"1. Run:
+
+   ```rust
+   fn main() {
+
+       println!("hi");
+   }
+   ```
+2. Check the output"#;
+        let expected = r#"1. Run:
+
+   ```rust
+   fn main() {
+
+       println!("hi");
+   }
+   ```
+
+2. Check the output
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_indented_code_block() {
+        let input = "Example:\n\n    let x = 1;\n\n    let y = 2;\n\nAfter";
+        let expected = "Example:\n\n    let x = 1;\n\n    let y = 2;\n\nAfter\n";
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_code_spans() {
+        let input = r#"Use `cargo run`, ``a`b``, and `` `tick` ``."#;
+        let expected = r#"Use `cargo run`, ``a`b``, and `` `tick` ``.
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_table() {
+        let input = r#"| Step | Command | Result |
+|:-----|:-------:|---:|
+| 1 | `a \| b` | ok |
+| 2 | *none* |"#;
+        let expected = r#"| Step | Command | Result |
+| :-- | :-: | --: |
+| 1 | `a \| b` | ok |
+| 2 | *none* |  |
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_padded_table() {
+        let arena = Arena::new();
+        let mut options = Options::default();
+        options.extension.table = true;
+        let input = r#"| Step | Command | Result |
+| - | :-: | -: |
+| 10 | run | ok |
+
+After"#;
+        let root = parse_document(&arena, input, &options);
+        let mut printer = MarkdownPrinter::with_source(input).pad_tables(true);
+        printer.render_node(root);
+        assert_eq!(
+            printer.finish(),
+            r#"| Step | Command | Result |
+| ---- | :-----: | -----: |
+| 10   |   run   |     ok |
+
+After
+"#
+        );
+    }
+
+    #[test]
+    fn test_padded_table_with_wide_characters() {
+        let arena = Arena::new();
+        let mut options = Options::default();
+        options.extension.table = true;
+        let input = "| 手順 | Note |\n| - | - |\n| インストール | 🎉 |\n| Run | ok |";
+        let root = parse_document(&arena, input, &options);
+        let mut printer = MarkdownPrinter::with_source(input).pad_tables(true);
+        printer.render_node(root);
+        assert_eq!(
+            printer.finish(),
+            "| 手順         | Note |\n| ------------ | ---- |\n| インストール | 🎉   |\n| Run          | ok   |\n"
+        );
+    }
+
+    #[test]
+    fn test_link_destinations_needing_escapes() {
+        let input = r#"[spaces](<docs/getting started.md>), [parens](https://example.com/a_(b)), [unbalanced](<a(b>), [quote](x "say \"hi\""), [empty](<>)"#;
+        let expected = r#"[spaces](<docs/getting started.md>), [parens](https://example.com/a_(b)), [unbalanced](<a(b>), [quote](x "say \"hi\""), [empty](<>)
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_links_in_list_items() {
+        let input = r#"- See [the guide](guide.md "Guide") and [**bold** link](x.md)
+- Plain"#;
+        let expected = r#"- See [the guide](guide.md "Guide") and [**bold** link](x.md)
+- Plain
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_reference_links_and_definitions() {
+        let input = r#"See [the Guide][Guide], [collapsed][], [shortcut] and ![logo][Logo Image].
+
+- An item linking to [the guide][guide]
+
+[Guide]: https://example.com/guide "The Guide"
+[collapsed]: <https://example.com/a b>
+[shortcut]: /shortcut
+[Logo Image]: logo.png"#;
+        let expected = r#"See [the Guide][Guide], [collapsed][], [shortcut] and ![logo][Logo Image].
+
+- An item linking to [the guide][guide]
+
+[Guide]: https://example.com/guide "The Guide"
+[collapsed]: <https://example.com/a b>
+[shortcut]: /shortcut
+[Logo Image]: logo.png
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_definitions_between_blocks() {
+        let input = r#"# Links
+
+[a]: /a
+[b]: /b
+
+Text with [a] and [b].
+
+- Item
+  
+  [c]: /c
+- Uses [c]"#;
+        let expected = r#"# Links
+
+[a]: /a
+[b]: /b
+
+Text with [a] and [b].
+
+- Item
+
+  [c]: /c
+
+- Uses [c]
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_images_in_list_items() {
+        let input = r#"- ![Screenshot](<shots/step one.png> "First step")
+- ![](empty-alt.png)"#;
+        let expected = r#"- ![Screenshot](<shots/step one.png> "First step")
+- ![](empty-alt.png)
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_angle_bracket_autolinks() {
+        let input = r#"Visit <https://example.com/docs> or mail <team@example.com>."#;
+        let expected = r#"Visit <https://example.com/docs> or mail <team@example.com>.
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_bare_url_autolinks() {
+        let arena = Arena::new();
+        let mut options = Options::default();
+        options.extension.autolink = true;
+        let input = "- See www.example.com, https://example.com/a. and team@example.com";
+        let root = parse_document(&arena, input, &options);
+        let mut printer = MarkdownPrinter::with_source(input);
+        printer.render_node(root);
+        assert_eq!(printer.finish(), format!("{}\n", input));
+    }
+
+    #[test]
+    fn test_strikethrough() {
+        let input = r#"- ~~Old step~~ and ~single~ tildes"#;
+        let expected = r#"- ~~Old step~~ and ~single~ tildes
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_task_list() {
+        let input = r#"- [ ] Open the file
+- [x] Edit it
+  - [X] Nested and checked
+- Not a task"#;
+        let expected = r#"- [ ] Open the file
+- [x] Edit it
+  - [X] Nested and checked
+- Not a task
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_setext_headings_become_atx_by_default() {
+        let input = "Title\n=====\n\nSection\n---\n\nText";
+        test_printer_output(input, "# Title\n\n## Section\n\nText\n");
+    }
+
+    #[test]
+    fn test_preserved_setext_headings() {
+        let arena = Arena::new();
+        let input = "Title\n=====\n\nA longer section\n---\n\n## Already ATX\n\nText";
+        let root = parse_document(&arena, input, &Options::default());
+        let mut printer = MarkdownPrinter::with_source(input).preserve_setext(true);
+        printer.render_node(root);
+        assert_eq!(
+            printer.finish(),
+            "Title\n=====\n\nA longer section\n---\n\n## Already ATX\n\nText\n"
+        );
+
+        let mut printer = MarkdownPrinter::with_source("").preserve_setext(true);
+        printer.render_node(root);
+        assert_eq!(
+            printer.finish(),
+            "Title\n=====\n\nA longer section\n----------------\n\n## Already ATX\n\nText\n"
+        );
+    }
+
+    #[test]
+    fn test_tab_indented_lists() {
+        let input = "- First\n\t- Nested\n\t\t- Deeper\n- Second";
+        let expected = "- First\n\t- Nested\n\t\t- Deeper\n- Second\n";
+        test_printer_output(input, expected);
+
+        let input = "- First\n\t- Nested\n\t  ```\n\t      code\n\t  ```\n- Second";
+        let expected = "- First\n\t- Nested\n\t  ```\n\t      code\n\t  ```\n- Second\n";
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_tab_width() {
+        let arena = Arena::new();
+        let input = "1. First\n\t- Nested";
+        let root = parse_document(&arena, input, &Options::default());
+        let mut printer = MarkdownPrinter::with_source(input).tab_width(2);
+        printer.render_node(root);
+        assert_eq!(printer.finish(), "1. First\n\t\t- Nested\n");
+    }
+
+    #[test]
+    fn test_backslash_escapes_are_kept() {
+        let input = r#"\*not emphasis\* and \_this\_, snake_case, \[not a link\], &amp;copy;
+\# not a heading
+1\. not a list"#;
+        let expected = r#"\*not emphasis\* and \_this\_, snake_case, \[not a link\], \&copy;
+\# not a heading
+1\. not a list
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_text_without_escapes_is_untouched() {
+        let input = r#"C:\path, a_b_c, 3 < 4 & [brackets] *"#;
+        let expected = r#"C:\path, a_b_c, 3 < 4 & [brackets] *
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_escape_text() {
+        assert_eq!(escape_text("*a* _b_ c_d", false), r"\*a\* \_b\_ c_d");
+        assert_eq!(escape_text("# x", true), r"\# x");
+        assert_eq!(escape_text("# x", false), "# x");
+        assert_eq!(escape_text("12) x", true), r"12\) x");
+        assert_eq!(escape_text(r"<div> &amp; a\b", false), r"\<div> \&amp; a\b");
+    }
+
+    #[test]
+    fn test_math() {
+        let input = r#"Inline $a+b$ and $$x^2$$, costs \$5.
+
+$$
+e = mc^2
+$$
+
+- Item with $\frac{1}{2}$"#;
+        let expected = r#"Inline $a+b$ and $$x^2$$, costs \$5.
+
+$$
+e = mc^2
+$$
+
+- Item with $\frac{1}{2}$
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_consecutive_paragraphs() {
+        let input = r#"First paragraph.
+
+Second paragraph.
+<!-- comment -->
+
+<span>Inline HTML block</span>"#;
+        let expected = r#"First paragraph.
+
+Second paragraph.
+<!-- comment -->
+
+<span>Inline HTML block</span>
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_wikilinks() {
+        let arena = Arena::new();
+        let mut options = Options::default();
+        options.extension.wikilinks_title_after_pipe = true;
+        let input = "- See [[Page Name]] and [[Other Page|its title]]";
+        let root = parse_document(&arena, input, &options);
+        let mut printer = MarkdownPrinter::with_source(input);
+        printer.render_node(root);
+        assert_eq!(printer.finish(), format!("{}\n", input));
+    }
+
+    #[test]
+    fn test_description_lists() {
+        let input = r#"Term one
+: Details one
+  continued
+
+Term two
+
+:   Details two
+
+    More details
+
+After"#;
+        let expected = r#"Term one
+: Details one
+  continued
+
+Term two
+
+:   Details two
+
+    More details
+
+After
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_multi_paragraph_items() {
+        let input = r#"1. First paragraph
+   of the first item.
+
+   Second paragraph.
+
+   > A note
+
+2. Second item
+
+   ```
+   code
+   ```
+3. Third item"#;
+        let expected = r#"1. First paragraph of the first item.
+
+   Second paragraph.
+
+   > A note
+
+2. Second item
+
+   ```
+   code
+   ```
+
+3. Third item
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_deeply_nested_lists() {
+        let input = r#"- Level one
+  1. Level two
+     - Level three
+       1) Level four
+       2) Level four again
+     - Level three again
+  2. Level two again
+- Level one again"#;
+        let expected = r#"- Level one
+  1. Level two
+     - Level three
+       1) Level four
+       2) Level four again
+     - Level three again
+  2. Level two again
+- Level one again
+"#;
+        test_printer_output(input, expected);
+    }
+
+    fn wrapped_output(input: &str, width: usize) -> String {
+        let arena = Arena::new();
+        let root = parse_document(&arena, input, &Options::default());
+        let mut printer = MarkdownPrinter::with_source(input).wrap(Wrap::Width(width));
+        printer.render_node(root);
+        printer.finish()
+    }
+
+    #[test]
+    fn test_wrap_paragraphs() {
+        let input = "The quick brown fox\njumps over the lazy dog and keeps running.";
+        assert_eq!(
+            wrapped_output(input, 20),
+            "The quick brown fox\njumps over the lazy\ndog and keeps\nrunning.\n"
+        );
+    }
+
+    #[test]
+    fn test_wrap_list_items_with_continuation_indent() {
+        let input = "1. The quick brown fox jumps over the lazy dog\n   - Nested item with quite a lot of words";
+        assert_eq!(
+            wrapped_output(input, 20),
+            "1. The quick brown\n   fox jumps over\n   the lazy dog\n   - Nested item\n     with quite a\n     lot of words\n"
+        );
+    }
+
+    #[test]
+    fn test_wrap_counts_wide_characters_as_two_columns() {
+        let input = "日本語の 文書を 折り返す";
+        assert_eq!(wrapped_output(input, 16), "日本語の 文書を\n折り返す\n");
+    }
+
+    #[test]
+    fn test_wrap_keeps_hard_breaks_and_block_starts() {
+        let input = "Line one  \nis short but this one counts - 1. and more";
+        assert_eq!(
+            wrapped_output(input, 30),
+            "Line one  \nis short but this one counts - 1.\nand more\n"
+        );
+    }
+
+    #[test]
+    fn test_starts_block() {
+        assert!(starts_block("-"));
+        assert!(starts_block("12."));
+        assert!(starts_block("#heading"));
+        assert!(starts_block("---"));
+        assert!(!starts_block("word"));
+        assert!(!starts_block("1.5"));
+    }
+
+    #[test]
+    fn test_preserve_soft_breaks_in_list_items() {
+        let input = "- First line\n  second line\n  - Nested first\n    nested second\n- Other";
+        let arena = Arena::new();
+        let root = parse_document(&arena, input, &Options::default());
+        let mut printer = MarkdownPrinter::with_source(input).preserve_soft_breaks(true);
+        printer.render_node(root);
+        assert_eq!(printer.finish(), format!("{}\n", input));
+
+        test_printer_output(
+            input,
+            "- First line second line\n  - Nested first nested second\n- Other\n",
+        );
+    }
+
+    #[test]
+    fn test_bullet_characters_are_kept() {
+        let input = r#"* Star
+* Star again
+
++ Plus
+  - Nested dash
+    * Nested star"#;
+        let expected = r#"* Star
+* Star again
+
++ Plus
+  - Nested dash
+    * Nested star
+"#;
+        test_printer_output(input, expected);
+    }
+
+    #[test]
+    fn test_padded_numbers() {
+        let input = "8. Eight\n9. Nine\n   continued\n10. Ten\n    - Nested\n11. Eleven";
+        let arena = Arena::new();
+        let root = parse_document(&arena, input, &Options::default());
+        let mut printer = MarkdownPrinter::with_source(input)
+            .pad_numbers(true)
+            .preserve_soft_breaks(true);
+        printer.render_node(root);
+        assert_eq!(
+            printer.finish(),
+            " 8. Eight\n 9. Nine\n    continued\n10. Ten\n    - Nested\n11. Eleven\n"
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let arena = Arena::new();
+        let mut options = Options::default();
+        options.extension.footnotes = true;
+        let input = "Text with a note[^1] and another[^long].\n\n[^1]: The note.\n\n[^long]: A longer note.\n\n    With a second paragraph.";
+        let root = parse_document(&arena, input, &options);
+        let mut printer = MarkdownPrinter::with_source(input);
+        printer.render_node(root);
+        assert_eq!(printer.finish(), format!("{}\n", input));
+    }
+
+    #[test]
+    fn test_nested_indent() {
+        let arena = Arena::new();
+        let input = "- One\n  - Two\n    - Three\n- Four\n\nText\n\n1. Five\n   - Six\n";
+        let root = parse_document(&arena, input, &Options::default());
+
+        let mut printer = MarkdownPrinter::with_source(input).nested_indent(Some(4));
+        printer.render_node(root);
+        assert_eq!(
+            printer.finish(),
+            "- One\n    - Two\n        - Three\n- Four\n\nText\n\n1. Five\n    - Six\n"
+        );
+
+        let mut printer = MarkdownPrinter::with_source(input).nested_indent(Some(2));
+        printer.render_node(root);
+        assert_eq!(
+            printer.finish(),
+            "- One\n  - Two\n    - Three\n- Four\n\nText\n\n1. Five\n   - Six\n"
+        );
+    }
+
+    #[test]
+    fn test_renumber_links() {
+        let arena = Arena::new();
+        let input = "See [the docs][3] and [1], ![a chart][7] and [2][].\n\n[1]: https://one.example\n[2]: https://two.example\n[3]: https://three.example \"Three\"\n[name]: https://named.example\n[7]: https://seven.example\n[9]: https://unused.example\n\nLater [9] and [named][name].\n";
+        let root = parse_document(&arena, input, &Options::default());
+
+        let mut printer = MarkdownPrinter::with_source(input);
+        printer.render_node(root);
+        assert_eq!(printer.finish(), input);
+
+        let mut printer = MarkdownPrinter::with_source(input).renumber_links(true);
+        printer.render_node(root);
+        assert_eq!(
+            printer.finish(),
+            "See [the docs][1] and [2], ![a chart][3] and [4][].\n\n[1]: https://three.example \"Three\"\n[2]: https://one.example\n[3]: https://seven.example\n[4]: https://two.example\n[5]: https://unused.example\n[name]: https://named.example\n\nLater [5] and [named][name].\n"
+        );
+    }
+
+    #[test]
+    fn test_fix_indent() {
+        let arena = Arena::new();
+        let input = "- a\n - b\n   - c\n    - d\n\n1. x\n  - too shallow\n";
+        let root = parse_document(&arena, input, &Options::default());
+
+        let mut printer = MarkdownPrinter::with_source(input).fix_indent(true);
+        printer.render_node(root);
+        assert_eq!(
+            printer.finish(),
+            "- a\n- b\n  - c\n  - d\n\n1. x\n- too shallow\n"
+        );
+
+        let mut printer = MarkdownPrinter::with_source(input)
+            .fix_indent(true)
+            .nested_indent(Some(4));
+        printer.render_node(root);
+        assert!(printer.finish().starts_with("- a\n- b\n    - c\n    - d\n"));
+    }
+
+    #[test]
+    fn test_bullet_and_delimiter_overrides() {
+        let arena = Arena::new();
+        let input = "- a
+
+* b
+
+1) c
+";
+        let root = parse_document(&arena, input, &Options::default());
+
+        let options = RenderOptions {
+            bullet: Some('+'),
+            delimiter: Some(ListDelimType::Period),
+            ..RenderOptions::default()
+        };
+        let mut printer = MarkdownPrinter::with_options(input, &options);
+        printer.render_node(root);
+        // The two bullet lists now share a marker, so they are kept apart.
+        assert_eq!(
+            printer.finish(),
+            "+ a\n\n<!-- end list -->\n\n+ b\n\n1. c\n"
+        );
+    }
 }
@@ -0,0 +1,117 @@
+use crate::marker::{Marker, MarkerSyntax, marker_syntax, marker_text, parse_marker};
+use crate::transform::new_node;
+use comrak::Arena;
+use comrak::nodes::{AstNode, NodeHtmlBlock, NodeValue};
+
+/// Fills in the `<!-- progress -->` comment right after `list` with how many
+/// of its task items are checked, e.g. `<!-- progress: 2 of 5 complete (40%)
+/// -->`. With `insert`, a comment is added if there is none yet.
+///
+/// Lists without task items are left alone.
+pub(crate) fn update_progress<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    list: &'a AstNode<'a>,
+    syntaxes: &[MarkerSyntax],
+    insert: bool,
+) {
+    let mut total = 0;
+    let mut checked = 0;
+    for item in list.children() {
+        if let NodeValue::TaskItem(symbol) = item.data.borrow().value {
+            total += 1;
+            if symbol.is_some() {
+                checked += 1;
+            }
+        }
+    }
+    if total == 0 {
+        return;
+    }
+    let summary = format!(
+        "progress: {} of {} complete ({}%)",
+        checked,
+        total,
+        checked * 100 / total
+    );
+
+    let existing = list.next_sibling().and_then(|next| {
+        let text = marker_text(next)?;
+        match parse_marker(&text, syntaxes)? {
+            (Marker::Progress, _) => Some((next, marker_syntax(&text, syntaxes)?)),
+            _ => None,
+        }
+    });
+    let (node, syntax) = match existing {
+        Some(existing) => existing,
+        None if insert => {
+            let Some(&syntax) = syntaxes.first() else {
+                return;
+            };
+            let node = match syntax {
+                MarkerSyntax::HtmlComment => new_node(
+                    arena,
+                    NodeValue::HtmlBlock(NodeHtmlBlock {
+                        block_type: 2,
+                        literal: String::new(),
+                    }),
+                ),
+                MarkerSyntax::MdxComment => {
+                    let paragraph = new_node(arena, NodeValue::Paragraph);
+                    paragraph.append(new_node(arena, NodeValue::Text(String::new())));
+                    paragraph
+                }
+            };
+            list.insert_after(node);
+            (node, syntax)
+        }
+        None => return,
+    };
+
+    let comment = syntax.wrap(&summary);
+    match &mut node.data.borrow_mut().value {
+        NodeValue::HtmlBlock(html_block) => html_block.literal = comment + "\n",
+        NodeValue::Paragraph => {
+            if let Some(text) = node.first_child() {
+                text.data.borrow_mut().value = NodeValue::Text(comment);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::TransformOptions;
+    use crate::transform::{transform, transform_with_options};
+
+    #[test]
+    fn test_fill_in_progress_comment() {
+        let input = "<!-- ol -->\n- [x] Tag the release\n- [ ] Publish\n- [x] Announce\n<!-- progress -->\n<!-- /ol -->\n";
+        let expected = "<!-- ol -->\n1. [x] Tag the release\n2. [ ] Publish\n3. [x] Announce\n<!-- progress: 2 of 3 complete (66%) -->\n<!-- /ol -->\n";
+        assert_eq!(transform(input), expected);
+
+        let updated = expected.replace("2. [ ]", "2. [x]");
+        assert_eq!(
+            transform(&updated),
+            updated.replace("2 of 3 complete (66%)", "3 of 3 complete (100%)")
+        );
+    }
+
+    #[test]
+    fn test_insert_progress_comments() {
+        let input = "<!-- ol progress -->\n- [ ] One\n- [x] Two\n\nText\n\n- Not a task list\n<!-- /ol -->\n";
+        let expected = "<!-- ol progress -->\n1. [ ] One\n2. [x] Two\n\n<!-- progress: 1 of 2 complete (50%) -->\n\nText\n\n1. Not a task list\n<!-- /ol -->\n";
+        assert_eq!(transform(input), expected);
+        assert_eq!(transform(expected), expected);
+
+        let options = TransformOptions {
+            task_progress: true,
+            ..TransformOptions::default()
+        };
+        let input = input.replace("ol progress", "ol");
+        assert_eq!(
+            transform_with_options(&input, &options),
+            expected.replace("ol progress", "ol")
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use crate::anchors::MovedAnchors;
+use crate::extract::{DataFormat, parse_data};
+use crate::options::TransformOptions;
+use crate::report::TransformReport;
+use crate::transform::transform_linked;
+use serde::Deserialize;
+
+/// The files of a project, in reading order, as listed in a manifest:
+///
+/// ```yaml
+/// files:
+///   - part-1.md
+///   - part-2.md
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The paths of the files, relative to the manifest.
+    pub files: Vec<String>,
+}
+
+impl Manifest {
+    /// Reads a manifest from JSON or YAML.
+    pub fn from_str(text: &str, format: DataFormat) -> Result<Self, String> {
+        let Some(value) = parse_data(text, format)? else {
+            return Ok(Self::default());
+        };
+        serde_json::from_value(value).map_err(|error| error.to_string())
+    }
+}
+
+/// Transforms `inputs` as consecutive parts of one document, returning the
+/// output and report of each. `continue` regions with no list before them
+/// in their part pick up from the last list of the parts before, so part 2
+/// of a tutorial can start at step 14. `(cur±N)` expressions resolve to
+/// numbers as usual, so they can point at items of earlier parts.
+///
+/// Links between the parts follow the item anchors of named regions, as
+/// they do within a document: once every part is numbered, links such as
+/// `[step 3](part-1.md#setup-step-3)` to anchors that moved in another part
+/// are pointed at their new IDs, with the step number in their text
+/// updated. Anchors are matched by ID alone, so region names should be
+/// unique across the project.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{TransformOptions, transform_project};
+///
+/// let parts = [
+///     "<!-- ol -->\n- Install\n- Configure\n<!-- /ol -->\n",
+///     "<!-- ol continue -->\n- Run, after (cur-1)\n<!-- /ol -->\n",
+/// ];
+/// let outputs = transform_project(&parts, &TransformOptions::default());
+/// assert_eq!(outputs[1].0, "<!-- ol continue -->\n3. Run, after (2)\n<!-- /ol -->\n");
+/// ```
+pub fn transform_project(
+    inputs: &[&str],
+    options: &TransformOptions,
+) -> Vec<(String, TransformReport)> {
+    // The first pass numbers the parts in order, recording where each one
+    // starts and which of its anchors moved.
+    let mut options = options.clone();
+    let mut parts = Vec::new();
+    for input in inputs {
+        let (output, report, moved) = transform_linked(input, &options, &MovedAnchors::new());
+        let part_options = options.clone();
+        options.continue_from = report.next_number;
+        parts.push((output, report, moved, part_options));
+    }
+    // The second pass transforms the parts again where other parts moved
+    // anchors, so that links to them follow.
+    let mut outputs = Vec::new();
+    for (index, input) in inputs.iter().enumerate() {
+        let linked: MovedAnchors = parts
+            .iter()
+            .enumerate()
+            .filter(|&(other, _)| other != index)
+            .flat_map(|(_, (_, _, moved, _))| moved.clone())
+            .collect();
+        let (output, report, _, part_options) = &parts[index];
+        if linked.is_empty() {
+            outputs.push((output.clone(), report.clone()));
+        } else {
+            let (output, report, _) = transform_linked(input, part_options, &linked);
+            outputs.push((output, report));
+        }
+    }
+    outputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbering_continues_across_parts() {
+        let parts = [
+            "<!-- ol -->\n1. Install\n2. Configure\n<!-- /ol -->\n",
+            "No steps here.\n",
+            "<!-- ol continue -->\n- Run\n- Check (cur-2)\n<!-- /ol -->\n\n<!-- ol -->\n- Again\n<!-- /ol -->\n",
+            "<!-- ol continue -->\n- Last\n<!-- /ol -->\n",
+        ];
+        let outputs = transform_project(&parts, &TransformOptions::default());
+        let reports: Vec<_> = outputs
+            .iter()
+            .map(|(_, report)| (report.next_number, report.diagnostics.len()))
+            .collect();
+        assert_eq!(
+            reports,
+            vec![(Some(3), 0), (Some(3), 0), (Some(2), 0), (Some(3), 0)]
+        );
+        assert_eq!(
+            outputs[2].0,
+            "<!-- ol continue -->\n3. Run\n4. Check (2)\n<!-- /ol -->\n\n<!-- ol -->\n1. Again\n<!-- /ol -->\n"
+        );
+        assert_eq!(
+            outputs[3].0,
+            "<!-- ol continue -->\n2. Last\n<!-- /ol -->\n"
+        );
+    }
+
+    #[test]
+    fn test_links_follow_anchors_across_parts() {
+        let options = TransformOptions {
+            item_anchors: Some(crate::options::AnchorStyle::Html),
+            ..TransformOptions::default()
+        };
+        let parts = [
+            "<!-- ol -->\n- Install\n- Configure\n<!-- /ol -->\n",
+            "<!-- ol continue name=deploy -->\n- <a id=\"deploy-step-1\"></a>Build\n- <a id=\"deploy-step-2\"></a>Ship\n<!-- /ol -->\n\n[Step 2](#deploy-step-2) ships [it](part-1.md#intro).\n",
+            "See [step 2](part-2.md#deploy-step-2).\n",
+        ];
+        let outputs = transform_project(&parts, &options);
+        assert_eq!(
+            outputs[1].0,
+            "<!-- ol continue name=deploy -->\n3. <a id=\"deploy-step-3\"></a>Build\n4. <a id=\"deploy-step-4\"></a>Ship\n<!-- /ol -->\n\n[Step 4](#deploy-step-4) ships [it](part-1.md#intro).\n"
+        );
+        assert_eq!(outputs[2].0, "See [step 4](part-2.md#deploy-step-4).\n");
+        assert!(
+            outputs
+                .iter()
+                .all(|(_, report)| report.diagnostics.is_empty())
+        );
+    }
+
+    #[test]
+    fn test_manifest_from_str() {
+        let manifest = Manifest::from_str("files:\n  - a.md\n  - b.md\n", DataFormat::Yaml);
+        assert_eq!(manifest.unwrap().files, vec!["a.md", "b.md"]);
+        assert!(Manifest::from_str(r#"{"file": []}"#, DataFormat::Json).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use crate::emit::render_html;
+use crate::options::{AnchorStyle, PublishOptions, TransformOptions};
+use crate::report::TransformReport;
+use crate::transform::transform_with_report;
+
+/// Turns the source form of a document into the form readers see, in one
+/// step: lists in regions are converted, `(cur±N)` expressions and caption
+/// references resolved, items of named regions given anchors, and magic
+/// comments and caption labels stripped. With [`PublishOptions::html`], the
+/// result is rendered as HTML.
+///
+/// The output is returned with the report of the transform; like
+/// [`transform_with_report`], it is worth checking for errors before
+/// publishing.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{PublishOptions, publish};
+///
+/// let input = "<!-- ol name=setup -->\n- Install\n- Run what (cur-1) installed\n<!-- /ol -->\n";
+/// let (output, report) = publish(input, &PublishOptions::default());
+/// assert!(!report.has_errors());
+/// assert_eq!(
+///     output,
+///     "1. <a id=\"setup-step-1\"></a>Install\n2. <a id=\"setup-step-2\"></a>Run what (1) installed\n"
+/// );
+/// ```
+pub fn publish(input: &str, options: &PublishOptions) -> (String, TransformReport) {
+    let mut transform = TransformOptions {
+        annotate: false,
+        strip_markers: true,
+        ..options.transform.clone()
+    };
+    if options.html && transform.item_anchors.is_some() {
+        transform.item_anchors = Some(AnchorStyle::Html);
+    }
+    let (output, report) = transform_with_report(input, &transform);
+    if options.html {
+        (render_html(&output, &transform.parse), report)
+    } else {
+        (output, report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish() {
+        let input = "# Deploy\n\n<!-- ol name=deploy -->\n- Build, as in (fig:flow)\n- Ship what (cur-1) built\n<!-- /ol -->\n\nFigure: The flow <!-- fig:flow -->\n";
+        let (output, _) = publish(input, &PublishOptions::default());
+        assert_eq!(
+            output,
+            "# Deploy\n\n1. <a id=\"deploy-step-1\"></a>Build, as in (Figure 1)\n2. <a id=\"deploy-step-2\"></a>Ship what (1) built\n\nFigure 1: The flow\n"
+        );
+
+        let options = PublishOptions {
+            transform: TransformOptions {
+                annotate: true,
+                item_anchors: Some(AnchorStyle::Attribute),
+                ..PublishOptions::default().transform
+            },
+            html: true,
+        };
+        let (output, _) = publish(input, &options);
+        assert_eq!(
+            output,
+            "<h1>Deploy</h1>\n<ol>\n<li><a id=\"deploy-step-1\"></a>Build, as in (Figure 1)</li>\n<li><a id=\"deploy-step-2\"></a>Ship what (1) built</li>\n</ol>\n<p>Figure 1: The flow</p>\n"
+        );
+    }
+}
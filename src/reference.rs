@@ -0,0 +1,119 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// A reference expression as the transform resolves it: `(cur±N)`,
+/// `(item:N)` or `(item:last)`.
+pub(crate) static REFERENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\((cur[+-]\d+|item:(?:\d+|last))\)").unwrap());
+
+/// Anything that looks like a reference expression, including misspelled
+/// ones such as `(cur + 1)` or `(item:first)` that are never resolved.
+pub(crate) static REFERENCE_LIKE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\((?:cur|item:)[^)]*\)").unwrap());
+
+/// The comment [`TransformOptions::annotate`] puts after a resolved
+/// reference, capturing the expression.
+///
+/// [`TransformOptions::annotate`]: crate::TransformOptions::annotate
+pub(crate) static ANNOTATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^<!-- (cur[+-]\d+|item:(?:\d+|last)) -->$").unwrap());
+
+/// The resolved number an annotation follows.
+pub(crate) static ANNOTATED_NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\((-?\d+)\)$").unwrap());
+
+/// A reference to an item of the list it is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Reference {
+    /// `(cur±N)`: the item N after or before the one it is written in.
+    Relative(i32),
+    /// `(item:N)`: the list's Nth item, counting from 1.
+    Item(i32),
+    /// `(item:last)`: the list's last item.
+    Last,
+}
+
+/// The numbers of an item and of the first and last items of its list,
+/// which references in the item are resolved against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ItemNumbers {
+    pub(crate) current: i32,
+    pub(crate) first: i32,
+    pub(crate) last: i32,
+}
+
+impl Reference {
+    /// Parses an expression without its parentheses, such as `cur-1` or
+    /// `item:last`. Returns `None` for anything else, including numbers too
+    /// large to resolve and `item:0`.
+    pub(crate) fn parse(expression: &str) -> Option<Self> {
+        if let Some(offset) = expression.strip_prefix("cur") {
+            return offset.parse().ok().map(Reference::Relative);
+        }
+        match expression.strip_prefix("item:")? {
+            "last" => Some(Reference::Last),
+            position => position
+                .parse()
+                .ok()
+                .filter(|&position| position > 0)
+                .map(Reference::Item),
+        }
+    }
+
+    /// Parses an expression with its parentheses, such as `(item:3)`.
+    pub(crate) fn parse_expression(expression: &str) -> Option<Self> {
+        Self::parse(expression.strip_prefix('(')?.strip_suffix(')')?)
+    }
+
+    /// Returns the number of the item the reference points at, or `None` if
+    /// it is too large to be a number or, for `(item:N)`, past the end of the
+    /// list.
+    pub(crate) fn resolve(self, numbers: ItemNumbers) -> Option<i32> {
+        match self {
+            Reference::Relative(offset) => numbers.current.checked_add(offset),
+            Reference::Item(position) => numbers
+                .first
+                .checked_add(position - 1)
+                .filter(|&number| number <= numbers.last),
+            Reference::Last => Some(numbers.last),
+        }
+    }
+}
+
+impl ItemNumbers {
+    /// The numbers of the item at `index` in a list of `len` items starting
+    /// at `start`.
+    pub(crate) fn new(start: i32, index: usize, len: usize) -> Self {
+        ItemNumbers {
+            current: start.saturating_add(index as i32),
+            first: start,
+            last: start.saturating_add(len as i32).saturating_sub(1),
+        }
+    }
+
+    /// Whether `number` is the number of one of the list's items.
+    pub(crate) fn contains(self, number: i32) -> bool {
+        (self.first..=self.last).contains(&number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_references() {
+        let numbers = ItemNumbers::new(4, 1, 3);
+        let resolve = |expression| Reference::parse(expression)?.resolve(numbers);
+        assert_eq!(resolve("cur-1"), Some(4));
+        assert_eq!(resolve("cur+1"), Some(6));
+        assert_eq!(resolve("item:3"), Some(6));
+        assert_eq!(resolve("item:4"), None);
+        assert_eq!(resolve("item:9"), None);
+        assert_eq!(resolve("item:last"), Some(6));
+        assert_eq!(resolve("item:0"), None);
+        assert_eq!(resolve("item:first"), None);
+        assert_eq!(resolve("cur+2147483647"), None);
+        assert!(numbers.contains(4) && numbers.contains(6) && !numbers.contains(7));
+    }
+}
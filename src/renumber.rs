@@ -0,0 +1,203 @@
+use crate::anchors::{MovedAnchors, renumber_anchors, sync_step_links};
+use crate::marker::RegionKind;
+use crate::options::{ParseOptions, TransformOptions};
+use crate::printer::MarkdownPrinter;
+use crate::reference::{ANNOTATED_NUMBER, ANNOTATION, ItemNumbers, Reference};
+use crate::report::TransformReport;
+use crate::transform::{converts_whole_file, parse, regions, replace_references, split_lists};
+use comrak::Arena;
+use comrak::nodes::{AstNode, ListType, NodeValue};
+
+/// Which ordered lists [`renumber`] rewrites.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenumberScope {
+    /// Only lists inside `<!-- ol -->` regions, or the whole file if its
+    /// front matter says `md_ol: all`.
+    #[default]
+    Regions,
+    /// Every ordered list in the document.
+    All,
+}
+
+/// Rewrites the markers of ordered lists so that they are numbered
+/// sequentially, fixing lists whose numbers went stale after items were
+/// copied, moved or deleted.
+///
+/// Lists in a region start from the region's `start` attribute, continue
+/// from the previous list with `continue` or within a `join` region, and
+/// start from 1 otherwise; lists outside regions, renumbered with
+/// [`RenumberScope::All`], keep their first number. Annotated references such as `(3)<!-- cur-1 -->` and any
+/// remaining `(cur±N)` expressions are resolved against the new numbers.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{RenumberScope, renumber};
+///
+/// let input = "<!-- ol -->\n1. A\n1. B\n5. After (1)<!-- cur-1 -->\n<!-- /ol -->";
+/// assert_eq!(
+///     renumber(input, RenumberScope::Regions),
+///     "<!-- ol -->\n1. A\n2. B\n3. After (2)<!-- cur-1 -->\n<!-- /ol -->\n"
+/// );
+/// ```
+pub fn renumber(input: &str, scope: RenumberScope) -> String {
+    let arena = Arena::new();
+    let Ok(root) = parse(&arena, input, &ParseOptions::default()) else {
+        return input.to_string();
+    };
+    let convert_all = scope == RenumberScope::All || converts_whole_file(root);
+    split_lists(&arena, root, &TransformOptions::default().marker_syntaxes);
+    let mut moved = MovedAnchors::new();
+    renumber_ast(root, convert_all, &mut moved);
+    sync_step_links(root, &moved);
+    let mut printer = MarkdownPrinter::with_source(input);
+    printer.render_node(root);
+    printer.finish()
+}
+
+fn renumber_ast<'a>(node: &'a AstNode<'a>, convert_all: bool, moved: &mut MovedAnchors) {
+    for child in node.children() {
+        renumber_ast(child, convert_all, moved);
+    }
+
+    let syntaxes = TransformOptions::default().marker_syntaxes;
+    let mut next_number: Option<usize> = None;
+    for region in regions(
+        node,
+        &syntaxes,
+        convert_all,
+        &mut TransformReport::default(),
+    ) {
+        if region.kind != RegionKind::Ol {
+            continue;
+        }
+        let mut joined = false;
+        for list in region.lists {
+            let NodeValue::List(list_data) = &mut list.data.borrow_mut().value else {
+                continue;
+            };
+            if list_data.list_type != ListType::Ordered {
+                continue;
+            }
+            list_data.start = match (region.attributes.start, next_number) {
+                (_, Some(next)) if region.attributes.continue_numbering || joined => next,
+                (Some(start), _) => start,
+                _ if region.position.is_none() => list_data.start,
+                _ => 1,
+            };
+            next_number = Some(list_data.start + list.children().count());
+            joined = region.attributes.join;
+            resolve_references(list, list_data.start);
+            if let Some(name) = &region.attributes.name {
+                renumber_anchors(list, list_data.start, name, moved);
+            }
+        }
+    }
+}
+
+/// Recomputes the numbers of annotated references such as `(3)<!-- cur-1 -->`
+/// and resolves any reference expressions in the items of `list`, leaving
+/// those in nested ordered lists alone.
+fn resolve_references<'a>(list: &'a AstNode<'a>, start: usize) {
+    let len = list.children().count();
+    for (index, item) in list.children().enumerate() {
+        let numbers = ItemNumbers::new(start as i32, index, len);
+        let mut stack: Vec<_> = item.children().collect();
+        while let Some(node) = stack.pop() {
+            // Nested ordered lists have numbers of their own.
+            if !matches!(
+                &node.data.borrow().value,
+                NodeValue::List(nested) if nested.list_type == ListType::Ordered
+            ) {
+                stack.extend(node.children());
+            }
+            let reference = match &node.data.borrow().value {
+                NodeValue::HtmlInline(html) => ANNOTATION
+                    .captures(html)
+                    .and_then(|captures| Reference::parse(&captures[1])),
+                NodeValue::Text(_) => None,
+                _ => continue,
+            };
+            match reference {
+                Some(reference) => {
+                    let Some(previous) = node.previous_sibling() else {
+                        continue;
+                    };
+                    if let NodeValue::Text(text) = &mut previous.data.borrow_mut().value
+                        && let Some(number) = ANNOTATED_NUMBER.find(text)
+                        && let Some(resolved) = reference.resolve(numbers)
+                    {
+                        text.replace_range(number.range(), &format!("({})", resolved));
+                    }
+                }
+                None => {
+                    if let NodeValue::Text(text) = &mut node.data.borrow_mut().value {
+                        *text = replace_references(text, numbers);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renumber_stale_lists_in_regions() {
+        let input = r#"1. Outside
+1. Left alone
+
+<!-- ol start=3 -->
+1. Third
+7. Fourth, after (3)<!-- cur-1 -->
+<!-- /ol -->
+
+Text
+
+<!-- ol continue -->
+2. Fifth refers to (cur-1)
+<!-- /ol -->
+"#;
+        let expected = r#"1. Outside
+2. Left alone
+
+<!-- ol start=3 -->
+3. Third
+4. Fourth, after (3)<!-- cur-1 -->
+<!-- /ol -->
+
+Text
+
+<!-- ol continue -->
+5. Fifth refers to (4)
+<!-- /ol -->
+"#;
+        assert_eq!(renumber(input, RenumberScope::Regions), expected);
+    }
+
+    #[test]
+    fn test_renumber_all() {
+        let input = "4. Four\n4. Five (9)<!-- cur-1 -->\n\n- Bullet (cur+1)\n";
+        assert_eq!(
+            renumber(input, RenumberScope::All),
+            "4. Four\n5. Five (4)<!-- cur-1 -->\n\n- Bullet (cur+1)\n"
+        );
+    }
+
+    #[test]
+    fn test_renumber_nested_lists() {
+        let input = "<!-- ol -->\n1. Outer\n1. Second, see (5)<!-- cur-1 -->\n   1. Inner\n   1. After (7)<!-- cur-1 -->\n<!-- /ol -->\n";
+        assert_eq!(
+            renumber(input, RenumberScope::Regions),
+            "<!-- ol -->\n1. Outer\n2. Second, see (1)<!-- cur-1 -->\n   1. Inner\n   2. After (7)<!-- cur-1 -->\n<!-- /ol -->\n"
+        );
+    }
+
+    #[test]
+    fn test_renumber_leaves_bullet_lists() {
+        let input = "<!-- ol -->\n- A (cur+1)\n- B\n<!-- /ol -->\n";
+        assert_eq!(renumber(input, RenumberScope::Regions), input);
+    }
+}
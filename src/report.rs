@@ -0,0 +1,282 @@
+use crate::marker::RegionKind;
+use std::fmt;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found while transforming a document, positioned at a 1-based
+/// line and column of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    /// What kind of problem this is, such as `unknown-attribute`; for lint
+    /// warnings, the rule that found it.
+    pub code: &'static str,
+    /// The ID of the region the problem was found in, if any.
+    pub region: Option<String>,
+    /// The [`lint`](crate::lint()) rule that found the problem, if any.
+    pub rule: Option<&'static str>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.summary())
+    }
+}
+
+impl Diagnostic {
+    /// The severity and message, followed by the region and rule if any.
+    fn summary(&self) -> String {
+        let mut summary = format!("{}: {}", self.severity, self.message);
+        if let Some(region) = &self.region {
+            summary.push_str(&format!(" (in region `{}`)", region));
+        }
+        if let Some(rule) = self.rule {
+            summary.push_str(&format!(" [{}]", rule));
+        }
+        summary
+    }
+
+    /// Renders the diagnostic with the line of `source` it points at, the
+    /// problem underlined, for a reader looking for it in a long document.
+    /// `name` is the name of the file `source` was read from.
+    ///
+    /// ```text
+    /// warning: unknown attribute `colour`
+    ///  --> guide.md:1:1
+    ///   |
+    /// 1 | <!-- ol start=3 colour=red -->
+    ///   |                 ^^^^^^
+    /// ```
+    ///
+    /// The underline covers the first name in backticks in the message if it
+    /// appears on the line from the diagnostic's column, and otherwise the
+    /// word at the column.
+    pub fn render(&self, name: &str, source: &str) -> String {
+        let headline = self.summary();
+        let location = format!("{}:{}:{}", name, self.line, self.column);
+        let Some(line) = source.lines().nth(self.line.wrapping_sub(1)) else {
+            return format!("{}\n --> {}\n", headline, location);
+        };
+
+        let mut start = self.column.saturating_sub(1).min(line.len());
+        while !line.is_char_boundary(start) {
+            start -= 1;
+        }
+        let quoted = self
+            .message
+            .split('`')
+            .nth(1)
+            .filter(|quoted| !quoted.is_empty());
+        let (start, end) = match quoted
+            .and_then(|quoted| line[start..].find(quoted).map(|offset| (offset, quoted)))
+        {
+            Some((offset, quoted)) => (start + offset, start + offset + quoted.len()),
+            None => {
+                let word = line[start..]
+                    .find(char::is_whitespace)
+                    .map_or(line.len(), |offset| start + offset);
+                (start, word.max(start))
+            }
+        };
+        // Tabs are kept so that the underline lines up however wide they are,
+        // and wide characters such as CJK ones are padded with two spaces.
+        let padding: String = line[..start]
+            .chars()
+            .map(|c| match c {
+                '\t' => "\t".to_string(),
+                c => " ".repeat(c.width().unwrap_or(0)),
+            })
+            .collect();
+        let underline = "^".repeat(line[start..end].width().max(1));
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        format!(
+            "{headline}\n{gutter}--> {location}\n{gutter} |\n{number} | {line}\n{gutter} | {padding}{underline}\n",
+            number = self.line,
+        )
+    }
+}
+
+/// A region found in the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionInfo {
+    /// The region's `name` attribute, or an ID generated from the position of
+    /// its opening marker, e.g. `line-12`.
+    pub id: String,
+    /// Whether the region numbers or un-numbers its lists.
+    pub kind: RegionKind,
+    /// The 1-based line of the opening marker.
+    pub line: usize,
+    /// The 1-based column of the opening marker.
+    pub column: usize,
+    /// The 1-based last line of the region: its closing marker, or the end
+    /// of the last block in it when it has none.
+    pub end_line: usize,
+    /// The attributes of the opening marker as written, e.g. `start=3`.
+    pub attributes: String,
+    /// The number of lists in the region.
+    pub lists: usize,
+    /// The number of items across the region's lists.
+    pub items: usize,
+}
+
+/// A decision the transform made about part of a document, such as why a
+/// list was numbered from 5 or left alone, positioned at a 1-based line and
+/// column of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// The pass that made the decision: `includes`, `tables` or `regions`.
+    pub pass: &'static str,
+    pub line: usize,
+    pub column: usize,
+    /// The ID of the region the decision concerns, if any.
+    pub region: Option<String>,
+    pub message: String,
+}
+
+/// Everything the transform found out about a document besides its output.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransformReport {
+    pub diagnostics: Vec<Diagnostic>,
+    /// The document's explicit regions, in document order.
+    pub regions: Vec<RegionInfo>,
+    /// The number following the last item of the last list numbered outside
+    /// block quotes and list items, which a following document continues
+    /// from with [`TransformOptions::continue_from`]. Passes
+    /// `continue_from` through when the document has no such list.
+    ///
+    /// [`TransformOptions::continue_from`]: crate::TransformOptions::continue_from
+    pub next_number: Option<usize>,
+    /// The decisions the transform made, in the order of its passes, for
+    /// finding out why a region was or wasn't converted.
+    pub events: Vec<Event>,
+}
+
+impl TransformReport {
+    pub(crate) fn warn(
+        &mut self,
+        code: &'static str,
+        line: usize,
+        column: usize,
+        message: String,
+    ) -> &mut Diagnostic {
+        self.push(Severity::Warning, code, line, column, message)
+    }
+
+    pub(crate) fn error(
+        &mut self,
+        code: &'static str,
+        line: usize,
+        column: usize,
+        message: String,
+    ) -> &mut Diagnostic {
+        self.push(Severity::Error, code, line, column, message)
+    }
+
+    pub(crate) fn event(
+        &mut self,
+        pass: &'static str,
+        line: usize,
+        column: usize,
+        message: String,
+    ) -> &mut Event {
+        self.events.push(Event {
+            pass,
+            line,
+            column,
+            region: None,
+            message,
+        });
+        self.events.last_mut().unwrap()
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        code: &'static str,
+        line: usize,
+        column: usize,
+        message: String,
+    ) -> &mut Diagnostic {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            line,
+            column,
+            message,
+            code,
+            region: None,
+            rule: None,
+        });
+        self.diagnostics.last_mut().unwrap()
+    }
+
+    /// Returns `true` if any diagnostic is an error.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(line: usize, column: usize, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            line,
+            column,
+            message: message.to_string(),
+            code: "test",
+            region: None,
+            rule: None,
+        }
+    }
+
+    #[test]
+    fn test_render_underlines_the_quoted_name() {
+        let source = "# Guide\n\n<!-- ol start=3 colour=red -->\n- A\n";
+        assert_eq!(
+            diagnostic(3, 1, "unknown attribute `colour`").render("guide.md", source),
+            "warning: unknown attribute `colour`\n --> guide.md:3:1\n  |\n3 | <!-- ol start=3 colour=red -->\n  |                 ^^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn test_render_underlines_the_word_at_the_column() {
+        let source = "\t- Run (cur+1)\n";
+        let mut found = diagnostic(1, 8, "reference points past the end of the list");
+        found.rule = Some("bad-references");
+        assert_eq!(
+            found.render("a.md", source),
+            "warning: reference points past the end of the list [bad-references]\n --> a.md:1:8\n  |\n1 | \t- Run (cur+1)\n  | \t      ^^^^^^^\n"
+        );
+        assert_eq!(
+            diagnostic(1, 10, "no such step").render("a.md", "- 手順 (cur+1)\n"),
+            "warning: no such step\n --> a.md:1:10\n  |\n1 | - 手順 (cur+1)\n  |        ^^^^^^^\n"
+        );
+        assert_eq!(
+            diagnostic(9, 1, "past the end").render("a.md", source),
+            "warning: past the end\n --> a.md:9:1\n"
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use crate::counters::restore_counters;
+use crate::marker::{MarkerSyntax, RegionKind};
+use crate::options::TransformOptions;
+use crate::printer::MarkdownPrinter;
+use crate::reference::{ANNOTATED_NUMBER, ANNOTATION};
+use crate::report::{Severity, TransformReport};
+use crate::transform::{converts_whole_file, parse, regions, transform_with_report};
+use comrak::Arena;
+use comrak::nodes::{AstNode, ListDelimType, ListType, NodeValue};
+
+/// Reverses a transform made with
+/// [`TransformOptions::annotate`](crate::TransformOptions::annotate).
+///
+/// Every resolved number followed by an annotation comment, such as
+/// `(3)<!-- cur-1 -->`, `(5)<!-- item:last -->` or `(2)<!-- counter:example
+/// -->`, is turned back into its expression, and
+/// ordered lists inside `<!-- ol -->` regions are turned back into bullet
+/// lists, so the result can be edited and transformed again.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::restore;
+///
+/// let input = "<!-- ol -->\n1. A\n2. After (1)<!-- cur-1 -->\n\n<!-- /ol -->";
+/// assert_eq!(
+///     restore(input),
+///     "<!-- ol -->\n- A\n- After (cur-1)\n\n<!-- /ol -->\n"
+/// );
+/// ```
+pub fn restore(input: &str) -> String {
+    restore_with_options(input, &TransformOptions::default())
+}
+
+/// Reverses a transform like [`restore`], recognizing the markers and
+/// extensions `options` does and writing the result with its layout, so that
+/// restored bullet lists use [`RenderOptions::bullet`], for instance.
+///
+/// [`RenderOptions::bullet`]: crate::RenderOptions::bullet
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{RenderOptions, TransformOptions, restore_with_options};
+///
+/// let options = TransformOptions {
+///     render: RenderOptions {
+///         bullet: Some('*'),
+///         ..RenderOptions::default()
+///     },
+///     ..TransformOptions::default()
+/// };
+/// assert_eq!(
+///     restore_with_options("<!-- ol -->\n1. A\n2. After (1)<!-- cur-1 -->\n<!-- /ol -->\n", &options),
+///     "<!-- ol -->\n* A\n* After (cur-1)\n<!-- /ol -->\n"
+/// );
+/// ```
+pub fn restore_with_options(input: &str, options: &TransformOptions) -> String {
+    let arena = Arena::new();
+    let Ok(root) = parse(&arena, input, &options.parse) else {
+        return input.to_string();
+    };
+    restore_ast(root, &options.marker_syntaxes, converts_whole_file(root));
+    restore_counters(root);
+    let mut printer = MarkdownPrinter::with_options(input, &options.render_options());
+    printer.render_node(root);
+    printer.finish()
+}
+
+/// Which way [`filter`] converts a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterDirection {
+    /// From the checked-out form with resolved numbers back to the `(cur±N)`
+    /// source form, as a git `clean` filter stores files.
+    Clean,
+    /// From the source form to resolved numbers with annotations, as a git
+    /// `smudge` filter checks files out.
+    Smudge,
+}
+
+/// Converts `input` for a git filter driver, annotating resolved numbers
+/// with [`TransformOptions::annotate`] so that [`restore_with_options`] can
+/// undo them.
+///
+/// A filter that changed a file irreversibly would corrupt it, so the result
+/// is only returned when converting it back the other way gives exactly
+/// `input`. Otherwise the error says why, and `input` should be passed
+/// through unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{FilterDirection, TransformOptions, filter};
+///
+/// let source = "<!-- ol -->\n- A\n- After (cur-1)\n\n<!-- /ol -->\n";
+/// let options = TransformOptions::default();
+/// let smudged = filter(source, FilterDirection::Smudge, &options).unwrap();
+/// assert_eq!(smudged, "<!-- ol -->\n1. A\n2. After (1)<!-- cur-1 -->\n\n<!-- /ol -->\n");
+/// assert_eq!(filter(&smudged, FilterDirection::Clean, &options).unwrap(), source);
+/// ```
+pub fn filter(
+    input: &str,
+    direction: FilterDirection,
+    options: &TransformOptions,
+) -> Result<String, String> {
+    let options = TransformOptions {
+        annotate: true,
+        ..options.clone()
+    };
+    let smudge = |text: &str| {
+        let (output, report) = transform_with_report(text, &options);
+        match report
+            .diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.severity == Severity::Error)
+        {
+            Some(error) => Err(error.to_string()),
+            None => Ok(output),
+        }
+    };
+    let (output, back) = match direction {
+        FilterDirection::Smudge => {
+            let output = smudge(input)?;
+            let back = restore_with_options(&output, &options);
+            (output, back)
+        }
+        FilterDirection::Clean => {
+            let output = restore_with_options(input, &options);
+            let back = smudge(&output)?;
+            (output, back)
+        }
+    };
+    if back != input {
+        let place = match input
+            .lines()
+            .zip(back.lines())
+            .position(|(original, round_trip)| original != round_trip)
+        {
+            Some(index) => format!("line {}", index + 1),
+            None => "the end of the file".to_string(),
+        };
+        return Err(format!(
+            "converting back would change {}; leaving the file as it is",
+            place
+        ));
+    }
+    Ok(output)
+}
+
+fn restore_ast<'a>(node: &'a AstNode<'a>, syntaxes: &[MarkerSyntax], convert_all: bool) {
+    for child in node.children() {
+        restore_ast(child, syntaxes, convert_all);
+    }
+
+    for region in regions(node, syntaxes, convert_all, &mut TransformReport::default()) {
+        if region.kind != RegionKind::Ol {
+            continue;
+        }
+        for list in region.lists {
+            restore_list(list);
+        }
+    }
+}
+
+fn restore_list<'a>(list: &'a AstNode<'a>) {
+    if let NodeValue::List(list_data) = &mut list.data.borrow_mut().value {
+        if list_data.list_type != ListType::Ordered {
+            return;
+        }
+        list_data.list_type = ListType::Bullet;
+        list_data.start = 0;
+        list_data.delimiter = ListDelimType::Period;
+        list_data.bullet_char = b'-';
+    }
+
+    let annotations: Vec<_> = list
+        .descendants()
+        .filter(|node| matches!(&node.data.borrow().value, NodeValue::HtmlInline(html) if ANNOTATION.is_match(html)))
+        .collect();
+
+    for annotation in annotations {
+        let Some(previous) = annotation.previous_sibling() else {
+            continue;
+        };
+        let expression = match &annotation.data.borrow().value {
+            NodeValue::HtmlInline(html) => ANNOTATION.captures(html).unwrap()[1].to_string(),
+            _ => continue,
+        };
+
+        let restored = match &mut previous.data.borrow_mut().value {
+            NodeValue::Text(text) => match ANNOTATED_NUMBER.find(text) {
+                Some(number) => {
+                    text.replace_range(number.range(), &format!("({})", expression));
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        };
+
+        if restored {
+            annotation.detach();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::RenderOptions;
+    use crate::transform::transform_with_options;
+
+    fn annotated(input: &str) -> String {
+        let options = TransformOptions {
+            annotate: true,
+            ..TransformOptions::default()
+        };
+        transform_with_options(input, &options)
+    }
+
+    #[test]
+    fn test_annotated_output() {
+        let input = r#"<!-- ol -->
+- First item
+- Second item with (cur-1) and (cur+1) references
+- Third item
+<!-- /ol -->"#;
+        let expected = r#"<!-- ol -->
+1. First item
+2. Second item with (1)<!-- cur-1 --> and (3)<!-- cur+1 --> references
+3. Third item
+<!-- /ol -->
+"#;
+        assert_eq!(annotated(input), expected);
+    }
+
+    #[test]
+    fn test_restore_annotated_output() {
+        let input = r#"<!-- ol -->
+1. First item
+2. Second item with (1)<!-- cur-1 --> and (3)<!-- cur+1 --> references
+3. Third item
+
+<!-- /ol -->
+"#;
+        let expected = r#"<!-- ol -->
+- First item
+- Second item with (cur-1) and (cur+1) references
+- Third item
+
+<!-- /ol -->
+"#;
+        assert_eq!(restore(input), expected);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = r#"<!-- ol -->
+- First item
+- Second item
+- Third item refers to (cur-2)
+
+<!-- /ol -->
+"#;
+        assert_eq!(restore(&annotated(source)), source);
+
+        let source = "<!-- ol -->\n- First item\n- Refers to (item:1) and (item:last)\n- Third item\n<!-- /ol -->\n";
+        assert_eq!(restore(&annotated(source)), source);
+        assert_eq!(annotated(&restore(&annotated(source))), annotated(source));
+    }
+
+    #[test]
+    fn test_filter_refuses_conversions_that_do_not_round_trip() {
+        let options = TransformOptions::default();
+        // Restoring writes bullets as `-`, so `*` would not survive a clean.
+        let source = "<!-- ol -->\n* A\n* B\n\n<!-- /ol -->\n";
+        assert_eq!(
+            filter(source, FilterDirection::Smudge, &options),
+            Err("converting back would change line 2; leaving the file as it is".to_string())
+        );
+        let checked_out = "<!-- ol -->\n1. A\n2. B\n<!-- /ol -->";
+        assert_eq!(
+            filter(checked_out, FilterDirection::Clean, &options),
+            Err(
+                "converting back would change the end of the file; leaving the file as it is"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_restore_leaves_lists_outside_regions() {
+        let input = r#"1. First item
+2. Second item (1)<!-- cur-1 -->
+"#;
+        assert_eq!(restore(input), input);
+    }
+
+    #[test]
+    fn test_restore_with_options() {
+        let options = TransformOptions {
+            render: RenderOptions {
+                bullet: Some('*'),
+                ..RenderOptions::default()
+            },
+            marker_syntaxes: vec![MarkerSyntax::MdxComment],
+            ..TransformOptions::default()
+        };
+        let source = "{/* ol */}\n\n* A\n* B after (cur-1)\n\n{/* /ol */}\n";
+        let smudged = filter(source, FilterDirection::Smudge, &options).unwrap();
+        assert_eq!(
+            smudged,
+            "{/* ol */}\n\n1. A\n2. B after (1)<!-- cur-1 -->\n\n{/* /ol */}\n"
+        );
+        assert_eq!(restore_with_options(&smudged, &options), source);
+    }
+}
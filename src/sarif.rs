@@ -0,0 +1,187 @@
+use crate::report::{Diagnostic, Severity};
+use serde_json::{Value, json};
+
+/// Every [`Diagnostic::code`], with what it means, listed as the rules of the
+/// SARIF log.
+const RULES: &[(&str, &str)] = &[
+    (
+        "marker-attribute",
+        "An attribute of a marker is unknown or has an invalid value",
+    ),
+    ("unclosed-region", "A region has no closing marker"),
+    (
+        "mismatched-closing-marker",
+        "A closing marker closes a region of the other kind",
+    ),
+    (
+        "misplaced-skip",
+        "`ol-skip` is outside a region or not followed by a list",
+    ),
+    (
+        "nothing-to-continue",
+        "A `continue` region has no list before it to continue from",
+    ),
+    (
+        "number-too-large",
+        "A list would start past the largest list number",
+    ),
+    (
+        "duplicate-region-name",
+        "More than one region has the same name",
+    ),
+    (
+        "duplicate-item",
+        "An item repeats an earlier item of its list",
+    ),
+    (
+        "table-conversion",
+        "A list could not be turned into a table, or a table was turned into a list",
+    ),
+    (
+        "include-failed",
+        "An included region could not be read or spliced in",
+    ),
+    (
+        "duplicate-caption-label",
+        "More than one caption has the same label",
+    ),
+    (
+        "unknown-caption-label",
+        "A reference names a caption label that doesn't exist",
+    ),
+    ("unknown-counter", "A counter is used before it is declared"),
+    (
+        "nesting-too-deep",
+        "The document is nested too deeply to transform",
+    ),
+    (
+        "inconsistent-bullets",
+        "A bullet list uses a different character than the document's first",
+    ),
+    (
+        "mixed-delimiters",
+        "An ordered list uses a different delimiter than the document's first",
+    ),
+    (
+        "non-sequential-numbers",
+        "An ordered list outside regions is numbered out of order",
+    ),
+    ("item-length", "An item is longer than the limit"),
+    ("unnamed-regions", "A region has no `name` attribute"),
+    (
+        "bad-references",
+        "A `(cur±N)` or `(item:N)` expression is misspelled, outside any region, or points outside its list",
+    ),
+];
+
+/// Writes the diagnostics found in each file as a SARIF 2.1.0 log, for code
+/// scanning dashboards. Each diagnostic becomes a result whose rule is its
+/// [`Diagnostic::code`], located at the file's name as given.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{TransformOptions, to_sarif, transform_with_report};
+///
+/// let (_, report) = transform_with_report("<!-- ol colour=red -->\n- A\n<!-- /ol -->", &TransformOptions::default());
+/// let log = to_sarif([("guide.md", report.diagnostics.as_slice())]);
+/// assert!(log.contains(r#""ruleId": "marker-attribute""#));
+/// ```
+pub fn to_sarif<'a>(files: impl IntoIterator<Item = (&'a str, &'a [Diagnostic])>) -> String {
+    let rules: Vec<_> = RULES
+        .iter()
+        .map(|(id, description)| {
+            json!({
+                "id": id,
+                "shortDescription": { "text": description },
+            })
+        })
+        .collect();
+    let results: Vec<_> = files
+        .into_iter()
+        .flat_map(|(file, diagnostics)| diagnostics.iter().map(move |found| result(file, found)))
+        .collect();
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&log).unwrap() + "\n"
+}
+
+fn result(file: &str, diagnostic: &Diagnostic) -> Value {
+    let mut result = json!({
+        "ruleId": diagnostic.code,
+        "level": match diagnostic.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        },
+        "message": { "text": diagnostic.message },
+        "locations": [{
+            "physicalLocation": {
+                "artifactLocation": { "uri": file.replace('\\', "/") },
+                "region": {
+                    "startLine": diagnostic.line,
+                    "startColumn": diagnostic.column,
+                },
+            },
+        }],
+    });
+    if let Some(index) = RULES.iter().position(|(id, _)| *id == diagnostic.code) {
+        result["ruleIndex"] = json!(index);
+    }
+    if let Some(region) = &diagnostic.region {
+        result["properties"] = json!({ "region": region });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::{LintConfig, lint};
+    use crate::options::TransformOptions;
+
+    #[test]
+    fn test_sarif_results() {
+        let input = "<!-- ol -->\n* A\n<!-- /ol -->\n\n- B\n";
+        let diagnostics = lint(input, &TransformOptions::default(), &LintConfig::default());
+        let log: Value = serde_json::from_str(&to_sarif([
+            ("docs/a.md", diagnostics.as_slice()),
+            ("docs/b.md", &[]),
+        ]))
+        .unwrap();
+        assert_eq!(log["version"], "2.1.0");
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "md_ol_util");
+        let results = run["results"].as_array().unwrap();
+        let rules: Vec<_> = results
+            .iter()
+            .map(|result| result["ruleId"].as_str().unwrap())
+            .collect();
+        assert_eq!(rules, vec!["unnamed-regions", "inconsistent-bullets"]);
+        let result = &results[1];
+        assert_eq!(result["level"], "warning");
+        assert_eq!(
+            run["tool"]["driver"]["rules"][result["ruleIndex"].as_u64().unwrap() as usize]["id"],
+            "inconsistent-bullets"
+        );
+        assert_eq!(
+            result["locations"][0]["physicalLocation"],
+            json!({
+                "artifactLocation": { "uri": "docs/a.md" },
+                "region": { "startLine": 5, "startColumn": 1 },
+            })
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use crate::marker::RegionKind;
+use crate::options::TransformOptions;
+use crate::reference::{ANNOTATED_NUMBER, ANNOTATION, ItemNumbers, REFERENCE_LIKE, Reference};
+use crate::report::TransformReport;
+use crate::transform::{converts_whole_file, parse, regions};
+use comrak::Arena;
+use comrak::nodes::{AstNode, ListType, NodeValue};
+use std::collections::HashSet;
+use std::ops::AddAssign;
+
+/// Counts describing the size and complexity of a document's regions, as
+/// returned by [`stats`]. Adding the stats of several documents gives their
+/// totals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocumentStats {
+    /// The number of explicit regions.
+    pub regions: usize,
+    /// The number of lists in regions.
+    pub lists: usize,
+    /// The number of items in those lists, including nested items.
+    pub items: usize,
+    /// How deeply lists in regions are nested: 1 for flat lists, 0 if there
+    /// are none.
+    pub max_depth: usize,
+    /// The number of `(cur±N)` and `(item:N)` expressions, and of references
+    /// annotated with [`TransformOptions::annotate`].
+    pub cur_expressions: usize,
+    /// The number of those that look wrong: expressions outside any region,
+    /// misspelled ones like `(cur + 1)`, references to items outside their
+    /// list, and annotated numbers that no longer match the item they point
+    /// at.
+    pub odd_references: usize,
+}
+
+impl AddAssign for DocumentStats {
+    fn add_assign(&mut self, other: Self) {
+        self.regions += other.regions;
+        self.lists += other.lists;
+        self.items += other.items;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.cur_expressions += other.cur_expressions;
+        self.odd_references += other.odd_references;
+    }
+}
+
+/// Counts the regions, lists, items and references of `input`, without
+/// transforming it.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{TransformOptions, stats};
+///
+/// let input = "<!-- ol -->\n- One\n- Two, after (cur-1)\n  - Nested (cur+5)\n<!-- /ol -->";
+/// let stats = stats(input, &TransformOptions::default());
+/// assert_eq!((stats.regions, stats.lists, stats.items), (1, 1, 3));
+/// assert_eq!((stats.max_depth, stats.cur_expressions, stats.odd_references), (2, 2, 1));
+/// ```
+pub fn stats(input: &str, options: &TransformOptions) -> DocumentStats {
+    let arena = Arena::new();
+    let Ok(root) = parse(&arena, input, &options.parse) else {
+        return DocumentStats::default();
+    };
+    let convert_all = converts_whole_file(root);
+
+    let mut stats = DocumentStats::default();
+    let mut counted = HashSet::new();
+    for parent in root.descendants() {
+        for region in regions(
+            parent,
+            &options.marker_syntaxes,
+            convert_all,
+            &mut TransformReport::default(),
+        ) {
+            if region.position.is_some() {
+                stats.regions += 1;
+            }
+            for list in region.lists {
+                stats.lists += 1;
+                stats.items += list
+                    .descendants()
+                    .filter(|node| {
+                        matches!(
+                            node.data.borrow().value,
+                            NodeValue::Item(_) | NodeValue::TaskItem(_)
+                        )
+                    })
+                    .count();
+                stats.max_depth = stats.max_depth.max(list_depth(list));
+                count_references(list, region.kind, &mut stats, &mut counted);
+            }
+        }
+    }
+
+    // Expressions outside regions are never resolved.
+    for node in root.descendants() {
+        if let NodeValue::Text(text) = &node.data.borrow().value
+            && !counted.contains(&std::ptr::from_ref(node))
+        {
+            let count = REFERENCE_LIKE.find_iter(text).count();
+            stats.cur_expressions += count;
+            stats.odd_references += count;
+        }
+    }
+    stats
+}
+
+/// Counts the references in the items of `list`, checking that each points
+/// at an item of the list, and adds the text nodes it looked at to
+/// `counted`.
+fn count_references<'a>(
+    list: &'a AstNode<'a>,
+    kind: RegionKind,
+    stats: &mut DocumentStats,
+    counted: &mut HashSet<*const AstNode<'a>>,
+) {
+    let NodeValue::List(list_data) = list.data.borrow().value else {
+        return;
+    };
+    let len = list.children().count();
+    for (index, item) in list.children().enumerate() {
+        let positions = ItemNumbers::new(0, index, len);
+        let numbers = ItemNumbers::new(list_data.start as i32, index, len);
+        for node in item.descendants() {
+            if !counted.insert(std::ptr::from_ref(node)) {
+                continue;
+            }
+            match &node.data.borrow().value {
+                NodeValue::Text(text) => {
+                    for expression in REFERENCE_LIKE.find_iter(text) {
+                        stats.cur_expressions += 1;
+                        let in_list = Reference::parse_expression(expression.as_str())
+                            .and_then(|reference| reference.resolve(positions))
+                            .is_some_and(|target| positions.contains(target));
+                        if !in_list {
+                            stats.odd_references += 1;
+                        }
+                    }
+                }
+                NodeValue::HtmlInline(html) => {
+                    let Some(reference) = ANNOTATION
+                        .captures(html)
+                        .and_then(|captures| Reference::parse(&captures[1]))
+                    else {
+                        continue;
+                    };
+                    stats.cur_expressions += 1;
+                    let annotated = node.previous_sibling().and_then(|previous| {
+                        match &previous.data.borrow().value {
+                            NodeValue::Text(text) => ANNOTATED_NUMBER
+                                .captures(text)
+                                .and_then(|captures| captures[1].parse::<i32>().ok()),
+                            _ => None,
+                        }
+                    });
+                    let matches = kind == RegionKind::Ul
+                        || list_data.list_type != ListType::Ordered
+                        || annotated.is_some() && annotated == reference.resolve(numbers);
+                    let in_list = reference
+                        .resolve(positions)
+                        .is_some_and(|target| positions.contains(target));
+                    if !in_list || !matches {
+                        stats.odd_references += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Returns how many levels of lists `list` has, counting itself.
+fn list_depth<'a>(list: &'a AstNode<'a>) -> usize {
+    1 + list
+        .children()
+        .flat_map(|item| item.children())
+        .filter(|child| matches!(child.data.borrow().value, NodeValue::List(_)))
+        .map(list_depth)
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let input = r#"Outside (cur-1)
+
+<!-- ol name=setup -->
+- One
+- Two, after (cur-1) and (cur + 1)
+  - Nested
+    - Deeper
+<!-- /ol -->
+
+<!-- ul -->
+1. First
+2. Second, after (1)<!-- cur-1 -->
+3. Third, after (1)<!-- cur-1 -->
+<!-- /ul -->
+"#;
+        assert_eq!(
+            stats(input, &TransformOptions::default()),
+            DocumentStats {
+                regions: 2,
+                lists: 2,
+                items: 7,
+                max_depth: 3,
+                cur_expressions: 5,
+                odd_references: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_stale_annotations_are_odd() {
+        let input = "<!-- ol -->\n1. First\n2. Second, after (1)<!-- cur-1 -->\n3. Third, after (1)<!-- cur-1 -->\n<!-- /ol -->\n";
+        let stats = stats(input, &TransformOptions::default());
+        assert_eq!((stats.cur_expressions, stats.odd_references), (2, 1));
+    }
+
+    #[test]
+    fn test_add_stats() {
+        let mut total = DocumentStats {
+            max_depth: 2,
+            items: 3,
+            ..DocumentStats::default()
+        };
+        total += DocumentStats {
+            max_depth: 1,
+            items: 4,
+            ..DocumentStats::default()
+        };
+        assert_eq!((total.max_depth, total.items), (2, 7));
+    }
+}
@@ -0,0 +1,99 @@
+use crate::counters;
+use crate::marker::{MarkerSyntax, marker_text, parse_marker};
+use crate::reference::ANNOTATION;
+use comrak::nodes::{AstNode, NodeValue};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// A caption label, or the annotation of a reference to a caption.
+static LABEL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^<!--\s*(?:fig|table):[A-Za-z0-9_-]+\s*-->$").unwrap());
+
+/// Removes the magic comments from the transformed tree under `root`: the
+/// blocks holding markers such as `<!-- ol -->`, `<!-- /ol -->` and
+/// `<!-- progress -->`, and the inline comments labelling captions and
+/// annotating resolved references.
+///
+/// Whitespace left at the end of a paragraph by a removed inline comment is
+/// trimmed along with it.
+pub(crate) fn strip_markers<'a>(root: &'a AstNode<'a>, syntaxes: &[MarkerSyntax]) {
+    let mut blocks = Vec::new();
+    let mut comments = Vec::new();
+    for node in root.descendants() {
+        let is_comment = matches!(
+            &node.data.borrow().value,
+            NodeValue::HtmlInline(html)
+                if LABEL.is_match(html)
+                    || ANNOTATION.is_match(html)
+                    || counters::ANNOTATION.is_match(html)
+        );
+        if is_comment {
+            comments.push(node);
+        } else if marker_text(node).is_some_and(|text| parse_marker(&text, syntaxes).is_some()) {
+            blocks.push(node);
+        }
+    }
+    for block in blocks {
+        block.detach();
+    }
+    for comment in comments {
+        let previous = comment.previous_sibling();
+        let last = comment.next_sibling().is_none();
+        comment.detach();
+        if last
+            && let Some(previous) = previous
+            && let NodeValue::Text(text) = &mut previous.data.borrow_mut().value
+        {
+            text.truncate(text.trim_end().len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::TransformOptions;
+    use crate::transform::transform_with_options;
+
+    fn strip(input: &str) -> String {
+        let options = TransformOptions {
+            strip_markers: true,
+            ..TransformOptions::default()
+        };
+        transform_with_options(input, &options)
+    }
+
+    #[test]
+    fn test_strip_markers() {
+        assert_eq!(
+            strip(
+                "# Setup\n\n<!-- ol name=setup -->\n- Install\n- Configure (cur-1)\n<!-- /ol -->\n\nDone.\n"
+            ),
+            "# Setup\n\n1. Install\n2. Configure (1)\n\nDone.\n"
+        );
+        assert_eq!(
+            strip("<!-- ol -->\n- A\n<!-- /ol -->\n<!-- ol -->\n- B\n<!-- /ol -->\n"),
+            "1. A\n\n<!-- end list -->\n\n1. B\n"
+        );
+        assert_eq!(
+            strip("Text <!-- a comment -->\n\n<!-- a comment -->\n"),
+            "Text <!-- a comment -->\n\n<!-- a comment -->\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_annotations_and_labels() {
+        let options = TransformOptions {
+            strip_markers: true,
+            annotate: true,
+            number_captions: true,
+            ..TransformOptions::default()
+        };
+        assert_eq!(
+            transform_with_options(
+                "See (fig:flow).\n\nFigure: The flow <!-- fig:flow -->\n\n<!-- ol -->\n- A\n- B (cur-1)\n<!-- /ol -->\n",
+                &options
+            ),
+            "See (Figure 1).\n\nFigure 1: The flow\n\n1. A\n2. B (1)\n"
+        );
+    }
+}
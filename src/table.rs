@@ -0,0 +1,323 @@
+use crate::marker::{MarkerSyntax, RegionKind, marker_text, set_marker_text};
+use crate::report::TransformReport;
+use crate::transform::{demote_list, new_node, regions};
+use comrak::Arena;
+use comrak::nodes::{
+    AstNode, ListDelimType, ListType, NodeList, NodeTable, NodeValue, TableAlignment,
+};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// The `untable` attribute of an opening marker, with its value if it has
+/// one.
+static UNTABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(^|\s)untable(?:=(?:"[^"]*"|'[^']*'|[^\s"']\S*))?"#).unwrap());
+
+/// Replaces the ordered `list` with a two-column table, with `headers` as its
+/// header row, the number of each item in the first column and its text in
+/// the second, e.g. `| 1 | Back up the database |`.
+///
+/// Only items made of paragraphs fit in a table cell; paragraphs after the
+/// first are joined with `<br>`. Returns an error message, leaving the list
+/// alone, if an item holds anything else, such as a nested list.
+pub(crate) fn list_to_table<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    list: &'a AstNode<'a>,
+    start: usize,
+    headers: &[String; 2],
+) -> Result<(), String> {
+    if list
+        .children()
+        .flat_map(|item| item.children())
+        .any(|block| !matches!(block.data.borrow().value, NodeValue::Paragraph))
+    {
+        return Err(
+            "`table` needs items made of paragraphs; leaving the list as it is".to_string(),
+        );
+    }
+
+    let table = new_node(
+        arena,
+        NodeValue::Table(NodeTable {
+            alignments: vec![TableAlignment::None; 2],
+            num_columns: 2,
+            num_rows: list.children().count() + 1,
+            num_nonempty_cells: 0,
+        }),
+    );
+    table.data.borrow_mut().sourcepos = list.data.borrow().sourcepos;
+
+    let header = new_node(arena, NodeValue::TableRow(true));
+    for text in headers {
+        header.append(text_cell(arena, text));
+    }
+    table.append(header);
+
+    for (index, item) in list.children().enumerate() {
+        let row = new_node(arena, NodeValue::TableRow(false));
+        row.append(text_cell(arena, &(start + index).to_string()));
+        let cell = new_node(arena, NodeValue::TableCell);
+        let paragraphs: Vec<_> = item.children().collect();
+        for (paragraph_index, paragraph) in paragraphs.into_iter().enumerate() {
+            if paragraph_index > 0 {
+                cell.append(new_node(arena, NodeValue::HtmlInline("<br>".to_string())));
+            }
+            let inlines: Vec<_> = paragraph.children().collect();
+            for inline in inlines {
+                let line_break = match inline.data.borrow().value {
+                    NodeValue::SoftBreak => Some(NodeValue::Text(" ".to_string())),
+                    NodeValue::LineBreak => Some(NodeValue::HtmlInline("<br>".to_string())),
+                    _ => None,
+                };
+                match line_break {
+                    Some(value) => cell.append(new_node(arena, value)),
+                    None => cell.append(inline),
+                }
+            }
+        }
+        row.append(cell);
+        table.append(row);
+    }
+
+    list.insert_after(table);
+    list.detach();
+    Ok(())
+}
+
+/// Turns the two-column tables in `untable` regions whose first column
+/// numbers the rows, as written by [`list_to_table`], back into lists, so that
+/// they can be edited as lists again. Tables in `ol` regions with a `table`
+/// attribute are left alone.
+///
+/// The lists are written as bullet lists, with references to the row numbers
+/// in the cells turned into `(cur±N)` expressions: `ol` regions then number
+/// them like any other list, while `ul` regions keep them as bullets. Each
+/// conversion is reported, and the table's headers are recorded as the value
+/// of `untable`, so that replacing it with `table` turns the list back into
+/// the same table.
+pub(crate) fn tables_to_lists<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    syntaxes: &[MarkerSyntax],
+    report: &mut TransformReport,
+) {
+    let parents: Vec<_> = root.descendants().collect();
+    for parent in parents {
+        for region in regions(parent, syntaxes, false, &mut TransformReport::default()) {
+            if !region.attributes.untable
+                || region.kind == RegionKind::Ol && region.attributes.table.is_some()
+            {
+                continue;
+            }
+            for table in region.tables {
+                let Some(list) = table_to_list(arena, table) else {
+                    continue;
+                };
+                let headers = table.first_child().map_or_else(Vec::new, |header| {
+                    header.children().map(|cell| plain_text(cell)).collect()
+                });
+                let marker = parent
+                    .children()
+                    .find(|child| Some(child.data.borrow().sourcepos.start) == region.position);
+                if let Some(marker) = marker {
+                    record_headers(marker, &headers);
+                }
+                let position = table.data.borrow().sourcepos.start;
+                report
+                    .warn(
+                        "table-conversion",
+                        position.line,
+                        position.column,
+                        format!(
+                            "turned the table into a list; its headers `{}` are kept in `untable`",
+                            headers.join(" | ")
+                        ),
+                    )
+                    .region = region.id.clone();
+                report
+                    .event(
+                        "tables",
+                        position.line,
+                        position.column,
+                        "turned the table into a list".to_string(),
+                    )
+                    .region = region.id.clone();
+                table.insert_after(list);
+                table.detach();
+                demote_list(list);
+            }
+        }
+    }
+}
+
+/// Sets the value of the `untable` attribute of the opening `marker` to
+/// `headers`, e.g. `untable="Step,What to do"`.
+fn record_headers<'a>(marker: &'a AstNode<'a>, headers: &[String]) {
+    let Some(text) = marker_text(marker) else {
+        return;
+    };
+    let attribute = format!(r#"${{1}}untable="{}""#, headers.join(",").replace('"', "'"));
+    set_marker_text(
+        marker,
+        UNTABLE.replace(&text, attribute.as_str()).into_owned(),
+    );
+}
+
+/// Returns the text of a table cell, without its formatting.
+fn plain_text<'a>(cell: &'a AstNode<'a>) -> String {
+    cell.descendants()
+        .filter_map(|node| match &node.data.borrow().value {
+            NodeValue::Text(text) => Some(text.clone()),
+            NodeValue::Code(code) => Some(code.literal.clone()),
+            _ => None,
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Builds an ordered list from a two-column table whose first column holds
+/// consecutive numbers, or returns `None` if the table is not like that.
+fn table_to_list<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    table: &'a AstNode<'a>,
+) -> Option<&'a AstNode<'a>> {
+    if !matches!(&table.data.borrow().value, NodeValue::Table(table) if table.num_columns == 2) {
+        return None;
+    }
+    let rows: Vec<_> = table.children().skip(1).collect();
+    let numbers = rows
+        .iter()
+        .map(|row| cell_number(row.first_child()?))
+        .collect::<Option<Vec<usize>>>()?;
+    let start = *numbers.first()?;
+    if numbers
+        .iter()
+        .zip(start..)
+        .any(|(&number, expected)| number != expected)
+    {
+        return None;
+    }
+
+    let list_data = NodeList {
+        list_type: ListType::Ordered,
+        marker_offset: 0,
+        padding: 3,
+        start,
+        delimiter: ListDelimType::Period,
+        bullet_char: b'-',
+        tight: true,
+        is_task_list: false,
+    };
+    let list = new_node(arena, NodeValue::List(list_data));
+    list.data.borrow_mut().sourcepos = table.data.borrow().sourcepos;
+    for row in rows {
+        let item = new_node(arena, NodeValue::Item(list_data));
+        item.data.borrow_mut().sourcepos = row.data.borrow().sourcepos;
+        let mut paragraph = new_node(arena, NodeValue::Paragraph);
+        item.append(paragraph);
+        let inlines: Vec<_> = row
+            .last_child()
+            .into_iter()
+            .flat_map(|cell| cell.children())
+            .collect();
+        for inline in inlines {
+            let is_break = matches!(
+                &inline.data.borrow().value,
+                NodeValue::HtmlInline(html)
+                    if matches!(html.to_ascii_lowercase().as_str(), "<br>" | "<br/>" | "<br />")
+            );
+            if is_break {
+                paragraph = new_node(arena, NodeValue::Paragraph);
+                item.append(paragraph);
+            } else {
+                paragraph.append(inline);
+            }
+        }
+        if item.children().count() > 1 {
+            list.data.borrow_mut().value = NodeValue::List(NodeList {
+                tight: false,
+                ..list_data
+            });
+        }
+        list.append(item);
+    }
+    Some(list)
+}
+
+/// Returns the number a table cell holds, if it holds nothing else.
+fn cell_number<'a>(cell: &'a AstNode<'a>) -> Option<usize> {
+    let text = cell
+        .first_child()
+        .filter(|text| text.next_sibling().is_none())?;
+    match &text.data.borrow().value {
+        NodeValue::Text(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn text_cell<'a>(arena: &'a Arena<AstNode<'a>>, text: &str) -> &'a AstNode<'a> {
+    let cell = new_node(arena, NodeValue::TableCell);
+    cell.append(new_node(arena, NodeValue::Text(text.to_string())));
+    cell
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::TransformOptions;
+    use crate::transform::{transform, transform_with_report};
+
+    #[test]
+    fn test_list_to_table() {
+        let input = "<!-- ol table=\"Step,What to do\" start=3 -->\n- Back up the *database*\n- Run the\n  migration\n\n  Then check (cur-1)\n<!-- /ol -->\n";
+        let expected = "<!-- ol table=\"Step,What to do\" start=3 -->\n| Step | What to do |\n| --- | --- |\n| 3 | Back up the *database* |\n| 4 | Run the migration<br>Then check (3) |\n\n<!-- /ol -->\n";
+        assert_eq!(transform(input), expected);
+        assert_eq!(transform(expected), expected);
+    }
+
+    #[test]
+    fn test_list_with_nested_blocks_stays_a_list() {
+        let input = "<!-- ol table=step,description -->\n- One\n  - Nested\n<!-- /ol -->\n";
+        let (output, report) = transform_with_report(input, &TransformOptions::default());
+        assert_eq!(
+            output,
+            "<!-- ol table=step,description -->\n1. One\n   - Nested\n<!-- /ol -->\n"
+        );
+        assert_eq!(
+            report.diagnostics[0].message,
+            "`table` needs items made of paragraphs; leaving the list as it is"
+        );
+    }
+
+    #[test]
+    fn test_table_to_list() {
+        let table = "| Step | What to do |\n| --- | --- |\n| 3 | Back up the *database* |\n| 4 | Run the migration<br>Then check (3) |\n";
+        let input = format!("<!-- ul untable -->\n{}\n<!-- /ul -->\n", table);
+        let expected = "<!-- ul untable=\"Step,What to do\" -->\n- Back up the *database*\n\n- Run the migration\n\n  Then check (cur-1)\n\n<!-- /ul -->\n";
+        let (output, report) = transform_with_report(&input, &TransformOptions::default());
+        assert_eq!(output, expected);
+        assert_eq!(
+            report.diagnostics[0].message,
+            "turned the table into a list; its headers `Step | What to do` are kept in `untable`"
+        );
+        assert_eq!(transform(expected), expected);
+
+        let input = format!("<!-- ol untable start=3 -->\n{}\n<!-- /ol -->\n", table);
+        let output = transform(&input);
+        assert_eq!(
+            output,
+            "<!-- ol untable=\"Step,What to do\" start=3 -->\n3. Back up the *database*\n\n4. Run the migration\n\n   Then check (3)\n\n<!-- /ol -->\n"
+        );
+        // Putting `table` back turns the list into the same table.
+        assert_eq!(
+            transform(&output.replace("untable=", "table=")),
+            "<!-- ol table=\"Step,What to do\" start=3 -->\n| Step | What to do |\n| --- | --- |\n| 3 | Back up the *database* |\n| 4 | Run the migration<br>Then check (3) |\n\n<!-- /ol -->\n"
+        );
+    }
+
+    #[test]
+    fn test_tables_stay_tables_without_untable() {
+        let input = "<!-- ol -->\n| Rank | Team |\n| --- | --- |\n| 1 | Red |\n| 2 | Blue |\n\n<!-- /ol -->\n\n<!-- ul untable -->\n| Name | Value |\n| --- | --- |\n| a | 1 |\n\n<!-- /ul -->\n\n<!-- ol untable -->\n| # | Step |\n| --- | --- |\n| 1 | One |\n| 3 | Three |\n\n<!-- /ol -->\n";
+        assert_eq!(transform(input), input);
+    }
+}
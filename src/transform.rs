@@ -1,7 +1,41 @@
-use crate::printer::Printer;
-use comrak::nodes::{AstNode, ListType, NodeList, NodeValue};
+use crate::anchors::{MovedAnchors, add_anchors, sync_links_between_files, sync_step_links};
+use crate::captions::number_captions;
+use crate::counters::number_counters;
+use crate::footnotes;
+use crate::headings::number_headings;
+use crate::include::expand_includes;
+use crate::line_endings::preserve_line_endings;
+use crate::marker::{
+    MAX_LIST_NUMBER, Marker, MarkerSyntax, RegionAttributes, RegionKind, marker_attributes,
+    marker_text, normalize_marker, parse_marker, set_marker_text,
+};
+use crate::options::{
+    Dedupe, ItemOrder, LineEndings, NumberStyle, ParseOptions, TransformOptions,
+    UnclosedRegionPolicy,
+};
+use crate::printer::MarkdownPrinter;
+use crate::progress::update_progress;
+use crate::reference::{ItemNumbers, REFERENCE, Reference};
+use crate::report::{RegionInfo, TransformReport};
+use crate::strip::strip_markers;
+use crate::table::{list_to_table, tables_to_lists};
+use comrak::nodes::{Ast, AstNode, LineColumn, ListType, NodeList, NodeValue};
 use comrak::{Arena, Options, parse_document};
 use regex::{Captures, Regex};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::{Range, RangeInclusive};
+use std::sync::LazyLock;
+
+/// A number in parentheses, as a `(cur±N)` expression resolves to.
+static RESOLVED_NUMBER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\((\d+)\)").unwrap());
+
+/// The front matter setting that converts every list in the file.
+static CONVERT_ALL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?m)^md_ol:\s*(all|"all"|'all')\s*$"#).unwrap());
+
+/// A number at the start of an item, which numeric sorting compares.
+static LEADING_NUMBER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\d+(?:\.\d+)?").unwrap());
 
 /// Transforms markdown content by converting unordered lists to ordered lists
 /// within magic comment blocks and replacing current position expressions.
@@ -10,6 +44,10 @@ use regex::{Captures, Regex};
 /// to numbered lists when they appear between `<!-- ol -->` and `<!-- /ol -->`
 /// comment blocks, and replaces expressions like `(cur±N)` with actual numbers.
 ///
+/// The output is a fixed point: transforming it again with the same options
+/// returns it unchanged. Blank lines between blocks are kept as in the input
+/// wherever Markdown allows either.
+///
 /// # Arguments
 ///
 /// * `input` - A string slice containing the markdown content to transform
@@ -36,274 +74,3224 @@ use regex::{Captures, Regex};
 /// // <!-- /ol -->
 /// ```
 pub fn transform(input: &str) -> String {
+    transform_with_options(input, &TransformOptions::default())
+}
+
+/// Transforms markdown content like [`transform`], with behavior controlled by
+/// `options`.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{TransformOptions, transform_with_options};
+///
+/// let options = TransformOptions {
+///     annotate: true,
+///     ..TransformOptions::default()
+/// };
+/// let result = transform_with_options("<!-- ol -->\n- A\n- After (cur-1)\n<!-- /ol -->", &options);
+/// assert!(result.contains("2. After (1)<!-- cur-1 -->"));
+/// ```
+pub fn transform_with_options(input: &str, options: &TransformOptions) -> String {
+    transform_with_report(input, options).0
+}
+
+/// Transforms markdown content like [`transform_with_options`], also returning
+/// a [`TransformReport`] with the diagnostics found along the way, such as
+/// unknown attributes on an opening marker.
+///
+/// No input makes the transform panic, however malformed: numbers too large
+/// for a list are reported as diagnostics, and `(cur±N)` offsets past the
+/// range of numbers are left as they are written. This holds for every
+/// function built on it, such as [`transform`] and [`format_range`].
+/// Documents nested more deeply than [`ParseOptions::max_nesting`] are
+/// reported and left as they are.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{TransformOptions, transform_with_report};
+///
+/// let input = "<!-- ol start=3 colour=red -->\n- A\n<!-- /ol -->";
+/// let (output, report) = transform_with_report(input, &TransformOptions::default());
+/// assert!(output.contains("3. A"));
+/// assert_eq!(report.diagnostics[0].message, "unknown attribute `colour`");
+/// ```
+pub fn transform_with_report(input: &str, options: &TransformOptions) -> (String, TransformReport) {
+    let (output, report, _) = transform_linked(input, options, &MovedAnchors::new());
+    (output, report)
+}
+
+/// Like [`transform_with_report`], also pointing links to other documents at
+/// the anchors `linked` records as moved there, and returning the anchors
+/// that moved in this one.
+pub(crate) fn transform_linked(
+    input: &str,
+    options: &TransformOptions,
+    linked: &MovedAnchors,
+) -> (String, TransformReport, MovedAnchors) {
+    transform_and_print(input, options, &parse_options(&options.parse), linked)
+}
+
+/// Transforms and prints `input`, parsing with `parse_options` as built from
+/// `options.parse`.
+fn transform_and_print(
+    input: &str,
+    options: &TransformOptions,
+    parse_options: &Options,
+    linked: &MovedAnchors,
+) -> (String, TransformReport, MovedAnchors) {
     let arena = Arena::new();
-    let options = Options::default();
-    let root = parse_document(&arena, input, &options);
-    transform_ast(root);
-    let mut printer = Printer::new();
-    printer.render_node(root);
-    printer.finish()
+    let transformed = transform_parsed(&arena, input, options, parse_options, linked);
+    if transformed.unchanged {
+        return (input.to_string(), transformed.report, MovedAnchors::new());
+    }
+    let mut printer = MarkdownPrinter::with_options(&transformed.source, &options.render_options())
+        .lazy_lists(transformed.lazy_lists);
+    printer.render_node(transformed.root);
+    let output = match options.line_endings {
+        LineEndings::Lf => printer.finish(),
+        LineEndings::Preserve => preserve_line_endings(input, &printer.finish()),
+    };
+    (output, transformed.report, transformed.moved_anchors)
 }
 
-fn transform_ast<'a>(node: &'a AstNode<'a>) {
-    // Process children first
-    for child in node.children() {
-        transform_ast(child);
+/// Transforms documents with the same options, like
+/// [`transform_with_report`], building the parser's configuration once
+/// rather than for every document. Build one for a batch of documents and
+/// reuse it.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{TransformOptions, Transformer};
+///
+/// let transformer = Transformer::new(TransformOptions::default());
+/// for input in ["<!-- ol -->\n- A\n<!-- /ol -->", "<!-- ol start=2 -->\n- B\n<!-- /ol -->"] {
+///     let (output, report) = transformer.transform(input);
+///     assert!(output.contains(". ") && !report.has_errors());
+/// }
+/// ```
+pub struct Transformer {
+    options: TransformOptions,
+    parse_options: Options<'static>,
+}
+
+impl Transformer {
+    /// Creates a transformer for documents transformed with `options`.
+    pub fn new(options: TransformOptions) -> Self {
+        Self {
+            parse_options: parse_options(&options.parse),
+            options,
+        }
+    }
+
+    /// The options documents are transformed with.
+    pub fn options(&self) -> &TransformOptions {
+        &self.options
     }
 
-    transform_ul(node);
+    /// Transforms `input`, returning the output with the report of the
+    /// transform.
+    pub fn transform(&self, input: &str) -> (String, TransformReport) {
+        let (output, report, _) = transform_and_print(
+            input,
+            &self.options,
+            &self.parse_options,
+            &MovedAnchors::new(),
+        );
+        (output, report)
+    }
 }
 
-fn transform_ul<'a>(parent: &'a AstNode<'a>) {
-    let children: Vec<&AstNode> = parent.children().collect();
-    let mut convert_mode = false;
-    let mut nodes_to_convert = Vec::new();
+/// Transforms the lines of `input` that the byte range `bytes` touches,
+/// returning the range of whole lines that was transformed and the text to
+/// replace it with, for formatting a selection in an editor.
+///
+/// The lines are transformed on their own, so they should include the
+/// markers of the regions in them. A range ending at the start of a line
+/// doesn't touch that line, and a range reaching past the end of `input` is
+/// cut short.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{TransformOptions, format_range};
+///
+/// let input = "# Steps\n\n<!-- ol -->\n- A\n- B\n<!-- /ol -->\n";
+/// let (range, text) = format_range(input, 12..30, &TransformOptions::default());
+/// assert_eq!(range, 9..42);
+/// assert_eq!(text, "<!-- ol -->\n1. A\n2. B\n<!-- /ol -->\n");
+/// ```
+pub fn format_range(
+    input: &str,
+    bytes: Range<usize>,
+    options: &TransformOptions,
+) -> (Range<usize>, String) {
+    let input_bytes = input.as_bytes();
+    let end = bytes.end.min(input.len());
+    let start = bytes.start.min(end);
+    let start = input_bytes[..start]
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let end = if end > start && input_bytes[end - 1] == b'\n' {
+        end
+    } else {
+        input_bytes[end..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(input.len(), |newline| end + newline + 1)
+    };
 
-    for child in children {
-        if let NodeValue::HtmlBlock(html_block) = &child.data.borrow().value {
-            let content = html_block.literal.trim();
-            if content == "<!-- ol -->" {
-                convert_mode = true;
-            } else if content == "<!-- /ol -->" {
-                convert_mode = false;
-            }
-        } else if convert_mode {
-            if let NodeValue::List(list_data) = &child.data.borrow().value {
-                if list_data.list_type == ListType::Bullet {
-                    nodes_to_convert.push(child);
-                }
-            }
-        }
+    let original = &input[start..end];
+    let mut formatted = transform_with_options(original, options);
+    if !original.ends_with('\n') {
+        formatted.truncate(formatted.trim_end_matches('\n').len());
     }
+    (start..end, formatted)
+}
 
-    for node in nodes_to_convert {
-        let node_list_clone = {
-            if let NodeValue::List(list_data) = &node.data.borrow().value {
-                Some(*list_data)
-            } else {
-                None
+/// Returns the lines of `output` that lie between the markers of one region,
+/// for pulling a single procedure out of a larger document. `output` and
+/// `report` are what [`transform_with_report`] returned.
+///
+/// `region` is the region's `name`, its ID such as `line-12` as listed in
+/// [`TransformReport::regions`], or its 1-based position among them. Blank
+/// lines at either end are left out.
+///
+/// # Examples
+///
+/// ```
+/// use md_ol_util::{TransformOptions, region_markdown, transform_with_report};
+///
+/// let input = "<!-- ol name=setup -->\n- Install\n<!-- /ol -->\n\n<!-- ol name=rollback -->\n- Stop\n- Restore\n<!-- /ol -->";
+/// let options = TransformOptions::default();
+/// let (output, report) = transform_with_report(input, &options);
+/// assert_eq!(
+///     region_markdown(&output, &report, "rollback", &options).unwrap(),
+///     "1. Stop\n2. Restore\n"
+/// );
+/// assert_eq!(
+///     region_markdown(&output, &report, "1", &options).unwrap(),
+///     "1. Install\n"
+/// );
+/// ```
+pub fn region_markdown(
+    output: &str,
+    report: &TransformReport,
+    region: &str,
+    options: &TransformOptions,
+) -> Result<String, String> {
+    let index = match report.regions.iter().position(|info| info.id == region) {
+        Some(index) => index,
+        None => match region.parse::<usize>() {
+            Ok(number) if (1..=report.regions.len()).contains(&number) => number - 1,
+            Ok(number) => {
+                return Err(match report.regions.len() {
+                    1 => format!("no region {}: the document has 1 region", number),
+                    count => format!("no region {}: the document has {} regions", number, count),
+                });
             }
-        };
+            Err(_) => return Err(format!("no region named `{}`", region)),
+        },
+    };
 
-        if let Some(node_list) = node_list_clone {
-            let start = 1;
-            let new_list = NodeList {
-                list_type: ListType::Ordered,
-                start,
-                delimiter: node_list.delimiter,
-                bullet_char: node_list.bullet_char,
-                tight: node_list.tight,
-                is_task_list: node_list.is_task_list,
-                marker_offset: node_list.marker_offset,
-                padding: node_list.padding,
-            };
-            node.data.borrow_mut().value = NodeValue::List(new_list);
+    let arena = Arena::new();
+    let root = parse(&arena, output, &options.parse)
+        .map_err(|_| nesting_message(options.parse.max_nesting))?;
+    let mut found: Vec<_> = root
+        .descendants()
+        .flat_map(|parent| {
+            regions(
+                parent,
+                &options.marker_syntaxes,
+                false,
+                &mut TransformReport::default(),
+            )
+        })
+        .filter_map(|found| Some((found.position?, found)))
+        .collect();
+    found.sort_by_key(|(position, _)| (position.line, position.column));
+
+    // The output keeps every marker, so its regions are the same ones in the
+    // same order, only on different lines.
+    let Some((position, found)) = found.get(index) else {
+        return Err(format!("region `{}` is missing from the output", region));
+    };
+    let end = match found.closing_marker {
+        true => found.end_line - 1,
+        false => found.end_line,
+    };
+    let lines: Vec<&str> = output.lines().collect();
+    let lines = lines
+        .get(position.line..end.min(lines.len()))
+        .unwrap_or_default();
+    let first = lines.iter().position(|line| !line.trim().is_empty());
+    let last = lines.iter().rposition(|line| !line.trim().is_empty());
+    Ok(match (first, last) {
+        (Some(first), Some(last)) => lines[first..=last].join("\n") + "\n",
+        _ => String::new(),
+    })
+}
+
+/// A document whose syntax tree has been transformed, ready to be printed.
+pub(crate) struct Transformed<'a> {
+    pub(crate) root: &'a AstNode<'a>,
+    pub(crate) report: TransformReport,
+    /// Where the lists numbered in the lazy style start.
+    pub(crate) lazy_lists: BTreeSet<LineColumn>,
+    /// The text that was parsed: the input with its includes expanded.
+    pub(crate) source: String,
+    /// Whether the document was left as it is, being nested too deeply to
+    /// transform. `root` is then empty.
+    pub(crate) unchanged: bool,
+    /// The IDs of the item anchors that changed, old to new.
+    pub(crate) moved_anchors: MovedAnchors,
+}
+
+/// Parses `input` and transforms its syntax tree, without printing it.
+pub(crate) fn transform_document<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    input: &str,
+    options: &TransformOptions,
+) -> Transformed<'a> {
+    transform_parsed(
+        arena,
+        input,
+        options,
+        &parse_options(&options.parse),
+        &MovedAnchors::new(),
+    )
+}
 
-            // After converting to ordered list, replace (cur-N) with actual numbers
-            replace_cur_expressions_in_list(node, start);
+/// Like [`transform_document`], parsing with `parse_options` as built from
+/// `options.parse` and pointing links to other documents at the anchors
+/// `linked` records as moved there.
+fn transform_parsed<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    input: &str,
+    options: &TransformOptions,
+    parse_options: &Options,
+    linked: &MovedAnchors,
+) -> Transformed<'a> {
+    let mut include_report = TransformReport::default();
+    let expanded = expand_includes(input, options, &mut include_report);
+    let root = match parse_with(
+        arena,
+        &expanded.text,
+        parse_options,
+        options.parse.max_nesting,
+    ) {
+        Ok(root) => root,
+        Err(position) => {
+            include_report.error(
+                "nesting-too-deep",
+                expanded.origin(position.line),
+                position.column,
+                nesting_message(options.parse.max_nesting),
+            );
+            return Transformed {
+                root: new_node(arena, NodeValue::Document),
+                report: include_report,
+                lazy_lists: BTreeSet::new(),
+                source: expanded.text,
+                unchanged: true,
+                moved_anchors: MovedAnchors::new(),
+            };
         }
+    };
+    if options.parse.footnotes {
+        footnotes::restore_positions(root);
+    }
+    let mut context = Context {
+        arena,
+        options,
+        convert_all: converts_whole_file(root),
+        report: TransformReport::default(),
+        lazy_lists: BTreeSet::new(),
+        moved_anchors: MovedAnchors::new(),
+        included: expanded.included.clone(),
+    };
+    split_lists(arena, root, &options.marker_syntaxes);
+    tables_to_lists(arena, root, &options.marker_syntaxes, &mut context.report);
+    transform_ast(&mut context, root);
+    sync_step_links(root, &context.moved_anchors);
+    sync_links_between_files(root, linked);
+    number_headings(
+        arena,
+        root,
+        &options.heading_numbers,
+        &options.marker_syntaxes,
+    );
+    if options.number_captions {
+        number_captions(arena, root, options.annotate, &mut context.report);
+    }
+    number_counters(
+        arena,
+        root,
+        &options.marker_syntaxes,
+        options.annotate,
+        &mut context.report,
+    );
+    if options.parse.footnotes && options.renumber_footnotes {
+        footnotes::renumber(root);
+    }
+    if options.strip_markers {
+        strip_markers(root, &options.marker_syntaxes);
+    }
+    check_region_names(&mut context.report);
+    let mut report = context.report;
+    for diagnostic in &mut report.diagnostics {
+        diagnostic.line = expanded.origin(diagnostic.line);
+    }
+    for region in &mut report.regions {
+        region.line = expanded.origin(region.line);
+        region.end_line = expanded.origin(region.end_line);
+    }
+    for event in &mut report.events {
+        event.line = expanded.origin(event.line);
+    }
+    include_report.diagnostics.append(&mut report.diagnostics);
+    report.diagnostics = include_report.diagnostics;
+    include_report.events.append(&mut report.events);
+    report.events = include_report.events;
+    Transformed {
+        root,
+        report,
+        lazy_lists: context.lazy_lists,
+        source: expanded.text,
+        unchanged: false,
+        moved_anchors: context.moved_anchors,
     }
 }
 
-fn replace_cur_expressions_in_list<'a>(list_node: &'a AstNode<'a>, start: usize) {
-    let mut item_number = start as i32;
+/// Sorts the collected regions into document order and reports names used by
+/// more than one region.
+fn check_region_names(report: &mut TransformReport) {
+    report
+        .regions
+        .sort_by_key(|region| (region.line, region.column));
 
-    for item in list_node.children() {
-        if let NodeValue::Item(_) = &item.data.borrow().value {
-            let mut stack = Vec::new();
-            stack.push(item);
+    let mut seen = HashSet::new();
+    let duplicates: Vec<_> = report
+        .regions
+        .iter()
+        .filter(|region| !seen.insert(region.id.clone()))
+        .map(|region| (region.line, region.column, region.id.clone()))
+        .collect();
+    for (line, column, id) in duplicates {
+        let message = format!("duplicate region name `{}`", id);
+        report
+            .warn("duplicate-region-name", line, column, message)
+            .region = Some(id);
+    }
+}
 
-            while let Some(node) = stack.pop() {
-                let new_text_opt = {
-                    if let NodeValue::Text(text) = &node.data.borrow().value {
-                        let new_text = replace_cur(text, item_number);
-                        if new_text != *text {
-                            Some(new_text)
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                };
+/// State shared by the passes of a single transform.
+struct Context<'a, 'o> {
+    arena: &'a Arena<AstNode<'a>>,
+    options: &'o TransformOptions,
+    /// Whether the front matter opts the whole file into conversion.
+    convert_all: bool,
+    report: TransformReport,
+    /// Where the lists numbered in the lazy style start.
+    lazy_lists: BTreeSet<LineColumn>,
+    /// Item anchors whose IDs changed, which links are updated to follow.
+    moved_anchors: MovedAnchors,
+    /// The lines spliced in by includes, whose lists are numbered as part of
+    /// the region around them.
+    included: Vec<RangeInclusive<usize>>,
+}
 
-                if let Some(new_text) = new_text_opt {
-                    node.data.borrow_mut().value = NodeValue::Text(new_text);
-                }
+/// The comrak options used to parse documents.
+pub(crate) fn parse_options(parse: &ParseOptions) -> Options<'static> {
+    let mut options = Options::default();
+    options.extension.front_matter_delimiter = parse.front_matter_delimiter.clone();
+    options.extension.table = parse.tables;
+    options.extension.tasklist = parse.task_lists;
+    options.extension.strikethrough = parse.strikethrough;
+    options.extension.autolink = parse.autolinks;
+    options.extension.footnotes = parse.footnotes;
+    options.extension.math_dollars = parse.math;
+    options.extension.description_lists = parse.description_lists;
+    options.extension.wikilinks_title_after_pipe = parse.wikilinks;
+    options.extension.superscript = parse.superscript;
+    options.extension.subscript = parse.subscript;
+    options.extension.spoiler = parse.spoilers;
+    options.extension.alerts = parse.alerts;
+    options
+}
 
-                for child in node.children() {
-                    stack.push(child);
-                }
-            }
+/// Parses `input`, failing with the position of the first node nested more
+/// than [`ParseOptions::max_nesting`] deep, as the recursive passes over the
+/// syntax tree could run out of stack on it.
+pub(crate) fn parse<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    input: &str,
+    parse: &ParseOptions,
+) -> Result<&'a AstNode<'a>, LineColumn> {
+    parse_with(arena, input, &parse_options(parse), parse.max_nesting)
+}
 
-            item_number += 1;
+/// Like [`parse`], with the comrak options already built.
+fn parse_with<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    input: &str,
+    options: &Options,
+    max_nesting: usize,
+) -> Result<&'a AstNode<'a>, LineColumn> {
+    let root = parse_document(arena, input, options);
+    let mut stack = vec![(root, 0)];
+    while let Some((node, depth)) = stack.pop() {
+        if depth > max_nesting {
+            return Err(node.data.borrow().sourcepos.start);
         }
+        stack.extend(node.children().map(|child| (child, depth + 1)));
     }
+    Ok(root)
 }
 
-fn replace_cur(text: &str, current_item_number: i32) -> String {
-    let re = Regex::new(r"\(cur([+-]\d+)\)").unwrap();
-    re.replace_all(text, |caps: &Captures| {
-        let offset_str = &caps[1];
-        if let Ok(offset) = offset_str.parse::<i32>() {
-            let result = current_item_number + offset;
-            format!("({})", result)
-        } else {
-            caps[0].to_string() // Return original if parsing fails
-        }
-    })
-    .to_string()
+/// The message reporting a document nested deeper than `max_nesting`.
+pub(crate) fn nesting_message(max_nesting: usize) -> String {
+    format!(
+        "nested more than {} levels deep; leaving the document as it is",
+        max_nesting
+    )
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Returns `true` if the document's YAML front matter contains `md_ol: all`,
+/// which converts every bullet list in the file as if it were inside a region.
+pub(crate) fn converts_whole_file<'a>(root: &'a AstNode<'a>) -> bool {
+    let Some(first) = root.first_child() else {
+        return false;
+    };
+    match &first.data.borrow().value {
+        NodeValue::FrontMatter(front_matter) => CONVERT_ALL.is_match(front_matter),
+        _ => false,
+    }
+}
 
-    #[test]
-    fn test_unordered_lists_unchanged_by_default() {
-        let input = r#"- First item
-- Second item
-- Third item"#;
-        let expected = r#"- First item
-- Second item
-- Third item
-"#;
-        assert_eq!(transform(input), expected);
+/// A run of sibling blocks between an opening and a closing marker, or, for
+/// `section` regions, between the opening marker and the end of its section.
+pub(crate) struct Region<'a> {
+    pub(crate) kind: RegionKind,
+    pub(crate) attributes: RegionAttributes,
+    /// The attributes as written in the opening marker, e.g. `start=3`.
+    pub(crate) written_attributes: String,
+    /// The region's `name`, or an ID generated from the position of its
+    /// opening marker; `None` for implicit regions.
+    pub(crate) id: Option<String>,
+    /// Where the opening marker is, or `None` for implicit regions.
+    pub(crate) position: Option<LineColumn>,
+    /// Whether the region ended properly: at a closing marker, at another
+    /// opening marker, or at the end of its section.
+    pub(crate) closed: bool,
+    /// The last line of the region, which is its closing marker if it has
+    /// one.
+    pub(crate) end_line: usize,
+    /// Whether the region ended at a closing marker, on `end_line`.
+    pub(crate) closing_marker: bool,
+    /// For `section` regions, the level of the heading the region appears
+    /// under, or 0 before the first heading.
+    section_level: Option<u8>,
+    /// The lists inside the region, in document order, excluding lists
+    /// preceded by `<!-- ol-skip -->`. Lists inside block quotes and alerts
+    /// count too, unless the quote has markers of its own.
+    pub(crate) lists: Vec<&'a AstNode<'a>>,
+    /// The tables directly inside the region, in document order.
+    pub(crate) tables: Vec<&'a AstNode<'a>>,
+}
+
+fn transform_ast<'a>(context: &mut Context<'a, '_>, node: &'a AstNode<'a>) {
+    // Process children first
+    for child in node.children() {
+        transform_ast(context, child);
     }
 
-    #[test]
-    fn test_magic_comment_conversion() {
-        let input = r#"<!-- ol -->
-- First item
-- Second item
-- Third item
-<!-- /ol -->"#;
-        let expected = r#"<!-- ol -->
-1. First item
-2. Second item
-3. Third item
+    transform_ul(context, node);
 
-<!-- /ol -->
-"#;
-        assert_eq!(transform(input), expected);
+    if context.options.normalize_markers {
+        normalize_marker_block(node, &context.options.marker_syntaxes);
     }
+}
 
-    #[test]
-    fn test_mixed_content_with_magic_comments() {
-        let input = r#"# Header
+fn normalize_marker_block<'a>(node: &'a AstNode<'a>, syntaxes: &[MarkerSyntax]) {
+    let Some(normalized) = marker_text(node).and_then(|text| normalize_marker(&text, syntaxes))
+    else {
+        return;
+    };
 
-Normal list (unchanged):
-- First item
-- Second item
+    let is_block = matches!(node.data.borrow().value, NodeValue::HtmlBlock(_));
+    set_marker_text(
+        node,
+        if is_block {
+            normalized + "\n"
+        } else {
+            normalized
+        },
+    );
+}
 
-<!-- ol -->
-- Third item
-- Fourth item
-<!-- /ol -->
+/// Replaces `list` with a table if `region` has a `table` attribute,
+/// returning whether it did. Lists that don't fit in a table are reported.
+fn convert_to_table<'a>(
+    context: &mut Context<'a, '_>,
+    list: &'a AstNode<'a>,
+    start: usize,
+    region: &Region<'a>,
+) -> bool {
+    let Some(headers) = &region.attributes.table else {
+        return false;
+    };
+    let position = list.data.borrow().sourcepos.start;
+    match list_to_table(context.arena, list, start, headers) {
+        Ok(()) => {
+            context
+                .report
+                .event(
+                    "regions",
+                    position.line,
+                    position.column,
+                    "turned the list into a table".to_string(),
+                )
+                .region = region.id.clone();
+            true
+        }
+        Err(message) => {
+            context
+                .report
+                .warn("table-conversion", position.line, position.column, message)
+                .region = region.id.clone();
+            false
+        }
+    }
+}
 
-Some text"#;
-        let expected = r#"# Header
+impl Region<'_> {
+    /// The region covering everything outside explicit regions when the front
+    /// matter opts the whole file into conversion.
+    fn implicit() -> Self {
+        Self {
+            kind: RegionKind::Ol,
+            attributes: RegionAttributes::default(),
+            written_attributes: String::new(),
+            id: None,
+            position: None,
+            closed: true,
+            end_line: 0,
+            closing_marker: false,
+            section_level: None,
+            lists: Vec::new(),
+            tables: Vec::new(),
+        }
+    }
 
-Normal list (unchanged):
+    fn close(self) -> Self {
+        Self {
+            closed: true,
+            ..self
+        }
+    }
+}
 
-- First item
-- Second item
+/// Returns the last line `node` takes up. comrak ends some blocks at column 0
+/// of the line after them, and HTML blocks that follow a list before they
+/// start, so neither end is taken as it is.
+fn last_line<'a>(node: &'a AstNode<'a>) -> usize {
+    let sourcepos = node.data.borrow().sourcepos;
+    let end = match sourcepos.end.column {
+        0 => sourcepos.end.line.saturating_sub(1),
+        _ => sourcepos.end.line,
+    };
+    end.max(sourcepos.start.line)
+}
 
-<!-- ol -->
-1. Third item
-2. Fourth item
+/// Collects the regions formed by `<!-- ol -->` and `<!-- /ol -->` markers
+/// among `parent`'s children, reporting misplaced or malformed markers.
+///
+/// With `convert_all`, children outside explicit regions belong to implicit
+/// regions with default attributes.
+pub(crate) fn regions<'a>(
+    parent: &'a AstNode<'a>,
+    syntaxes: &[MarkerSyntax],
+    convert_all: bool,
+    report: &mut TransformReport,
+) -> Vec<Region<'a>> {
+    let mut regions = Vec::new();
+    let mut current: Option<Region<'a>> = convert_all.then(Region::implicit);
+    let mut skip: Option<LineColumn> = None;
 
-<!-- /ol -->
-Some text
-"#;
-        assert_eq!(transform(input), expected);
-    }
+    for child in parent.children() {
+        let position = child.data.borrow().sourcepos.start;
+        let text = marker_text(child);
+        let marker = text
+            .as_deref()
+            .and_then(|text| parse_marker(text, syntaxes));
+        let is_list = matches!(child.data.borrow().value, NodeValue::List(_));
 
-    #[test]
-    fn test_indented_lists_with_magic_comments() {
-        let input = r#"<!-- ol -->
-  - Indented item
-  - Another indented item
-<!-- /ol -->"#;
-        let expected = r#"<!-- ol -->
-  1. Indented item
-  2. Another indented item
+        if let NodeValue::Heading(heading) = &child.data.borrow().value
+            && let Some(level) = current.as_ref().and_then(|region| region.section_level)
+            && (level == 0 || heading.level <= level)
+        {
+            regions.extend(current.take().map(Region::close));
+            current = convert_all.then(Region::implicit);
+        }
 
-<!-- /ol -->
-"#;
-        assert_eq!(transform(input), expected);
+        let skipped = skip.take();
+        if let Some(skip_position) = skipped
+            && !is_list
+        {
+            report
+                .warn(
+                    "misplaced-skip",
+                    skip_position.line,
+                    skip_position.column,
+                    "`ol-skip` is not immediately followed by a list".to_string(),
+                )
+                .region = current.as_ref().and_then(|region| region.id.clone());
+        }
+        if let Some(region) = current.as_ref()
+            && skipped.is_some()
+            && is_list
+        {
+            report
+                .event(
+                    "regions",
+                    position.line,
+                    position.column,
+                    "left the list unconverted, as `ol-skip` says".to_string(),
+                )
+                .region = region.id.clone();
+        }
+
+        match marker {
+            Some((Marker::Open(kind, mut attributes), warnings)) => {
+                let id = attributes
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("line-{}", position.line));
+                for warning in warnings {
+                    report
+                        .warn("marker-attribute", position.line, position.column, warning)
+                        .region = Some(id.clone());
+                }
+                let mut section_level = attributes.section.then(|| preceding_heading_level(child));
+                if attributes.split
+                    && let Some(parent) = current.as_ref().filter(|region| region.kind == kind)
+                {
+                    attributes.inherit(&parent.attributes);
+                    section_level = section_level.or(parent.section_level);
+                }
+                regions.extend(current.take().map(Region::close));
+                current = Some(Region {
+                    kind,
+                    written_attributes: text
+                        .as_deref()
+                        .and_then(|text| marker_attributes(text, syntaxes))
+                        .unwrap_or_default(),
+                    id: Some(id),
+                    position: Some(position),
+                    closed: false,
+                    end_line: position.line,
+                    closing_marker: false,
+                    section_level,
+                    attributes,
+                    lists: Vec::new(),
+                    tables: Vec::new(),
+                });
+            }
+            Some((Marker::Close(kind), _)) => {
+                if let Some(region) = current.as_ref()
+                    && region.position.is_some()
+                    && region.kind != kind
+                {
+                    report
+                        .warn(
+                            "mismatched-closing-marker",
+                            position.line,
+                            position.column,
+                            format!(
+                                "`<!-- /{} -->` closes a `{}` region",
+                                kind.keyword(),
+                                region.kind.keyword()
+                            ),
+                        )
+                        .region = region.id.clone();
+                }
+                if let Some(region) = current.as_mut() {
+                    region.end_line = last_line(child);
+                    region.closing_marker = true;
+                }
+                regions.extend(current.take().map(Region::close));
+                current = convert_all.then(Region::implicit);
+            }
+            Some((
+                Marker::TocNumbers
+                | Marker::TocNumbersEnd
+                | Marker::Progress
+                | Marker::Include(_)
+                | Marker::IncludeEnd
+                | Marker::Counter { .. },
+                _,
+            )) => {}
+            Some((Marker::Skip, _)) => {
+                if current.is_some() {
+                    skip = Some(position);
+                } else {
+                    report.warn(
+                        "misplaced-skip",
+                        position.line,
+                        position.column,
+                        "`ol-skip` outside of a region has no effect".to_string(),
+                    );
+                }
+            }
+            None => {
+                if let Some(region) = current.as_mut()
+                    && is_list
+                    && skipped.is_none()
+                {
+                    region.lists.push(child);
+                } else if let Some(region) = current.as_mut()
+                    && matches!(child.data.borrow().value, NodeValue::Table(_))
+                {
+                    region.tables.push(child);
+                } else if let Some(region) = current.as_mut() {
+                    region.lists.extend(quoted_lists(child, syntaxes));
+                }
+            }
+        }
+        if let Some(region) = current.as_mut() {
+            region.end_line = region.end_line.max(last_line(child));
+        }
     }
 
-    #[test]
-    fn test_no_unordered_lists() {
-        let input = r#"Just some text
-with no lists"#;
-        let expected = r#"Just some text
-with no lists
-"#;
-        assert_eq!(transform(input), expected);
+    if let Some(skip_position) = skip {
+        report
+            .warn(
+                "misplaced-skip",
+                skip_position.line,
+                skip_position.column,
+                "`ol-skip` is not immediately followed by a list".to_string(),
+            )
+            .region = current.as_ref().and_then(|region| region.id.clone());
     }
 
-    #[test]
-    fn test_cur_minus_one_replacement() {
-        let input = r#"<!-- ol -->
-- First item
-- Second item
-- Third item with (cur-1) reference
-<!-- /ol -->"#;
-        let expected = r#"<!-- ol -->
-1. First item
-2. Second item
-3. Third item with (2) reference
+    // Section regions need no closing marker.
+    regions.extend(current.map(|region| {
+        if region.section_level.is_some() {
+            region.close()
+        } else {
+            region
+        }
+    }));
+    regions
+}
 
-<!-- /ol -->
-"#;
-        assert_eq!(transform(input), expected);
+/// Splits lists in two after each item containing a `split` marker, such as
+/// `<!-- ol split -->`, moving the marker between the two lists so that it
+/// divides the region there.
+pub(crate) fn split_lists<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    syntaxes: &[MarkerSyntax],
+) {
+    let is_split = |node: &'a AstNode<'a>| {
+        matches!(
+            marker_text(node).and_then(|text| parse_marker(&text, syntaxes)),
+            Some((Marker::Open(_, attributes), _)) if attributes.split
+        )
+    };
+    let markers: Vec<_> = root
+        .descendants()
+        .filter(|node| {
+            is_split(node)
+                && node.parent().is_some_and(|item| {
+                    matches!(
+                        item.data.borrow().value,
+                        NodeValue::Item(_) | NodeValue::TaskItem(_)
+                    )
+                })
+        })
+        .collect();
+
+    for marker in markers {
+        let Some(item) = marker.parent() else {
+            continue;
+        };
+        let Some(list) = item.parent() else {
+            continue;
+        };
+        marker.detach();
+        list.insert_after(marker);
+        let Some(first) = item.next_sibling() else {
+            continue;
+        };
+        let rest = new_node(arena, list.data.borrow().value.clone());
+        rest.data.borrow_mut().sourcepos = first.data.borrow().sourcepos;
+        let mut sibling = Some(first);
+        while let Some(next) = sibling {
+            sibling = next.next_sibling();
+            rest.append(next);
+        }
+        marker.insert_after(rest);
     }
+}
 
-    #[test]
-    fn test_cur_expressions_various_offsets() {
-        let input = r#"<!-- ol -->
-- First item
-- Second item with (cur-1) and (cur+1)
-- Third item with (cur-2) and (cur+0)
-- Fourth item with (cur-3)
-<!-- /ol -->"#;
-        let expected = r#"<!-- ol -->
-1. First item
-2. Second item with (1) and (3)
-3. Third item with (1) and (3)
-4. Fourth item with (1)
+/// Returns the lists directly inside `node` if it is a block quote or an
+/// alert, including those in quotes nested in it. Quotes containing markers
+/// form regions of their own and are left to them.
+fn quoted_lists<'a>(node: &'a AstNode<'a>, syntaxes: &[MarkerSyntax]) -> Vec<&'a AstNode<'a>> {
+    if !matches!(
+        node.data.borrow().value,
+        NodeValue::BlockQuote | NodeValue::Alert(_)
+    ) || node.children().any(|child| {
+        marker_text(child).is_some_and(|text| {
+            parse_marker(&text, syntaxes).is_some_and(|(marker, _)| {
+                !matches!(marker, Marker::Progress | Marker::Counter { .. })
+            })
+        })
+    }) {
+        return Vec::new();
+    }
 
-<!-- /ol -->
-"#;
-        assert_eq!(transform(input), expected);
+    let mut lists = Vec::new();
+    for child in node.children() {
+        if matches!(child.data.borrow().value, NodeValue::List(_)) {
+            lists.push(child);
+        } else {
+            lists.extend(quoted_lists(child, syntaxes));
+        }
     }
+    lists
+}
 
-    #[test]
-    fn test_cur_expressions_no_magic_comments() {
-        let input = r#"- First item with (cur-1)
-- Second item with (cur+1)"#;
-        let expected = r#"- First item with (cur-1)
-- Second item with (cur+1)
-"#;
-        assert_eq!(transform(input), expected);
+/// Returns the level of the closest heading before `node` among its siblings,
+/// or 0 if there is none.
+fn preceding_heading_level<'a>(node: &'a AstNode<'a>) -> u8 {
+    let mut sibling = node.previous_sibling();
+    while let Some(previous) = sibling {
+        if let NodeValue::Heading(heading) = &previous.data.borrow().value {
+            return heading.level;
+        }
+        sibling = previous.previous_sibling();
     }
+    0
+}
 
-    #[test]
-    fn test_cur_expressions_edge_cases() {
-        let input = r#"<!-- ol -->
-- First item with (cur-1) should be (0)
-- Second item with (cur+0) should be (2)
-- Third item with (cur-10) should be (-7)
+fn transform_ul<'a>(context: &mut Context<'a, '_>, parent: &'a AstNode<'a>) {
+    // The number following the last item of the previous converted list,
+    // which `continue` regions pick up from.
+    let top_level = parent.parent().is_none();
+    let mut next_number = context.options.continue_from.filter(|_| top_level);
+    if let Some(next) = next_number
+        && next > MAX_LIST_NUMBER
+    {
+        context.report.warn(
+            "number-too-large",
+            1,
+            1,
+            format!(
+                "cannot continue from {}, past the largest list number {}",
+                next, MAX_LIST_NUMBER
+            ),
+        );
+        next_number = None;
+    }
+
+    for region in regions(
+        parent,
+        &context.options.marker_syntaxes,
+        context.convert_all,
+        &mut context.report,
+    ) {
+        if let (Some(id), Some(position)) = (&region.id, region.position) {
+            context.report.regions.push(RegionInfo {
+                id: id.clone(),
+                kind: region.kind,
+                line: position.line,
+                column: position.column,
+                end_line: region.end_line,
+                attributes: region.written_attributes.clone(),
+                lists: region.lists.len(),
+                items: region
+                    .lists
+                    .iter()
+                    .map(|list| list.children().count())
+                    .sum(),
+            });
+        }
+
+        if !region.closed
+            && let Some(position) = region.position
+        {
+            let message = format!(
+                "`{0}` region has no closing `<!-- /{0} -->`",
+                region.kind.keyword()
+            );
+            let diagnostic = match context.options.unclosed_regions {
+                UnclosedRegionPolicy::Error => {
+                    context
+                        .report
+                        .error("unclosed-region", position.line, position.column, message)
+                }
+                UnclosedRegionPolicy::ConvertToEnd => context.report.warn(
+                    "unclosed-region",
+                    position.line,
+                    position.column,
+                    format!("{}; converting to the end of the enclosing block", message),
+                ),
+                UnclosedRegionPolicy::Skip => context.report.warn(
+                    "unclosed-region",
+                    position.line,
+                    position.column,
+                    format!("{}; leaving it unconverted", message),
+                ),
+            };
+            diagnostic.region = region.id.clone();
+            let converted = context.options.unclosed_regions == UnclosedRegionPolicy::ConvertToEnd;
+            context
+                .report
+                .event(
+                    "regions",
+                    position.line,
+                    position.column,
+                    if converted {
+                        "no closing marker; converting to the end of the enclosing block"
+                    } else {
+                        "no closing marker; leaving the region unconverted"
+                    }
+                    .to_string(),
+                )
+                .region = region.id.clone();
+            if !converted {
+                continue;
+            }
+        }
+        if region.lists.is_empty()
+            && let Some(position) = region.position
+        {
+            context
+                .report
+                .event(
+                    "regions",
+                    position.line,
+                    position.column,
+                    "no lists to convert".to_string(),
+                )
+                .region = region.id.clone();
+        }
+
+        if region.kind == RegionKind::Ul {
+            for node in region.lists {
+                let position = node.data.borrow().sourcepos.start;
+                context
+                    .report
+                    .event(
+                        "regions",
+                        position.line,
+                        position.column,
+                        "un-numbered the list".to_string(),
+                    )
+                    .region = region.id.clone();
+                demote_list(node);
+                if let Some(prefix) = &region.attributes.prefix {
+                    add_prefixes(
+                        context.arena,
+                        node,
+                        region.attributes.start.unwrap_or(1),
+                        prefix,
+                    );
+                }
+            }
+            continue;
+        }
+
+        let lists = if region.attributes.merge {
+            merge_lists(&region.lists, &context.options.marker_syntaxes)
+        } else {
+            region.lists.clone()
+        };
+        let style = region
+            .attributes
+            .style
+            .unwrap_or(context.options.number_style);
+        // Whether the next list continues a list converted earlier in the
+        // same region.
+        let mut joined = false;
+        for node in lists {
+            // Included lists, and the list after the last of them, are
+            // numbered on from the list before.
+            let included = context
+                .included
+                .iter()
+                .any(|lines| lines.contains(&node.data.borrow().sourcepos.start.line));
+            if included {
+                demote_list(node);
+            }
+            let included = included || follows_include(node, &context.options.marker_syntaxes);
+            if let Some(order) = region.attributes.sort {
+                sort_items(node, order);
+            }
+            if let Some(dedupe) = region.attributes.dedupe {
+                dedupe_items(&mut context.report, node, dedupe, region.id.as_deref());
+            }
+            if style == NumberStyle::Lazy {
+                context
+                    .lazy_lists
+                    .insert(node.data.borrow().sourcepos.start);
+            }
+
+            let node_list_clone = {
+                if let NodeValue::List(list_data) = &node.data.borrow().value {
+                    Some(*list_data)
+                } else {
+                    None
+                }
+            };
+
+            if let Some(node_list) = node_list_clone
+                && node_list.list_type == ListType::Bullet
+            {
+                let start = match next_number {
+                    Some(next) if region.attributes.continue_numbering || joined || included => {
+                        next
+                    }
+                    _ => {
+                        if region.attributes.continue_numbering {
+                            let position = node.data.borrow().sourcepos.start;
+                            context
+                                .report
+                                .warn(
+                                    "nothing-to-continue",
+                                    position.line,
+                                    position.column,
+                                    "`continue` has no previous list to continue from".to_string(),
+                                )
+                                .region = region.id.clone();
+                        }
+                        region.attributes.start.unwrap_or(1)
+                    }
+                };
+                let reason = match next_number {
+                    Some(_) if included => "after an include",
+                    Some(_) if joined => "joining the previous list",
+                    Some(_) if region.attributes.continue_numbering => {
+                        "continuing the previous list"
+                    }
+                    _ if region.attributes.start.is_some() => "as `start` says",
+                    _ => "by default",
+                };
+                let position = node.data.borrow().sourcepos.start;
+                context
+                    .report
+                    .event(
+                        "regions",
+                        position.line,
+                        position.column,
+                        format!("numbered the list from {}, {}", start, reason),
+                    )
+                    .region = region.id.clone();
+                // Lists already converted were reversed when they were, so
+                // only bullet lists are, keeping the output stable.
+                if region.attributes.reverse {
+                    let items: Vec<_> = node.children().collect();
+                    for item in items.iter().rev() {
+                        item.detach();
+                        node.append(item);
+                    }
+                }
+                let tasks = region.attributes.tasks || context.options.tasks;
+                if tasks {
+                    for item in node.children() {
+                        let mut data = item.data.borrow_mut();
+                        if matches!(data.value, NodeValue::Item(_)) {
+                            data.value = NodeValue::TaskItem(None);
+                        }
+                    }
+                }
+                let new_list = NodeList {
+                    list_type: ListType::Ordered,
+                    start,
+                    delimiter: region.attributes.delimiter.unwrap_or(node_list.delimiter),
+                    bullet_char: node_list.bullet_char,
+                    tight: node_list.tight,
+                    is_task_list: node_list.is_task_list || tasks,
+                    marker_offset: node_list.marker_offset,
+                    padding: node_list.padding,
+                };
+                node.data.borrow_mut().value = NodeValue::List(new_list);
+
+                // After converting to ordered list, replace (cur-N) with actual numbers
+                replace_references_in_list(context, node, start);
+                if let Some(prefix) = &region.attributes.prefix {
+                    add_prefixes(context.arena, node, start, prefix);
+                }
+                if let (Some(style), Some(name)) =
+                    (context.options.item_anchors, &region.attributes.name)
+                {
+                    add_anchors(
+                        context.arena,
+                        node,
+                        start,
+                        name,
+                        style,
+                        &mut context.moved_anchors,
+                    );
+                }
+                next_number = Some(start + node.children().count());
+                joined = region.attributes.join || context.options.join_lists;
+
+                if convert_to_table(context, node, start, &region) {
+                    continue;
+                }
+            } else if let Some(node_list) = node_list_clone {
+                // Lists converted by an earlier run keep their numbers, and
+                // `continue` regions after them pick up from there.
+                next_number = Some(node_list.start + node.children().count());
+                let position = node.data.borrow().sourcepos.start;
+                context
+                    .report
+                    .event(
+                        "regions",
+                        position.line,
+                        position.column,
+                        format!("kept the numbers of the list, from {}", node_list.start),
+                    )
+                    .region = region.id.clone();
+                // As lists turned back from tables are, once `table` is
+                // added again.
+                if convert_to_table(context, node, node_list.start, &region) {
+                    continue;
+                }
+                if let Some(prefix) = &region.attributes.prefix {
+                    add_prefixes(context.arena, node, node_list.start, prefix);
+                }
+            }
+
+            update_progress(
+                context.arena,
+                node,
+                &context.options.marker_syntaxes,
+                region.attributes.progress || context.options.task_progress,
+            );
+        }
+    }
+
+    if top_level {
+        context.report.next_number = next_number;
+    }
+}
+
+/// Returns whether `list` comes right after a `<!-- /include -->` marker.
+fn follows_include<'a>(list: &'a AstNode<'a>, syntaxes: &[MarkerSyntax]) -> bool {
+    list.previous_sibling()
+        .and_then(marker_text)
+        .and_then(|text| parse_marker(&text, syntaxes))
+        .is_some_and(|(marker, _)| marker == Marker::IncludeEnd)
+}
+
+/// Puts `prefix` at the start of each item of `list`, with `{n}` replaced by
+/// the item's number counting from `start`, replacing the prefix an earlier
+/// run put there.
+fn add_prefixes<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    list: &'a AstNode<'a>,
+    start: usize,
+    prefix: &str,
+) {
+    let existing = Regex::new(&format!(
+        "^{}",
+        regex::escape(prefix).replace(r"\{n\}", r"\d+")
+    ))
+    .unwrap();
+    for (index, item) in list.children().enumerate() {
+        let Some(paragraph) = item
+            .first_child()
+            .filter(|child| matches!(child.data.borrow().value, NodeValue::Paragraph))
+        else {
+            continue;
+        };
+        let text = prefix.replace("{n}", &(start + index).to_string());
+        if let Some(first) = paragraph.first_child()
+            && let NodeValue::Text(literal) = &mut first.data.borrow_mut().value
+        {
+            let rest = existing.replace(literal, "").into_owned();
+            *literal = text + &rest;
+            continue;
+        }
+        paragraph.prepend(new_node(arena, NodeValue::Text(text)));
+    }
+}
+
+/// Moves the items of each bullet list in `lists` into the bullet list before
+/// it when only HTML comments come between them, removing the emptied list and
+/// the comments. Returns the lists that remain.
+fn merge_lists<'a>(lists: &[&'a AstNode<'a>], syntaxes: &[MarkerSyntax]) -> Vec<&'a AstNode<'a>> {
+    let is_bullet_list = |node: &'a AstNode<'a>| {
+        matches!(
+            &node.data.borrow().value,
+            NodeValue::List(list) if list.list_type == ListType::Bullet
+        )
+    };
+    let is_comment = |node: &'a AstNode<'a>| match &node.data.borrow().value {
+        NodeValue::HtmlBlock(html_block) => {
+            let literal = html_block.literal.trim();
+            literal.starts_with("<!--")
+                && literal.ends_with("-->")
+                && parse_marker(literal, syntaxes).is_none()
+        }
+        _ => false,
+    };
+
+    let mut merged: Vec<&'a AstNode<'a>> = Vec::new();
+    for &list in lists {
+        if let Some(&previous) = merged.last()
+            && is_bullet_list(previous)
+            && is_bullet_list(list)
+        {
+            let mut between = Vec::new();
+            let mut sibling = previous.next_sibling();
+            while let Some(node) = sibling.filter(|node| is_comment(node)) {
+                between.push(node);
+                sibling = node.next_sibling();
+            }
+            if sibling.is_some_and(|node| std::ptr::eq(node, list)) {
+                for node in between {
+                    node.detach();
+                }
+                for item in list.children() {
+                    previous.append(item);
+                }
+                list.detach();
+                continue;
+            }
+        }
+        merged.push(list);
+    }
+    merged
+}
+
+/// Reorders the items of `list` by the text of their first line, keeping
+/// items that compare equal in their original order.
+fn sort_items<'a>(list: &'a AstNode<'a>, order: ItemOrder) {
+    let mut items: Vec<_> = list
+        .children()
+        .map(|item| (first_line_text(item).to_lowercase(), item))
+        .collect();
+    match order {
+        ItemOrder::Alpha => items.sort_by(|(a, _), (b, _)| a.cmp(b)),
+        ItemOrder::Reverse => items.sort_by(|(a, _), (b, _)| b.cmp(a)),
+        ItemOrder::Numeric => items.sort_by(|(a, _), (b, _)| {
+            let number = |text: &str| {
+                LEADING_NUMBER
+                    .find(text)
+                    .and_then(|number| number.as_str().parse::<f64>().ok())
+            };
+            match (number(a), number(b)) {
+                (Some(x), Some(y)) => x.total_cmp(&y),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => a.cmp(b),
+            }
+        }),
+    }
+    for (_, item) in &items {
+        item.detach();
+    }
+    for (_, item) in items {
+        list.append(item);
+    }
+}
+
+/// Removes or reports the items of `list` whose text repeats that of an
+/// earlier item, ignoring case, spacing and formatting.
+fn dedupe_items<'a>(
+    report: &mut TransformReport,
+    list: &'a AstNode<'a>,
+    dedupe: Dedupe,
+    region: Option<&str>,
+) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (index, item) in list.children().enumerate() {
+        let mut text = String::new();
+        for node in item.descendants() {
+            match &node.data.borrow().value {
+                NodeValue::Text(literal) => text.push_str(literal),
+                NodeValue::Code(code) => text.push_str(&code.literal),
+                NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+                _ => {}
+            }
+        }
+        let text = text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        let Some(&first) = seen.get(&text) else {
+            seen.insert(text, index + 1);
+            continue;
+        };
+        match dedupe {
+            Dedupe::Remove => item.detach(),
+            Dedupe::Warn => {
+                let position = item.data.borrow().sourcepos.start;
+                report
+                    .warn(
+                        "duplicate-item",
+                        position.line,
+                        position.column,
+                        format!("item {} repeats item {} of its list", index + 1, first),
+                    )
+                    .region = region.map(str::to_string);
+            }
+        }
+    }
+}
+
+/// Returns the text of the first line of a list item, without formatting.
+fn first_line_text<'a>(item: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+    let Some(block) = item.first_child() else {
+        return text;
+    };
+    for node in block.descendants() {
+        match &node.data.borrow().value {
+            NodeValue::Text(literal) => text.push_str(literal),
+            NodeValue::Code(code) => text.push_str(&code.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => break,
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Turns an ordered list back into a bullet list, replacing references to its
+/// step numbers, such as `(3)`, with `(cur±N)` expressions relative to the item
+/// they appear in.
+///
+/// Only numbers within the list's own range are replaced; anything else is
+/// left as written since it cannot be expressed relative to the list. Code
+/// spans are left as written too.
+pub(crate) fn demote_list<'a>(node: &'a AstNode<'a>) {
+    let NodeValue::List(list) = node.data.borrow().value else {
+        return;
+    };
+    if list.list_type != ListType::Ordered {
+        return;
+    }
+    node.data.borrow_mut().value = NodeValue::List(NodeList {
+        list_type: ListType::Bullet,
+        bullet_char: b'-',
+        ..list
+    });
+
+    let first = list.start as i32;
+    let numbers = first..first.saturating_add(node.children().count() as i32);
+    for (item, item_number) in node.children().zip(numbers.clone()) {
+        for descendant in item.descendants() {
+            let mut data = descendant.data.borrow_mut();
+            let text = match &mut data.value {
+                NodeValue::Text(text) => text,
+                NodeValue::Link(link) | NodeValue::Image(link) => &mut link.title,
+                _ => continue,
+            };
+            let new_text = unresolve_cur(text, item_number, &numbers);
+            if new_text != *text {
+                *text = new_text;
+            }
+        }
+    }
+}
+
+/// The inverse of `replace_references` for `(cur±N)`: rewrites `(N)` as `(cur±M)` for every `N` in
+/// `numbers`.
+fn unresolve_cur(text: &str, current_item_number: i32, numbers: &Range<i32>) -> String {
+    RESOLVED_NUMBER
+        .replace_all(text, |caps: &Captures| match caps[1].parse::<i32>() {
+            Ok(number) if numbers.contains(&number) => {
+                format!("(cur{:+})", number - current_item_number)
+            }
+            _ => caps[0].to_string(),
+        })
+        .to_string()
+}
+
+fn replace_references_in_list<'a>(
+    context: &mut Context<'a, '_>,
+    list_node: &'a AstNode<'a>,
+    start: usize,
+) {
+    let items: Vec<_> = list_node
+        .children()
+        .filter(|item| {
+            matches!(
+                item.data.borrow().value,
+                NodeValue::Item(_) | NodeValue::TaskItem(_)
+            )
+        })
+        .collect();
+
+    for (index, item) in items.iter().enumerate() {
+        let numbers = ItemNumbers::new(start as i32, index, items.len());
+        let mut stack = Vec::new();
+        stack.push(*item);
+
+        while let Some(node) = stack.pop() {
+            if context.options.annotate {
+                annotate_references_in_text(context.arena, node, numbers);
+            }
+            replace_references_in_node(node, numbers, context.options);
+
+            for child in node.children() {
+                stack.push(child);
+            }
+        }
+    }
+}
+
+/// Splits a `Text` node containing reference expressions so that every
+/// resolved number is followed by an inline HTML comment holding the original
+/// expression, e.g. `(3)<!-- cur-1 -->` or `(5)<!-- item:last -->`.
+///
+/// Only `Text` nodes are annotated; titles cannot hold inline HTML and are
+/// resolved without annotations by `replace_references_in_node`.
+fn annotate_references_in_text<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    node: &'a AstNode<'a>,
+    numbers: ItemNumbers,
+) {
+    let text = match &node.data.borrow().value {
+        NodeValue::Text(text) => text.clone(),
+        _ => return,
+    };
+
+    let mut pieces = Vec::new();
+    let mut last = 0;
+    for caps in REFERENCE.captures_iter(&text) {
+        let whole = caps.get(0).unwrap();
+        if let Some(number) =
+            Reference::parse(&caps[1]).and_then(|reference| reference.resolve(numbers))
+        {
+            let resolved = format!("{}({})", &text[last..whole.start()], number);
+            pieces.push((resolved, format!("<!-- {} -->", &caps[1])));
+            last = whole.end();
+        }
+    }
+
+    if pieces.is_empty() {
+        return;
+    }
+
+    let mut anchor = node;
+    for (index, (resolved, comment)) in pieces.into_iter().enumerate() {
+        let text_node = if index == 0 {
+            node.data.borrow_mut().value = NodeValue::Text(resolved);
+            node
+        } else {
+            let text_node = new_node(arena, NodeValue::Text(resolved));
+            anchor.insert_after(text_node);
+            text_node
+        };
+        let comment_node = new_node(arena, NodeValue::HtmlInline(comment));
+        text_node.insert_after(comment_node);
+        anchor = comment_node;
+    }
+
+    if last < text.len() {
+        anchor.insert_after(new_node(arena, NodeValue::Text(text[last..].to_string())));
+    }
+}
+
+pub(crate) fn new_node<'a>(arena: &'a Arena<AstNode<'a>>, value: NodeValue) -> &'a AstNode<'a> {
+    arena.alloc(AstNode::new(RefCell::new(Ast::new(
+        value,
+        LineColumn::from((0, 0)),
+    ))))
+}
+
+/// Replaces `(cur±N)`, `(item:N)` and `(item:last)` in the text carried by a
+/// single node.
+///
+/// Covers every inline node that holds prose: plain text and the titles of
+/// links and images. Container blocks (block quotes, nested paragraphs,
+/// sub-lists, ...) carry no text of their own and are reached through the
+/// traversal in `replace_references_in_list`. Inline HTML, such as attribute
+/// values in `<a title="step (cur-1)">`, is only rewritten when
+/// [`TransformOptions::resolve_inline_html`] is set. Literal content such as
+/// code spans, code blocks and HTML blocks is left untouched.
+fn replace_references_in_node<'a>(
+    node: &'a AstNode<'a>,
+    numbers: ItemNumbers,
+    options: &TransformOptions,
+) {
+    let mut data = node.data.borrow_mut();
+    match &mut data.value {
+        NodeValue::HtmlInline(html) if options.resolve_inline_html => {
+            replace_references_in_place(html, numbers)
+        }
+        NodeValue::Text(text) => replace_references_in_place(text, numbers),
+        NodeValue::Link(link) | NodeValue::Image(link) => {
+            replace_references_in_place(&mut link.title, numbers)
+        }
+        _ => {}
+    }
+}
+
+fn replace_references_in_place(text: &mut String, numbers: ItemNumbers) {
+    let new_text = replace_references(text, numbers);
+    if new_text != *text {
+        *text = new_text;
+    }
+}
+
+/// Replaces the reference expressions in `text` with the numbers they point
+/// at from an item numbered as `numbers` says, leaving those that can't be
+/// resolved as they are.
+pub(crate) fn replace_references(text: &str, numbers: ItemNumbers) -> String {
+    REFERENCE
+        .replace_all(text, |caps: &Captures| {
+            match Reference::parse(&caps[1]).and_then(|reference| reference.resolve(numbers)) {
+                Some(result) => format!("({})", result),
+                None => caps[0].to_string(),
+            }
+        })
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::{AnchorStyle, RenderOptions, Wrap};
+    use crate::report::{Diagnostic, Severity};
+
+    #[test]
+    fn test_unordered_lists_unchanged_by_default() {
+        let input = r#"- First item
+- Second item
+- Third item"#;
+        let expected = r#"- First item
+- Second item
+- Third item
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_magic_comment_conversion() {
+        let input = r#"<!-- ol -->
+- First item
+- Second item
+- Third item
 <!-- /ol -->"#;
         let expected = r#"<!-- ol -->
-1. First item with (0) should be (0)
-2. Second item with (2) should be (2)
-3. Third item with (-7) should be (-7)
+1. First item
+2. Second item
+3. Third item
+<!-- /ol -->
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_mixed_content_with_magic_comments() {
+        let input = r#"# Header
+
+Normal list (unchanged):
+- First item
+- Second item
+
+<!-- ol -->
+- Third item
+- Fourth item
+<!-- /ol -->
+
+Some text"#;
+        let expected = r#"# Header
+
+Normal list (unchanged):
+
+- First item
+- Second item
+
+<!-- ol -->
+1. Third item
+2. Fourth item
+<!-- /ol -->
+
+Some text
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_indented_lists_with_magic_comments() {
+        let input = r#"<!-- ol -->
+  - Indented item
+  - Another indented item
+<!-- /ol -->"#;
+        let expected = r#"<!-- ol -->
+  1. Indented item
+  2. Another indented item
+<!-- /ol -->
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_no_unordered_lists() {
+        let input = r#"Just some text
+with no lists"#;
+        let expected = r#"Just some text
+with no lists
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_cur_minus_one_replacement() {
+        let input = r#"<!-- ol -->
+- First item
+- Second item
+- Third item with (cur-1) reference
+<!-- /ol -->"#;
+        let expected = r#"<!-- ol -->
+1. First item
+2. Second item
+3. Third item with (2) reference
+<!-- /ol -->
+"#;
+        assert_eq!(transform(input), expected);
+    }
 
+    #[test]
+    fn test_cur_expressions_various_offsets() {
+        let input = r#"<!-- ol -->
+- First item
+- Second item with (cur-1) and (cur+1)
+- Third item with (cur-2) and (cur+0)
+- Fourth item with (cur-3)
+<!-- /ol -->"#;
+        let expected = r#"<!-- ol -->
+1. First item
+2. Second item with (1) and (3)
+3. Third item with (1) and (3)
+4. Fourth item with (1)
 <!-- /ol -->
 "#;
         assert_eq!(transform(input), expected);
     }
+
+    #[test]
+    fn test_cur_expressions_no_magic_comments() {
+        let input = r#"- First item with (cur-1)
+- Second item with (cur+1)"#;
+        let expected = r#"- First item with (cur-1)
+- Second item with (cur+1)
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_cur_expressions_edge_cases() {
+        let input = r#"<!-- ol -->
+- First item with (cur-1) should be (0)
+- Second item with (cur+0) should be (2)
+- Third item with (cur-10) should be (-7)
+<!-- /ol -->"#;
+        let expected = r#"<!-- ol -->
+1. First item with (0) should be (0)
+2. Second item with (2) should be (2)
+3. Third item with (-7) should be (-7)
+<!-- /ol -->
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    fn resolved_texts(input: &str) -> Vec<String> {
+        let arena = Arena::new();
+        let root = parse_document(&arena, input, &Options::default());
+        let mut context = Context {
+            arena: &arena,
+            options: &TransformOptions::default(),
+            convert_all: false,
+            report: TransformReport::default(),
+            lazy_lists: BTreeSet::new(),
+            moved_anchors: MovedAnchors::new(),
+            included: Vec::new(),
+        };
+        transform_ast(&mut context, root);
+
+        root.descendants()
+            .filter_map(|node| match &node.data.borrow().value {
+                NodeValue::Text(text) => Some(text.clone()),
+                NodeValue::Code(code) => Some(code.literal.clone()),
+                NodeValue::Link(link) | NodeValue::Image(link) => Some(link.title.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cur_expressions_in_blockquote() {
+        let input = r#"<!-- ol -->
+- First item
+- Second item
+
+  > Quoted (cur-1)
+<!-- /ol -->"#;
+        let texts = resolved_texts(input);
+        assert!(texts.contains(&"Quoted (1)".to_string()));
+    }
+
+    #[test]
+    fn test_cur_expressions_in_nested_paragraph() {
+        let input = r#"<!-- ol -->
+- First item
+- Second item
+
+  Continued in (cur+1)
+<!-- /ol -->"#;
+        let texts = resolved_texts(input);
+        assert!(texts.contains(&"Continued in (3)".to_string()));
+    }
+
+    #[test]
+    fn test_cur_expressions_in_nested_list() {
+        let input = r#"<!-- ol -->
+- First item
+- Second item
+  - Sub item refers to (cur-1)
+<!-- /ol -->"#;
+        let texts = resolved_texts(input);
+        assert!(texts.contains(&"Sub item refers to (1)".to_string()));
+    }
+
+    #[test]
+    fn test_cur_expressions_in_emphasis_and_strong() {
+        let input = r#"<!-- ol -->
+- First item
+- See *step (cur-1)* and **step (cur+1)**
+<!-- /ol -->"#;
+        let texts = resolved_texts(input);
+        assert!(texts.contains(&"step (1)".to_string()));
+        assert!(texts.contains(&"step (3)".to_string()));
+    }
+
+    #[test]
+    fn test_cur_expressions_in_code_span() {
+        let input = r#"<!-- ol -->
+- First item
+- Run `make step-(cur-1)`
+<!-- /ol -->"#;
+        let texts = resolved_texts(input);
+        assert!(texts.contains(&"make step-(cur-1)".to_string()));
+    }
+
+    #[test]
+    fn test_cur_expressions_in_link_and_image() {
+        let input = r#"<!-- ol -->
+- First item
+- [Back to (cur-1)](#top "Step (cur-1)") ![Shot (cur+0)](a.png "Step (cur+0)")
+<!-- /ol -->"#;
+        let texts = resolved_texts(input);
+        assert!(texts.contains(&"Back to (1)".to_string()));
+        assert!(texts.contains(&"Step (1)".to_string()));
+        assert!(texts.contains(&"Shot (2)".to_string()));
+        assert!(texts.contains(&"Step (2)".to_string()));
+    }
+
+    #[test]
+    fn test_cur_expressions_in_code_block_untouched() {
+        let input = r#"<!-- ol -->
+- First item
+- Second item
+
+  ```
+  echo (cur-1)
+  ```
+<!-- /ol -->"#;
+        let arena = Arena::new();
+        let root = parse_document(&arena, input, &Options::default());
+        let mut context = Context {
+            arena: &arena,
+            options: &TransformOptions::default(),
+            convert_all: false,
+            report: TransformReport::default(),
+            lazy_lists: BTreeSet::new(),
+            moved_anchors: MovedAnchors::new(),
+            included: Vec::new(),
+        };
+        transform_ast(&mut context, root);
+        let literal = root
+            .descendants()
+            .find_map(|node| match &node.data.borrow().value {
+                NodeValue::CodeBlock(block) => Some(block.literal.clone()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(literal, "echo (cur-1)\n");
+    }
+
+    #[test]
+    fn test_cur_expressions_between_inline_html_tags() {
+        let input = r#"<!-- ol -->
+- First item
+- <b>see step (cur-1)</b>
+<!-- /ol -->"#;
+        let expected = r#"<!-- ol -->
+1. First item
+2. <b>see step (1)</b>
+<!-- /ol -->
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_cur_expressions_in_inline_html_untouched_by_default() {
+        let input = r#"<!-- ol -->
+- First item
+- <a title="step (cur-1)">link</a>
+<!-- /ol -->"#;
+        let expected = r#"<!-- ol -->
+1. First item
+2. <a title="step (cur-1)">link</a>
+<!-- /ol -->
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_cur_expressions_in_inline_html_with_option() {
+        let input = r#"<!-- ol -->
+- First item
+- <a title="step (cur-1)" data-step="(cur+0)">link</a>
+<!-- /ol -->"#;
+        let expected = r#"<!-- ol -->
+1. First item
+2. <a title="step (1)" data-step="(2)">link</a>
+<!-- /ol -->
+"#;
+        let options = TransformOptions {
+            resolve_inline_html: true,
+            ..TransformOptions::default()
+        };
+        assert_eq!(transform_with_options(input, &options), expected);
+    }
+
+    #[test]
+    fn test_item_references() {
+        let input = "<!-- ol start=4 -->\n- Configure\n- Restart what (item:1) configured\n- Check, as in (item:last), (item:0) and (item:2)\n- Done\n<!-- /ol -->";
+        assert_eq!(
+            transform(input),
+            "<!-- ol start=4 -->\n4. Configure\n5. Restart what (4) configured\n6. Check, as in (7), (item:0) and (5)\n7. Done\n<!-- /ol -->\n"
+        );
+
+        // Items past the end of the list are left as written.
+        assert_eq!(
+            transform("<!-- ol -->\n- A\n- B, not (item:3) or (item:9)\n<!-- /ol -->"),
+            "<!-- ol -->\n1. A\n2. B, not (item:3) or (item:9)\n<!-- /ol -->\n"
+        );
+
+        let options = TransformOptions {
+            annotate: true,
+            ..TransformOptions::default()
+        };
+        assert_eq!(
+            transform_with_options(
+                "<!-- ol -->\n- A, before (item:last)\n- B\n<!-- /ol -->",
+                &options
+            ),
+            "<!-- ol -->\n1. A, before (2)<!-- item:last -->\n2. B\n<!-- /ol -->\n"
+        );
+    }
+
+    #[test]
+    fn test_list_numbers_stop_at_largest_marker() {
+        let input = "999999998. a\n999999999. b\n1. c\n";
+        let output = transform(input);
+        assert_eq!(output, "999999998. a\n999999999. b\n999999999. c\n");
+        assert_eq!(transform(&output), output);
+    }
+
+    #[test]
+    fn test_padded_numbers_are_stable() {
+        let options = TransformOptions {
+            render: RenderOptions {
+                pad_numbers: true,
+                ..RenderOptions::default()
+            },
+            ..TransformOptions::default()
+        };
+        let input: String = (1..=10).map(|n| format!("{}. Item\n", n)).collect();
+        let once = transform_with_options(&input, &options);
+        assert!(once.starts_with(" 1. Item\n") && once.ends_with("\n10. Item\n"));
+        assert_eq!(transform_with_options(&once, &options), once);
+        let aligned = " 9. a\n10. b\n";
+        assert_eq!(transform_with_options(aligned, &options), aligned);
+    }
+
+    #[test]
+    fn test_region_attributes_start_and_delimiter() {
+        let input = r#"<!-- ol start=4 delim=paren name=setup -->
+- Fourth item
+- Fifth item refers to (cur-1)
+<!-- /ol -->"#;
+        let expected = r#"<!-- ol start=4 delim=paren name=setup -->
+4) Fourth item
+5) Fifth item refers to (4)
+<!-- /ol -->
+"#;
+        let (output, report) = transform_with_report(input, &TransformOptions::default());
+        assert_eq!(output, expected);
+        assert!(report.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_region_attribute_warnings_are_reported() {
+        let input = r#"Intro
+
+<!-- ol type=alpha -->
+- First item
+<!-- /ol -->"#;
+        let (output, report) = transform_with_report(input, &TransformOptions::default());
+        assert!(output.contains("1. First item"));
+        assert_eq!(
+            report.diagnostics,
+            vec![Diagnostic {
+                severity: Severity::Warning,
+                line: 3,
+                column: 1,
+                message: "unknown attribute `type`".to_string(),
+                code: "marker-attribute",
+                region: Some("line-3".to_string()),
+                rule: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_ol_skip_leaves_next_list_untouched() {
+        let input = r#"<!-- ol -->
+- First step
+- Second step
+
+Pick one of:
+
+<!-- ol-skip -->
+- Option A
+- Option B
+
+Then:
+
+- Third step
+<!-- /ol -->"#;
+        let expected = r#"<!-- ol -->
+1. First step
+2. Second step
+
+Pick one of:
+
+<!-- ol-skip -->
+- Option A
+- Option B
+
+Then:
+
+1. Third step
+<!-- /ol -->
+"#;
+        let (output, report) = transform_with_report(input, &TransformOptions::default());
+        assert_eq!(output, expected);
+        assert!(report.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_misplaced_ol_skip_warns() {
+        let input = r#"<!-- ol-skip -->
+- Outside
+
+<!-- ol -->
+<!-- ol-skip -->
+Not a list
+
+- Converted
+<!-- /ol -->"#;
+        let (output, report) = transform_with_report(input, &TransformOptions::default());
+        assert!(output.contains("- Outside"));
+        assert!(output.contains("1. Converted"));
+        let messages: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, "`ol-skip` outside of a region has no effect"),
+                (5, "`ol-skip` is not immediately followed by a list"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_section_region_ends_at_same_level_heading() {
+        let input = r#"## Install
+
+<!-- ol section -->
+- Download
+- Unpack
+
+### Notes
+
+- Converted too
+
+## Usage
+
+- Left alone"#;
+        let expected = r#"## Install
+
+<!-- ol section -->
+1. Download
+2. Unpack
+
+### Notes
+
+1. Converted too
+
+## Usage
+
+- Left alone
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_section_region_before_any_heading() {
+        let input = r#"<!-- ol section -->
+- First
+
+# Heading
+
+- Second"#;
+        let expected = r#"<!-- ol section -->
+1. First
+
+# Heading
+
+- Second
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_section_region_closed_early() {
+        let input = r#"# Steps
+
+<!-- ol section -->
+- First
+<!-- /ol -->
+
+- Second"#;
+        let expected = r#"# Steps
+
+<!-- ol section -->
+1. First
+<!-- /ol -->
+
+- Second
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_front_matter_converts_whole_file() {
+        let input = r#"---
+title: Setup
+md_ol: all
+---
+
+# Steps
+
+- First step
+- Second step refers to (cur-1)
+
+<!-- ol start=10 -->
+- Tenth step
+<!-- /ol -->
+
+<!-- ol-skip -->
+- Left alone"#;
+        let expected = r#"---
+title: Setup
+md_ol: all
+---
+
+# Steps
+
+1. First step
+2. Second step refers to (1)
+
+<!-- ol start=10 -->
+10. Tenth step
+<!-- /ol -->
+
+<!-- ol-skip -->
+- Left alone
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_front_matter_without_switch() {
+        let input = r#"---
+title: Setup
+---
+
+- First step"#;
+        let expected = r#"---
+title: Setup
+---
+
+- First step
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_continue_numbering_after_interruption() {
+        let input = r#"<!-- ol -->
+- First step
+- Second step
+
+Some explanation.
+
+<!-- ol continue -->
+- Third step refers to (cur-2)
+- Fourth step
+<!-- /ol -->"#;
+        let expected = r#"<!-- ol -->
+1. First step
+2. Second step
+
+Some explanation.
+
+<!-- ol continue -->
+3. Third step refers to (1)
+4. Fourth step
+<!-- /ol -->
+"#;
+        let (output, report) = transform_with_report(input, &TransformOptions::default());
+        assert_eq!(output, expected);
+        assert!(report.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_continue_without_previous_list() {
+        let input = r#"<!-- ol continue start=5 -->
+- Fifth step
+<!-- /ol -->"#;
+        let (output, report) = transform_with_report(input, &TransformOptions::default());
+        assert!(output.contains("5. Fifth step"));
+        assert_eq!(
+            report.diagnostics[0].message,
+            "`continue` has no previous list to continue from"
+        );
+        assert_eq!(report.diagnostics[0].line, 2);
+    }
+
+    #[test]
+    fn test_unclosed_region_converts_to_end_with_warning() {
+        let input = r#"<!-- ol -->
+- First item
+- Second item"#;
+        let (output, report) = transform_with_report(input, &TransformOptions::default());
+        assert_eq!(output, "<!-- ol -->\n1. First item\n2. Second item\n");
+        assert_eq!(
+            report.diagnostics,
+            vec![Diagnostic {
+                severity: Severity::Warning,
+                line: 1,
+                column: 1,
+                message: "`ol` region has no closing `<!-- /ol -->`; converting to the end of the enclosing block".to_string(),
+                code: "unclosed-region",
+                region: Some("line-1".to_string()),
+                rule: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unclosed_region_skip_policy() {
+        let input = r#"<!-- ol -->
+- First item"#;
+        let options = TransformOptions {
+            unclosed_regions: UnclosedRegionPolicy::Skip,
+            ..TransformOptions::default()
+        };
+        let (output, report) = transform_with_report(input, &options);
+        assert_eq!(output, "<!-- ol -->\n- First item\n");
+        assert_eq!(report.diagnostics[0].severity, Severity::Warning);
+        assert!(!report.has_errors());
+    }
+
+    #[test]
+    fn test_unclosed_region_error_policy() {
+        let input = r#"<!-- ol -->
+- First item"#;
+        let options = TransformOptions {
+            unclosed_regions: UnclosedRegionPolicy::Error,
+            ..TransformOptions::default()
+        };
+        let (output, report) = transform_with_report(input, &options);
+        assert_eq!(output, "<!-- ol -->\n- First item\n");
+        assert!(report.has_errors());
+    }
+
+    #[test]
+    fn test_events_explain_region_decisions() {
+        let input = "<!-- ol start=3 name=setup -->\n- A\n- B\n<!-- /ol -->\n\n<!-- ol continue -->\n1. Kept\n\n<!-- ol-skip -->\n- Skipped\n<!-- /ol -->\n\n<!-- ol -->\nNo lists.\n<!-- /ol -->\n\n<!-- ol -->\n- Open";
+        let (_, report) = transform_with_report(input, &TransformOptions::default());
+        let events: Vec<_> = report
+            .events
+            .iter()
+            .map(|event| {
+                (
+                    event.pass,
+                    event.line,
+                    event.region.as_deref().unwrap_or(""),
+                    event.message.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (
+                    "regions",
+                    10,
+                    "line-6",
+                    "left the list unconverted, as `ol-skip` says"
+                ),
+                (
+                    "regions",
+                    2,
+                    "setup",
+                    "numbered the list from 3, as `start` says"
+                ),
+                (
+                    "regions",
+                    7,
+                    "line-6",
+                    "kept the numbers of the list, from 1"
+                ),
+                ("regions", 13, "line-13", "no lists to convert"),
+                (
+                    "regions",
+                    17,
+                    "line-17",
+                    "no closing marker; converting to the end of the enclosing block"
+                ),
+                (
+                    "regions",
+                    18,
+                    "line-17",
+                    "numbered the list from 1, by default"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_region_closed_by_next_opening_marker_is_not_unclosed() {
+        let input = r#"<!-- ol -->
+- First item
+
+<!-- ol continue -->
+- Second item
+<!-- /ol -->"#;
+        let (_, report) = transform_with_report(input, &TransformOptions::default());
+        assert!(report.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_tolerant_markers() {
+        let input = "<!--OL-->\n- First item\n- Second item\n<!--  /ol  -->\r\n";
+        let expected = "<!--OL-->\n1. First item\n2. Second item\n<!--  /ol  -->\n";
+        let (output, report) = transform_with_report(input, &TransformOptions::default());
+        assert_eq!(output, expected);
+        assert!(report.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_markers() {
+        let input = "<!--OL start=2-->\n- Second item\n<!--/ol-->";
+        let expected = "<!-- ol start=2 -->\n2. Second item\n<!-- /ol -->\n";
+        let options = TransformOptions {
+            normalize_markers: true,
+            ..TransformOptions::default()
+        };
+        assert_eq!(transform_with_options(input, &options), expected);
+    }
+
+    #[test]
+    fn test_mdx_markers() {
+        let input = r#"{/* ol */}
+- First item
+- Second item
+
+{/* /ol */}
+
+- Untouched"#;
+        let expected = r#"{/* ol */}
+
+1. First item
+2. Second item
+
+{/* /ol */}
+
+- Untouched
+"#;
+        let options = TransformOptions {
+            marker_syntaxes: vec![MarkerSyntax::HtmlComment, MarkerSyntax::MdxComment],
+            ..TransformOptions::default()
+        };
+        assert_eq!(transform_with_options(input, &options), expected);
+    }
+
+    #[test]
+    fn test_mdx_markers_disabled_by_default() {
+        let input = r#"{/* ol */}
+- First item"#;
+        assert!(transform(input).contains("- First item"));
+    }
+
+    #[test]
+    fn test_region_ids_in_report() {
+        let input = r#"<!-- ol name=setup -->
+- First
+- Second
+<!-- /ol -->
+
+> <!-- ol -->
+> - Quoted
+> <!-- /ol -->
+
+<!-- ol name=setup -->
+- Again
+<!-- /ol -->"#;
+        let (_, report) = transform_with_report(input, &TransformOptions::default());
+        let regions: Vec<_> = report
+            .regions
+            .iter()
+            .map(|region| (region.id.as_str(), region.line, region.lists, region.items))
+            .collect();
+        assert_eq!(
+            regions,
+            vec![("setup", 1, 1, 2), ("line-6", 6, 1, 1), ("setup", 10, 1, 1),]
+        );
+        assert_eq!(report.diagnostics.len(), 1);
+        assert_eq!(
+            report.diagnostics[0].message,
+            "duplicate region name `setup`"
+        );
+        assert_eq!(report.diagnostics[0].line, 10);
+        assert_eq!(
+            report.diagnostics[0].to_string(),
+            "10:1: warning: duplicate region name `setup` (in region `setup`)"
+        );
+    }
+
+    #[test]
+    fn test_region_markdown() {
+        let input = r#"# Deploy
+
+<!-- ol name=deploy -->
+- Build
+- Ship (cur-1)
+<!-- /ol -->
+
+## Rollback
+
+<!-- ol -->
+
+- Stop
+- Restore
+
+<!-- /ol -->
+"#;
+        let options = TransformOptions::default();
+        let (output, report) = transform_with_report(input, &options);
+        let region = |region| region_markdown(&output, &report, region, &options);
+        assert_eq!(region("deploy").unwrap(), "1. Build\n2. Ship (1)\n");
+        assert_eq!(region("line-10").unwrap(), "1. Stop\n2. Restore\n");
+        assert_eq!(region("2"), region("line-10"));
+        assert_eq!(
+            region("3").unwrap_err(),
+            "no region 3: the document has 2 regions"
+        );
+        assert_eq!(
+            region("teardown").unwrap_err(),
+            "no region named `teardown`"
+        );
+    }
+
+    #[test]
+    fn test_region_extents_in_report() {
+        let input = r#"<!-- ol name=setup start=3 -->
+- A
+
+- B
+<!-- /ol -->
+
+<!-- ul -->
+1. X
+2. Y
+
+## Next
+
+<!-- ol section -->
+- P
+
+More
+
+## Last"#;
+        let (_, report) = transform_with_report(input, &TransformOptions::default());
+        let regions: Vec<_> = report
+            .regions
+            .iter()
+            .map(|region| {
+                (
+                    region.kind,
+                    region.line,
+                    region.end_line,
+                    region.attributes.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            regions,
+            vec![
+                (RegionKind::Ol, 1, 5, "name=setup start=3"),
+                (RegionKind::Ul, 7, 11, ""),
+                (RegionKind::Ol, 13, 16, "section"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ul_region_demotes_ordered_lists() {
+        let input = r#"<!-- ul -->
+1. Open the file
+2. Edit the line from (1)
+3. Save it, unlike (5)
+<!-- /ul -->
+
+1. Outside (1)"#;
+        let output = transform(input);
+        assert!(output.contains("- Open the file\n"));
+        assert!(output.contains("- Edit the line from (cur-1)\n"));
+        assert!(output.contains("- Save it, unlike (5)\n"));
+        assert!(output.contains("1. Outside (1)\n"));
+    }
+
+    #[test]
+    fn test_ul_region_leaves_code_spans() {
+        let input = "<!-- ul -->\n1. Open the file\n2. Run `sed -n (1)p` on (1)\n<!-- /ul -->\n";
+        assert_eq!(
+            transform(input),
+            "<!-- ul -->\n- Open the file\n- Run `sed -n (1)p` on (cur-1)\n<!-- /ul -->\n"
+        );
+    }
+
+    #[test]
+    fn test_bullet_option_sets_house_style() {
+        let input = r#"* Unconverted
+
+Text
+
++ Still unconverted
+
+<!-- ul -->
+1. Demoted
+<!-- /ul -->"#;
+        let options = TransformOptions {
+            render: RenderOptions {
+                bullet: Some('-'),
+                ..RenderOptions::default()
+            },
+            ..TransformOptions::default()
+        };
+        assert_eq!(
+            transform_with_options(input, &options),
+            "- Unconverted\n\nText\n\n- Still unconverted\n\n<!-- ul -->\n- Demoted\n<!-- /ul -->\n"
+        );
+    }
+
+    #[test]
+    fn test_ul_region_respects_list_start() {
+        let input = r#"<!-- ul -->
+3) Third, see [next](#next "(4)")
+4) Fourth, see (4)
+<!-- /ul -->"#;
+        let output = transform(input);
+        assert!(output.contains(r#"- Third, see [next](#next "(cur+1)")"#));
+        assert!(output.contains("- Fourth, see (cur+0)\n"));
+    }
+
+    #[test]
+    fn test_ul_region_reverses_ol_region() {
+        let input = r#"<!-- ol -->
+- First
+- After (cur-1)
+<!-- /ol -->"#;
+        let ordered = transform(input);
+        assert!(ordered.contains("2. After (1)"));
+        let bullets = transform(
+            &ordered
+                .replace("<!-- ol -->", "<!-- ul -->")
+                .replace("<!-- /ol -->", "<!-- /ul -->"),
+        );
+        assert!(bullets.contains("- First\n- After (cur-1)\n"));
+    }
+
+    #[test]
+    fn test_mismatched_closing_marker_warns() {
+        let input = r#"<!-- ul -->
+1. First
+<!-- /ol -->"#;
+        let (output, report) = transform_with_report(input, &TransformOptions::default());
+        assert!(output.contains("- First"));
+        assert_eq!(report.diagnostics.len(), 1);
+        assert_eq!(
+            report.diagnostics[0].message,
+            "`<!-- /ol -->` closes a `ul` region"
+        );
+    }
+
+    #[test]
+    fn test_region_inside_list_item() {
+        let input = r#"- Prepare
+  <!-- ol -->
+  - Wash (cur+1)
+  - Dry after (cur-1)
+  <!-- /ol -->
+- Serve
+  - Unconverted"#;
+        let expected = r#"- Prepare
+  <!-- ol -->
+  1. Wash (2)
+  2. Dry after (1)
+  <!-- /ol -->
+- Serve
+  - Unconverted
+"#;
+        assert_eq!(transform(input), expected);
+    }
+
+    #[test]
+    fn test_nested_region_takes_precedence() {
+        let input = r#"<!-- ol -->
+- Prepare
+  <!-- ol start=10 -->
+  - Sub step after (cur-1)
+  - Sub step
+  <!-- /ol -->
+- Refers to (cur-1)
+<!-- /ol -->"#;
+        let output = transform(input);
+        assert!(
+            output.contains("1. Prepare\n   <!-- ol start=10 -->\n   10. Sub step after (9)\n")
+        );
+        assert!(output.contains("2. Refers to (1)\n"));
+    }
+
+    #[test]
+    fn test_code_blocks_in_region_are_preserved() {
+        let input = r#"<!-- ol -->
+- Run:
+
+  ```sh
+  echo "(cur-1)"
+  ```
+- Then (cur-1)
+<!-- /ol -->"#;
+        let output = transform(input);
+        assert!(output.contains("1. Run:\n\n   ```sh\n   echo \"(cur-1)\"\n   ```\n"));
+        assert!(output.contains("2. Then (1)\n"));
+    }
+
+    #[test]
+    fn test_images_in_converted_items() {
+        let input = r#"<!-- ol -->
+- Click ![the *Save* button](save.png "Step (cur+1) follows")
+- [![badge](b.svg)](https://ci.example.com) done
+<!-- /ol -->"#;
+        let output = transform(input);
+        assert!(output.contains(r#"1. Click ![the *Save* button](save.png "Step (2) follows")"#));
+        assert!(output.contains("2. [![badge](b.svg)](https://ci.example.com) done\n"));
+    }
+
+    #[test]
+    fn test_autolinks_option() {
+        let input = r#"<!-- ol -->
+- Open https://example.com/(cur+1)
+- Done
+<!-- /ol -->"#;
+        let output = transform(input);
+        assert!(output.contains("1. Open https://example.com/(2)\n"));
+
+        let options = TransformOptions {
+            parse: ParseOptions {
+                autolinks: true,
+                ..ParseOptions::default()
+            },
+            ..TransformOptions::default()
+        };
+        let (output, _) = transform_with_report(input, &options);
+        assert!(output.contains("1. Open https://example.com/(2)\n"));
+    }
+
+    #[test]
+    fn test_task_lists_in_region() {
+        let input = r#"<!-- ol -->
+- [ ] Back up (cur+1)
+- [x] Upgrade after (cur-1)
+- Plain item
+<!-- /ol -->"#;
+        let output = transform(input);
+        assert!(output.contains("1. [ ] Back up (2)\n2. [x] Upgrade after (1)\n3. Plain item\n"));
+    }
+
+    #[test]
+    fn test_task_lists_in_ul_region() {
+        let input = r#"<!-- ul -->
+1. [ ] First
+2. [x] After (1)
+<!-- /ul -->"#;
+        let output = transform(input);
+        assert!(output.contains("- [ ] First\n- [x] After (cur-1)\n"));
+    }
+
+    #[test]
+    fn test_tab_indented_region() {
+        let input = "<!-- ol -->\n- First\n- Second\n\t- Nested (cur-1)\n<!-- /ol -->";
+        let output = transform(input);
+        assert!(output.contains("1. First\n2. Second\n\t - Nested (1)\n"));
+    }
+
+    #[test]
+    fn test_escapes_survive_resolution() {
+        let input = r#"<!-- ol -->
+- First
+- Multiply by 2 \* see (cur-1)
+<!-- /ol -->"#;
+        let output = transform(input);
+        assert!(output.contains(r"2. Multiply by 2 \* see (1)"));
+    }
+
+    #[test]
+    fn test_math_is_left_alone() {
+        let input = r#"<!-- ol -->
+- Compute $x_{(cur)}$
+- Then (cur-1) with $$\sum_i (cur-1)$$
+<!-- /ol -->"#;
+        let output = transform(input);
+        assert!(output.contains("1. Compute $x_{(cur)}$\n"));
+        assert!(output.contains(r"2. Then (1) with $$\sum_i (cur-1)$$"));
+    }
+
+    #[test]
+    fn test_wikilinks_option() {
+        let input = r#"<!-- ol -->
+- Read [[Setup|setup (cur+1)]]
+- Then [[Usage]]
+<!-- /ol -->"#;
+        let options = TransformOptions {
+            parse: ParseOptions {
+                wikilinks: true,
+                ..ParseOptions::default()
+            },
+            ..TransformOptions::default()
+        };
+        let (output, _) = transform_with_report(input, &options);
+        assert!(output.contains("1. Read [[Setup|setup (2)]]\n2. Then [[Usage]]\n"));
+    }
+
+    #[test]
+    fn test_description_list_in_region() {
+        let input = r#"<!-- ol -->
+- Pick a mode
+
+  Fast
+  : Skips the checks in (cur+1)
+
+- Run the checks
+<!-- /ol -->"#;
+        let output = transform(input);
+        assert!(output.contains(
+            "1. Pick a mode\n\n   Fast\n   : Skips the checks in (2)\n\n2. Run the checks\n"
+        ));
+    }
+
+    #[test]
+    fn test_nested_lists_under_converted_items() {
+        let items: String = (1..=9).map(|n| format!("- Item {}\n", n)).collect();
+        let input = format!(
+            "<!-- ol -->\n{}- Item 10\n  - Nested\n    - Deeper\n      - Deepest\n<!-- /ol -->",
+            items
+        );
+        let output = transform(&input);
+        assert!(
+            output.contains(
+                "9. Item 9\n10. Item 10\n    - Nested\n      - Deeper\n        - Deepest\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_nested_region_three_levels_deep() {
+        let input = r#"<!-- ol -->
+- Outer
+  - Middle
+    <!-- ol -->
+    - Inner
+    - Inner again, after (cur-1)
+    <!-- /ol -->
+<!-- /ol -->"#;
+        let output = transform(input);
+        assert!(output.contains("1. Outer\n   - Middle\n     <!-- ol -->\n     1. Inner\n     2. Inner again, after (1)\n"));
+    }
+
+    #[test]
+    fn test_preserve_soft_breaks_in_converted_items() {
+        let input =
+            "<!-- ol -->\n- Hand-wrapped\n  first step\n- Refers to\n  (cur-1)\n<!-- /ol -->";
+        let options = TransformOptions {
+            render: RenderOptions {
+                preserve_soft_breaks: true,
+                ..RenderOptions::default()
+            },
+            ..TransformOptions::default()
+        };
+        let (output, _) = transform_with_report(input, &options);
+        assert!(output.contains("1. Hand-wrapped\n   first step\n2. Refers to\n   (1)\n"));
+    }
+
+    #[test]
+    fn test_ordered_lists_pass_through_unchanged() {
+        let input = r#"3) Third
+4) Fourth
+
+<!-- ol -->
+1) Already ordered
+2) Kept as written
+<!-- /ol -->
+"#;
+        let output = transform(input);
+        assert!(output.starts_with("3) Third\n4) Fourth\n"));
+        assert!(output.contains("1) Already ordered\n2) Kept as written\n"));
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let input =
+            "| a | b |\n| - | - |\n| 1 | 2 |\n\nText[^note] ~~old~~\n\n[^note]: A footnote.";
+        let options = TransformOptions {
+            parse: ParseOptions {
+                tables: false,
+                strikethrough: false,
+                footnotes: true,
+                ..ParseOptions::default()
+            },
+            ..TransformOptions::default()
+        };
+        let (output, _) = transform_with_report(input, &options);
+        assert_eq!(
+            output,
+            "| a | b |\n| - | - |\n| 1 | 2 |\n\nText[^note] ~~old~~\n\n[^note]: A footnote.\n"
+        );
+    }
+
+    #[test]
+    fn test_lists_in_alerts() {
+        let input = "<!-- ol -->\n> [!NOTE]\n> Steps:\n>\n> - One\n> - Two after (cur-1)\n\n> Quoted\n>\n> > - Nested\n<!-- /ol -->\n\n> [!warning] Mind the gap\n> - Untouched\n";
+        assert_eq!(
+            transform(input),
+            "<!-- ol -->\n> [!NOTE]\n> Steps:\n>\n> 1. One\n> 2. Two after (1)\n\n> Quoted\n>\n> > 1. Nested\n<!-- /ol -->\n\n> [!warning] Mind the gap\n> - Untouched\n"
+        );
+    }
+
+    #[test]
+    fn test_quote_with_own_region() {
+        let input = "<!-- ol -->\n> - Quoted\n>\n> <!-- ul -->\n>\n> 1. Numbered\n> <!-- /ul -->\n<!-- /ol -->";
+        assert_eq!(
+            transform(input),
+            "<!-- ol -->\n> - Quoted\n>\n> <!-- ul -->\n>\n> - Numbered\n>\n> <!-- /ul -->\n<!-- /ol -->\n"
+        );
+    }
+
+    #[test]
+    fn test_transform_is_idempotent() {
+        assert_eq!(transform("Para\n>\n# H\n"), "Para\n\n>\n\n# H\n");
+        assert_eq!(transform("Para\n\t- tab\n"), "Para\n\\- tab\n");
+        assert_eq!(transform("p\n\n---\n\nq\n"), "p\n\n---\n\nq\n");
+
+        // Documents are put together from lines that start or continue every
+        // kind of block, in a fixed pseudo-random order.
+        const LINES: &[&str] = &[
+            "Para",
+            "- item",
+            "* star",
+            "+ plus",
+            "1. one",
+            "2) two",
+            "10. ten",
+            "- [ ] task",
+            "> quote",
+            ">",
+            "> - q",
+            ">> deep",
+            "# H",
+            "## Sub",
+            "Setext\n===",
+            "",
+            "",
+            "\t- tab",
+            "  - nested",
+            "  continued",
+            "   indented",
+            "    code",
+            "```",
+            "~~~",
+            "---",
+            "***",
+            "<!-- ol -->",
+            "<!-- /ol -->",
+            "<!-- ul -->",
+            "<!-- /ul -->",
+            "<!-- comment -->",
+            "<div>",
+            "</div>",
+            "| a | b |",
+            "| - | - |",
+            "text (cur-1)",
+            "[l]: http://x",
+            "\\- esc",
+            "**bold**",
+            "a  \nb",
+        ];
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+        let option_sets = [
+            TransformOptions::default(),
+            TransformOptions {
+                annotate: true,
+                render: RenderOptions {
+                    pad_numbers: true,
+                    ..RenderOptions::default()
+                },
+                ..TransformOptions::default()
+            },
+            TransformOptions {
+                render: RenderOptions {
+                    wrap: Wrap::Width(30),
+                    ..RenderOptions::default()
+                },
+                ..TransformOptions::default()
+            },
+        ];
+        for _ in 0..1000 {
+            let lines = 1 + next(8);
+            let document: String = (0..lines)
+                .map(|_| format!("{}\n", LINES[next(LINES.len())]))
+                .collect();
+            for options in &option_sets {
+                let once = transform_with_options(&document, options);
+                assert_eq!(
+                    transform_with_options(&once, options),
+                    once,
+                    "input: {:?}",
+                    document
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_superscript_subscript_and_spoilers() {
+        let input =
+            "<!-- ol -->\n- E = mc^2^ and H~2~O, see (cur+1)\n- ||Spoiler|| ~~gone~~\n<!-- /ol -->";
+        let options = TransformOptions {
+            parse: ParseOptions {
+                superscript: true,
+                subscript: true,
+                spoilers: true,
+                ..ParseOptions::default()
+            },
+            ..TransformOptions::default()
+        };
+        let (output, _) = transform_with_report(input, &options);
+        assert_eq!(
+            output,
+            "<!-- ol -->\n1. E = mc^2^ and H~2~O, see (2)\n2. ||Spoiler|| ~~gone~~\n<!-- /ol -->\n"
+        );
+    }
+
+    #[test]
+    fn test_lazy_number_style() {
+        let input = "<!-- ol style=lazy start=3 -->\n- First\n- Second after (cur-1)\n<!-- /ol -->\n\n<!-- ol -->\n- Other\n- List\n<!-- /ol -->";
+        let expected = "<!-- ol style=lazy start=3 -->\n3. First\n3. Second after (3)\n<!-- /ol -->\n\n<!-- ol -->\n1. Other\n2. List\n<!-- /ol -->\n";
+        assert_eq!(transform(input), expected);
+        assert_eq!(transform(expected), expected);
+
+        let options = TransformOptions {
+            number_style: NumberStyle::Lazy,
+            ..TransformOptions::default()
+        };
+        let (output, _) = transform_with_report(input, &options);
+        assert!(output.ends_with("<!-- ol -->\n1. Other\n1. List\n<!-- /ol -->\n"));
+    }
+
+    #[test]
+    fn test_join_lists_around_code_block() {
+        let input = "<!-- ol join -->\n- Install\n- Run:\n\n```sh\nmake\n```\n\n- Check the output of (cur-1)\n<!-- /ol -->\n\n<!-- ol -->\n- Restarts\n<!-- /ol -->";
+        let expected = "<!-- ol join -->\n1. Install\n2. Run:\n\n```sh\nmake\n```\n\n3. Check the output of (2)\n<!-- /ol -->\n\n<!-- ol -->\n1. Restarts\n<!-- /ol -->\n";
+        assert_eq!(transform(input), expected);
+
+        let options = TransformOptions {
+            join_lists: true,
+            ..TransformOptions::default()
+        };
+        let (output, _) = transform_with_report(&input.replace(" join", ""), &options);
+        assert_eq!(output, expected.replace(" join", ""));
+    }
+
+    #[test]
+    fn test_prefix_attribute() {
+        let input = r#"<!-- ol prefix="Step {n}: " -->
+- Install
+- **Configure**, after (cur-1)
+<!-- /ol -->
+
+<!-- ul prefix="Step {n} - " -->
+1. Back up
+2. Migrate
+<!-- /ul -->"#;
+        let expected = r#"<!-- ol prefix="Step {n}: " -->
+1. Step 1: Install
+2. Step 2: **Configure**, after (1)
+<!-- /ol -->
+
+<!-- ul prefix="Step {n} - " -->
+- Step 1 - Back up
+- Step 2 - Migrate
+<!-- /ul -->
+"#;
+        assert_eq!(transform(input), expected);
+        assert_eq!(transform(expected), expected);
+
+        let inserted = expected.replace("- Step 1 - Back up\n", "- Step 1 - Back up\n- Check\n");
+        assert!(transform(&inserted).contains("- Step 2 - Check\n- Step 3 - Migrate\n"));
+    }
+
+    #[test]
+    fn test_item_anchors() {
+        let input = "<!-- ol start=3 name=setup -->\n- Install\n- Configure\n  more text\n<!-- /ol -->\n\n<!-- ol -->\n- Unnamed\n<!-- /ol -->";
+        let mut options = TransformOptions {
+            item_anchors: Some(AnchorStyle::Html),
+            ..TransformOptions::default()
+        };
+        let output = transform_with_options(input, &options);
+        assert_eq!(
+            output,
+            "<!-- ol start=3 name=setup -->\n3. <a id=\"setup-step-3\"></a>Install\n4. <a id=\"setup-step-4\"></a>Configure more text\n<!-- /ol -->\n\n<!-- ol -->\n1. Unnamed\n<!-- /ol -->\n"
+        );
+        assert_eq!(transform_with_options(&output, &options), output);
+
+        options.item_anchors = Some(AnchorStyle::Attribute);
+        let output = transform_with_options(input, &options);
+        assert!(
+            output.contains("3. Install {#setup-step-3}\n4. Configure more text {#setup-step-4}\n")
+        );
+        assert_eq!(transform_with_options(&output, &options), output);
+    }
+
+    #[test]
+    fn test_tasks_attribute() {
+        let input = "<!-- ol tasks -->\n- Back up\n- [x] Migrate\n  - Check the logs\n- Verify\n<!-- /ol -->\n\n<!-- ol -->\n- Plain\n<!-- /ol -->";
+        let expected = "<!-- ol tasks -->\n1. [ ] Back up\n2. [x] Migrate\n   - Check the logs\n3. [ ] Verify\n<!-- /ol -->\n\n<!-- ol -->\n1. Plain\n<!-- /ol -->\n";
+        assert_eq!(transform(input), expected);
+        assert_eq!(transform(expected), expected);
+
+        let options = TransformOptions {
+            tasks: true,
+            ..TransformOptions::default()
+        };
+        let (output, _) = transform_with_report(input, &options);
+        assert!(output.ends_with("<!-- ol -->\n1. [ ] Plain\n<!-- /ol -->\n"));
+    }
+
+    #[test]
+    fn test_sort_attribute() {
+        let input = "<!-- ol sort=alpha -->\n- pear\n- *Apple*\n  - keeps its notes\n- banana\n<!-- /ol -->\n\n<!-- ol sort=numeric -->\n- 16 GB\n- n/a\n- 2 GB\n<!-- /ol -->\n\n<!-- ol sort=reverse -->\n- a\n- c\n- b\n<!-- /ol -->";
+        let expected = "<!-- ol sort=alpha -->\n1. *Apple*\n   - keeps its notes\n2. banana\n3. pear\n<!-- /ol -->\n\n<!-- ol sort=numeric -->\n1. 2 GB\n2. 16 GB\n3. n/a\n<!-- /ol -->\n\n<!-- ol sort=reverse -->\n1. c\n2. b\n3. a\n<!-- /ol -->\n";
+        assert_eq!(transform(input), expected);
+        assert_eq!(transform(expected), expected);
+    }
+
+    #[test]
+    fn test_dedupe_attribute() {
+        let input = "<!-- ol dedupe -->\n- Back up\n- Run  *the* migration\n- back up\n- Run the\n  migration\n- Verify (cur-1)\n<!-- /ol -->";
+        assert_eq!(
+            transform(input),
+            "<!-- ol dedupe -->\n1. Back up\n2. Run  *the* migration\n3. Verify (2)\n<!-- /ol -->\n"
+        );
+
+        let input = input.replace("dedupe", "dedupe=warn");
+        let (output, report) = transform_with_report(&input, &TransformOptions::default());
+        assert!(output.contains("5. Verify (4)"));
+        let messages: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (4, "item 3 repeats item 1 of its list"),
+                (5, "item 4 repeats item 2 of its list")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reverse_attribute() {
+        let input = "<!-- ol reverse -->\n- v1.0: first release\n- v1.1: fixes\n- v2.0: see (cur+1) for older changes\n<!-- /ol -->";
+        let expected = "<!-- ol reverse -->\n1. v2.0: see (2) for older changes\n2. v1.1: fixes\n3. v1.0: first release\n<!-- /ol -->\n";
+        assert_eq!(transform(input), expected);
+        assert_eq!(transform(expected), expected);
+    }
+
+    #[test]
+    fn test_merge_attribute() {
+        let input = "<!-- ol merge -->\n- One\n- Two\n\n<!-- end list -->\n\n- Three (cur-1)\n\n\n* Four\n\nText\n\n- Five\n<!-- /ol -->";
+        let expected = "<!-- ol merge -->\n1. One\n2. Two\n3. Three (2)\n4. Four\n\nText\n\n1. Five\n<!-- /ol -->\n";
+        assert_eq!(transform(input), expected);
+        assert_eq!(transform(expected), expected);
+    }
+
+    #[test]
+    fn test_split_marker() {
+        let input = "<!-- ol delim=paren -->\n- Prepare\n- Install\n  <!-- ol split -->\n- Configure\n  <!-- ol split continue -->\n- Run (cur-1)\n<!-- /ol -->";
+        let expected = "<!-- ol delim=paren -->\n1) Prepare\n2) Install\n<!-- ol split -->\n1) Configure\n<!-- ol split continue -->\n2) Run (1)\n<!-- /ol -->\n";
+        assert_eq!(transform(input), expected);
+        assert_eq!(transform(expected), expected);
+
+        let (_, report) = transform_with_report(input, &TransformOptions::default());
+        assert!(report.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_format_range_snaps_to_lines() {
+        let options = TransformOptions::default();
+        let input = "Intro\n<!-- ol -->\n- A\n<!-- /ol -->";
+        // Ending at the start of a line doesn't touch it.
+        assert_eq!(
+            format_range(input, 0..6, &options),
+            (0..6, "Intro\n".to_string())
+        );
+        // An empty range touches its line, and a range past the end is cut short.
+        assert_eq!(
+            format_range(input, 8..8, &options),
+            (6..18, "<!-- ol -->\n".to_string())
+        );
+        assert_eq!(
+            format_range(input, 6..100, &options),
+            (
+                6..input.len(),
+                "<!-- ol -->\n1. A\n<!-- /ol -->".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_numbers_too_large_for_a_list_do_not_panic() {
+        // Found by feeding the transform random documents; each used to
+        // overflow a counter.
+        let options = TransformOptions {
+            annotate: true,
+            ..TransformOptions::default()
+        };
+        let (output, report) = transform_with_report(
+            "<!-- ol start=2147483647 -->\n- A\n- B (cur+1)\n<!-- /ol -->",
+            &options,
+        );
+        assert_eq!(
+            output,
+            "<!-- ol start=2147483647 -->\n1. A\n2. B (3)<!-- cur+1 -->\n<!-- /ol -->\n"
+        );
+        assert_eq!(
+            report.diagnostics[0].message,
+            "`start` can be at most 999999999, the largest number a list can start from"
+        );
+
+        let input = "<!-- ol -->\n- A (cur+2147483647)\n- B (cur-2147483648)\n<!-- /ol -->";
+        assert_eq!(
+            transform_with_options(input, &options),
+            "<!-- ol -->\n1. A (cur+2147483647)\n2. B (-2147483646)<!-- cur-2147483648 -->\n<!-- /ol -->\n"
+        );
+        assert_eq!(
+            transform("<!-- ol start=18446744073709551615 reverse -->\n- A\n<!-- /ol -->"),
+            "<!-- ol start=18446744073709551615 reverse -->\n1. A\n<!-- /ol -->\n"
+        );
+
+        let options = TransformOptions {
+            continue_from: Some(usize::MAX),
+            ..TransformOptions::default()
+        };
+        let (output, report) =
+            transform_with_report("<!-- ol continue -->\n- A\n<!-- /ol -->", &options);
+        assert_eq!(output, "<!-- ol continue -->\n1. A\n<!-- /ol -->\n");
+        assert_eq!(
+            report.diagnostics[0].message,
+            format!(
+                "cannot continue from {}, past the largest list number 999999999",
+                usize::MAX
+            )
+        );
+    }
+
+    #[test]
+    fn test_documents_nested_too_deeply_are_left_as_they_are() {
+        let input = format!(
+            "<!-- ol -->\n{}- A (cur-1)\n<!-- /ol -->\n",
+            "> ".repeat(10_000)
+        );
+        let (output, report) = transform_with_report(&input, &TransformOptions::default());
+        assert_eq!(output, input);
+        assert_eq!(
+            report.diagnostics[0].to_string(),
+            "2:257: error: nested more than 128 levels deep; leaving the document as it is"
+        );
+        assert_eq!(crate::restore(&input), input);
+
+        let options = TransformOptions {
+            parse: ParseOptions {
+                max_nesting: 4,
+                ..ParseOptions::default()
+            },
+            ..TransformOptions::default()
+        };
+        let (_, report) = transform_with_report("<!-- ol -->\n- A\n<!-- /ol -->", &options);
+        assert!(report.diagnostics.is_empty());
+        let (_, report) = transform_with_report("<!-- ol -->\n- A\n  - B\n<!-- /ol -->", &options);
+        assert!(report.has_errors());
+    }
 }
@@ -0,0 +1,90 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Runs the binary on `input` with `args` and the environment variables
+/// `env`, returning its output.
+fn run(args: &[&str], env: &[(&str, &str)], input: &str) -> String {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_md_ol_util"));
+    command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    for (name, _) in std::env::vars().filter(|(name, _)| name.starts_with("MD_OL_UTIL_")) {
+        command.env_remove(name);
+    }
+    command.envs(env.iter().copied());
+    let mut child = command.spawn().unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// A temporary directory whose config file sets `bullet: "*"`.
+fn configured_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("md_ol_util-env-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join(".md_ol_util.yml"), "bullet: \"*\"\n").unwrap();
+    directory
+}
+
+const STEPS: &str = "<!-- ol -->\n- Install\n- Run after (cur-1)\n<!-- /ol -->\n";
+
+#[test]
+fn test_switches_accept_any_value() {
+    for (value, annotated) in [
+        ("1", true),
+        ("true", true),
+        ("yes", true),
+        ("", false),
+        ("0", false),
+        ("false", false),
+        ("off", false),
+    ] {
+        let output = run(&[], &[("MD_OL_UTIL_ANNOTATE", value)], STEPS);
+        assert_eq!(output.contains("<!-- cur-1 -->"), annotated, "{:?}", value);
+    }
+}
+
+#[test]
+fn test_flags_override_environment() {
+    let output = run(&["--annotate"], &[("MD_OL_UTIL_ANNOTATE", "false")], STEPS);
+    assert!(output.contains("2. Run after (1)<!-- cur-1 -->"));
+
+    let output = run(
+        &["--bullet", "+"],
+        &[("MD_OL_UTIL_BULLET", "*")],
+        "* Loose\n",
+    );
+    assert_eq!(output, "+ Loose\n");
+}
+
+#[test]
+fn test_environment_overrides_config_file() {
+    let directory = configured_directory("layering");
+    let path = directory.join("guide.md");
+    let path = path.to_str().unwrap();
+
+    let output = run(&["--stdin-filepath", path], &[], "- Loose\n");
+    assert_eq!(output, "* Loose\n");
+    let output = run(
+        &["--stdin-filepath", path],
+        &[("MD_OL_UTIL_BULLET", "+")],
+        "- Loose\n",
+    );
+    assert_eq!(output, "+ Loose\n");
+    fs::remove_dir_all(directory).unwrap();
+}
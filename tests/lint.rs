@@ -0,0 +1,33 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn test_lint_finds_the_config_of_each_file() {
+    let directory =
+        std::env::temp_dir().join(format!("md_ol_util-lint-config-{}", std::process::id()));
+    fs::create_dir_all(directory.join("quiet")).unwrap();
+    fs::create_dir_all(directory.join("strict")).unwrap();
+    fs::write(
+        directory.join("quiet/.md_ol_util.yml"),
+        "rules:\n  unnamed-regions: false\n",
+    )
+    .unwrap();
+    let steps = "<!-- ol -->\n1. Install\n<!-- /ol -->\n";
+    let quiet = directory.join("quiet/guide.md");
+    let strict = directory.join("strict/guide.md");
+    fs::write(&quiet, steps).unwrap();
+    fs::write(&strict, steps).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_md_ol_util"))
+        .current_dir(&directory)
+        .args(["lint", "quiet/guide.md", "strict/guide.md"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "strict/guide.md:1:1: warning: region has no `name` attribute [unnamed-regions]\n"
+    );
+    assert!(!output.status.success());
+    fs::remove_dir_all(directory).unwrap();
+}